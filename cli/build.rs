@@ -0,0 +1,56 @@
+// Generate the man page of the CLI from the definitions of its commands. It's written to
+// `$OUT_DIR/ledger_manager_cli.1`, and printed by `ledger_manager_cli --man`.
+
+use std::{env, fs, path::Path};
+
+include!("src/commands.rs");
+
+// Escape text for roff.
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('-', "\\-")
+}
+
+fn env_entries(page: &mut String, vars: &[EnvDef]) {
+    for var in vars {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            roff(var.name),
+            roff(var.about)
+        ));
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/commands.rs");
+
+    let mut page = format!(
+        ".TH LEDGER_MANAGER_CLI 1 \"\" \"ledger_manager_cli {}\"\n",
+        env::var("CARGO_PKG_VERSION").unwrap_or_default()
+    );
+    page.push_str(
+        ".SH NAME\nledger_manager_cli \\- manage the apps installed on a Ledger device\n",
+    );
+    page.push_str(".SH SYNOPSIS\nLEDGER_COMMAND=\\fIcommand\\fR [\\fIVARIABLE\\fR=\\fIvalue\\fR ...] \\fBledger_manager_cli\\fR\n");
+    page.push_str(".SH DESCRIPTION\nTalk to the Ledger device connected by USB, and to the Ledger API. The command and its options are set through environment variables.\n");
+
+    page.push_str(".SH COMMANDS\n");
+    for command in COMMANDS {
+        page.push_str(&format!(
+            ".TP\n\\fB{}\\fR\n{}\n",
+            roff(command.name),
+            roff(command.about)
+        ));
+        if !command.env.is_empty() {
+            page.push_str(".RS\n");
+            env_entries(&mut page, command.env);
+            page.push_str(".RE\n");
+        }
+    }
+
+    page.push_str(".SH ENVIRONMENT\n");
+    env_entries(&mut page, COMMON_ENV);
+
+    let out_dir = env::var("OUT_DIR").expect("Set by cargo");
+    fs::write(Path::new(&out_dir).join("ledger_manager_cli.1"), page)
+        .expect("Writing the man page");
+}
@@ -0,0 +1,361 @@
+// The definitions of the commands of the CLI and of the environment variables configuring them.
+// The long help and the man page are generated from these. This file is also included by the build
+// script, so it must only contain plain data.
+
+/// An environment variable configuring the CLI.
+pub struct EnvDef {
+    pub name: &'static str,
+    pub about: &'static str,
+}
+
+/// A command of the CLI, as set in LEDGER_COMMAND.
+pub struct CommandDef {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// The environment variables specific to this command.
+    pub env: &'static [EnvDef],
+}
+
+pub const COMMANDS: &[CommandDef] = &[
+    CommandDef {
+        name: "getinfo",
+        about: "Get information (such as the list of installed apps, the settings reported by the device and the state of the battery) for your device, or tell how to recover it if it's in bootloader mode.",
+        env: &[],
+    },
+    CommandDef {
+        name: "genuinecheck",
+        about: "Check your Ledger device is genuine.",
+        env: &[],
+    },
+    CommandDef {
+        name: "installapp",
+        about: "Install the Bitcoin app on your device, or the app selected with LEDGER_TESTNET, LEDGER_SOLANA, LEDGER_ETHEREUM, LEDGER_LITECOIN, LEDGER_CARDANO, LEDGER_COSMOS, LEDGER_POLKADOT, LEDGER_MONERO, LEDGER_XRP or LEDGER_EXCHANGE.",
+        env: &[],
+    },
+    CommandDef {
+        name: "updateapp",
+        about: "Update the Bitcoin app on your device, or the app selected with LEDGER_TESTNET, LEDGER_SOLANA, LEDGER_ETHEREUM, LEDGER_LITECOIN, LEDGER_CARDANO, LEDGER_COSMOS, LEDGER_POLKADOT, LEDGER_MONERO, LEDGER_XRP or LEDGER_EXCHANGE.",
+        env: &[],
+    },
+    CommandDef {
+        name: "openapp",
+        about: "Open the Bitcoin app on your device, or the app selected with LEDGER_TESTNET, LEDGER_SOLANA, LEDGER_ETHEREUM, LEDGER_LITECOIN, LEDGER_CARDANO, LEDGER_COSMOS, LEDGER_POLKADOT, LEDGER_MONERO or LEDGER_XRP.",
+        env: &[
+            EnvDef {
+                name: "LEDGER_SHOW_KEY",
+                about: "Once the app is open, display the master key fingerprint of the seed on the device (or the address of the default account for the Solana and Ethereum apps).",
+            },
+            EnvDef {
+                name: "LEDGER_WAIT_CLOSE",
+                about: "Wait until the app is quit on the device and print how long it was open.",
+            },
+        ],
+    },
+    CommandDef {
+        name: "updatefirm",
+        about: "Update the firmware of your device to the latest version, after checking it's genuine, charged enough (or charging over USB) and has enough storage left, and showing its release notes. The progress of every stage is shown, and the device restarts in between, possibly in bootloader mode to update its MCU. If the update is interrupted, run the command again to resume it.",
+        env: &[
+            EnvDef {
+                name: "LEDGER_CHECK",
+                about: "Only print the firmware versions of the device and the latest firmware available for it, without updating.",
+            },
+            EnvDef {
+                name: "LEDGER_RESUME",
+                about: "Only complete an interrupted update, for instance if the device was unplugged while stuck in the firmware updater or in bootloader mode. Other commands refuse to run on such a device. A device in bootloader mode is recovered by flashing the MCU firmware matching the firmware of its Secure Element.",
+            },
+        ],
+    },
+    CommandDef {
+        name: "uninstallapp",
+        about: "Uninstall the Bitcoin app from your device, or the app selected with LEDGER_TESTNET, LEDGER_SOLANA, LEDGER_ETHEREUM, LEDGER_LITECOIN, LEDGER_CARDANO, LEDGER_COSMOS, LEDGER_POLKADOT, LEDGER_MONERO, LEDGER_XRP or LEDGER_EXCHANGE.",
+        env: &[EnvDef {
+            name: "LEDGER_ALL_EXCEPT",
+            about: "A comma-separated list of app names. Uninstall every app but those (and the libraries they depend on) instead, after confirming the list of apps to be removed.",
+        }],
+    },
+    CommandDef {
+        name: "apdu",
+        about: "Send a raw APDU to your device and print the response data and status word.",
+        env: &[EnvDef {
+            name: "LEDGER_APDU",
+            about: "The APDU to send, as hex. For instance e001000000.",
+        }],
+    },
+    CommandDef {
+        name: "rename",
+        about: "Print the name of your device, as shown over Bluetooth, or rename it. Only for the models with Bluetooth.",
+        env: &[EnvDef {
+            name: "LEDGER_DEVICE_NAME",
+            about: "The new name of the device, up to 20 characters. It must be confirmed on the device.",
+        }],
+    },
+    CommandDef {
+        name: "language",
+        about: "List the language packs available for your device, or switch it to another language. A device holds a single language pack besides English.",
+        env: &[EnvDef {
+            name: "LEDGER_LANGUAGE",
+            about: "The language to switch to, as listed (for instance french). Its pack replaces the one installed, if any. Set it to english to remove the language pack.",
+        }],
+    },
+    CommandDef {
+        name: "lockscreen",
+        about: "Show a picture of your choice while your device is locked, or restore the default lock screen. Only for the Ledger Stax and Flex.",
+        env: &[
+            EnvDef {
+                name: "LEDGER_FILE",
+                about: "The picture to show (PNG or JPEG). It's cropped to the proportions of the screen, resized and converted to shades of gray. It must be confirmed on the device.",
+            },
+            EnvDef {
+                name: "LEDGER_CLEAR",
+                about: "Restore the default lock screen instead.",
+            },
+        ],
+    },
+    CommandDef {
+        name: "watch",
+        about: "Print when a Ledger device is connected or disconnected.",
+        env: &[EnvDef {
+            name: "LEDGER_WATCH_ACTION",
+            about: "A command (for instance getinfo) to run every time a device is connected.",
+        }],
+    },
+    CommandDef {
+        name: "listavailable",
+        about: "List the apps available in Ledger's catalog for your device.",
+        env: &[],
+    },
+    CommandDef {
+        name: "watchreleases",
+        about: "Periodically check Ledger's catalog for new firmware and app versions for your device, and print them.",
+        env: &[
+            EnvDef {
+                name: "LEDGER_TARGET_ID",
+                about: "The target id of the device model to check for, instead of that of the connected device. Requires LEDGER_FIRMWARE_VERSION.",
+            },
+            EnvDef {
+                name: "LEDGER_FIRMWARE_VERSION",
+                about: "The firmware version of the device model to check for. Requires LEDGER_TARGET_ID.",
+            },
+            EnvDef {
+                name: "LEDGER_POLL_INTERVAL",
+                about: "The number of seconds between checks. One hour by default.",
+            },
+            EnvDef {
+                name: "LEDGER_FILE",
+                about: "A JSON file where to remember the versions seen across runs.",
+            },
+            EnvDef {
+                name: "LEDGER_ONCE",
+                about: "Check a single time, for instance from cron.",
+            },
+        ],
+    },
+    CommandDef {
+        name: "setup",
+        about: "Guided setup of a brand new device. Performs the genuine check, checks the firmware version and installs a starter set of apps.",
+        env: &[EnvDef {
+            name: "LEDGER_SETUP_APPS",
+            about: "A comma-separated list of the names of the apps to install. The Bitcoin app by default.",
+        }],
+    },
+    CommandDef {
+        name: "exportapps",
+        about: "Write the firmware version and the list of installed apps (names and versions) to a JSON file.",
+        env: &[EnvDef {
+            name: "LEDGER_FILE",
+            about: "The file to write to.",
+        }],
+    },
+    CommandDef {
+        name: "restoreapps",
+        about: "Install the apps listed in a JSON file written by exportapps, for instance on a freshly reset or replacement device.",
+        env: &[EnvDef {
+            name: "LEDGER_FILE",
+            about: "The file to read the apps from.",
+        }],
+    },
+    CommandDef {
+        name: "bridge",
+        about: "Serve your device to other machines, which run commands with LEDGER_TRANSPORT set to remote:HOST:PORT. For instance for a headless signer or a virtual machine without USB passthrough. There is no authentication nor encryption: anyone able to connect can manage your device, so only listen on a trusted network or go through an SSH tunnel.",
+        env: &[EnvDef {
+            name: "LEDGER_BRIDGE_LISTEN",
+            about: "The address to listen on (127.0.0.1:9998 by default), or the path of a Unix socket.",
+        }],
+    },
+    CommandDef {
+        name: "download",
+        about: "Download the catalog and language packs for a device model, to later use them without access to Ledger's API with LEDGER_PACKAGE_DIR. A manifest.json lists the apps with their versions and hashes, and the hash of every file downloaded. Installing apps and firmwares still needs a connection to Ledger's HSM.",
+        env: &[
+            EnvDef {
+                name: "LEDGER_DOWNLOAD_DIR",
+                about: "The directory to download into.",
+            },
+            EnvDef {
+                name: "LEDGER_DOWNLOAD_APPS",
+                about: "A comma-separated list of the names of the apps to look up in the catalog.",
+            },
+            EnvDef {
+                name: "LEDGER_FILE",
+                about: "A file written by exportapps whose apps to look up, if LEDGER_DOWNLOAD_APPS isn't set.",
+            },
+            EnvDef {
+                name: "LEDGER_TARGET_ID",
+                about: "The target id of the device model to download for, instead of that of the connected device. Requires LEDGER_FIRMWARE_VERSION.",
+            },
+            EnvDef {
+                name: "LEDGER_FIRMWARE_VERSION",
+                about: "The firmware version of the device model to download for. Requires LEDGER_TARGET_ID.",
+            },
+        ],
+    },
+    CommandDef {
+        name: "compare",
+        about: "Print the differences in firmware and apps between a file written by exportapps and your device, or another file.",
+        env: &[
+            EnvDef {
+                name: "LEDGER_FILE",
+                about: "The file to compare.",
+            },
+            EnvDef {
+                name: "LEDGER_COMPARE_FILE",
+                about: "The file to compare it to, instead of the device.",
+            },
+        ],
+    },
+];
+
+/// The environment variables which apply to several commands.
+pub const COMMON_ENV: &[EnvDef] = &[
+    EnvDef {
+        name: "LEDGER_APP",
+        about: "The name of any app of Ledger's catalog (for instance Polygon) to install, update, open or uninstall instead of the Bitcoin app. To open an app, it must be spelled as on the device.",
+    },
+    EnvDef {
+        name: "LEDGER_TESTNET",
+        about: "Use the Bitcoin testnet app instead where applicable.",
+    },
+    EnvDef {
+        name: "LEDGER_SOLANA",
+        about: "Use the Solana app instead of the Bitcoin app.",
+    },
+    EnvDef {
+        name: "LEDGER_ETHEREUM",
+        about: "Use the Ethereum app instead of the Bitcoin app.",
+    },
+    EnvDef {
+        name: "LEDGER_LITECOIN",
+        about: "Use the Litecoin app instead of the Bitcoin app. The library apps it depends on, if any, are installed along with it.",
+    },
+    EnvDef {
+        name: "LEDGER_CARDANO",
+        about: "Use the Cardano app (named Cardano ADA in the catalog) instead of the Bitcoin app.",
+    },
+    EnvDef {
+        name: "LEDGER_COSMOS",
+        about: "Use the Cosmos app instead of the Bitcoin app.",
+    },
+    EnvDef {
+        name: "LEDGER_POLKADOT",
+        about: "Use the Polkadot app instead of the Bitcoin app.",
+    },
+    EnvDef {
+        name: "LEDGER_MONERO",
+        about: "Use the Monero app instead of the Bitcoin app. It's one of the largest apps, installing or updating it fails early if it doesn't fit on the device.",
+    },
+    EnvDef {
+        name: "LEDGER_XRP",
+        about: "Use the XRP (Ripple) app instead of the Bitcoin app.",
+    },
+    EnvDef {
+        name: "LEDGER_EXCHANGE",
+        about: "Use Ledger's Exchange app instead of the Bitcoin app. Installing or updating it also installs or updates the currency apps it needs to perform swaps.",
+    },
+    EnvDef {
+        name: "LEDGER_APP_ONLY",
+        about: "When installing an app, only install the app binary and fail if one of its libraries is missing.",
+    },
+    EnvDef {
+        name: "LEDGER_ALLOW_DOWNGRADE",
+        about: "Update an app or the firmware even if the version to install is older than the installed one. Refused by default.",
+    },
+    EnvDef {
+        name: "LEDGER_RELEASE_NOTES",
+        about: "When installing or updating an app, show the description Ledger published for the version about to be installed and ask for confirmation first.",
+    },
+    EnvDef {
+        name: "LEDGER_FORCE",
+        about: "Reinstall the app even if it is already installed or up to date. The installed app is uninstalled first: if installing it again fails, it is left uninstalled.",
+    },
+    EnvDef {
+        name: "LEDGER_PROTECTED_APPS",
+        about: "A comma-separated list of the names of apps which must never be uninstalled.",
+    },
+    EnvDef {
+        name: "LEDGER_HSM_URLS",
+        about: "A comma-separated list of websocket URLs of Ledger's HSM. The fastest one is used.",
+    },
+    EnvDef {
+        name: "LEDGER_PROVIDER",
+        about: "The numeric id of the provider (channel) whose catalog to look up the apps and firmwares in, for instance a beta channel or the custom provider of an enterprise. 1, Ledger's default provider, by default.",
+    },
+    EnvDef {
+        name: "LEDGER_API_URL",
+        about: "The base URL of the Ledger API to query the catalog of apps and firmwares from, for instance a mirror or a staging server. https://manager.api.live.ledger.com/api by default.",
+    },
+    EnvDef {
+        name: "LEDGER_PROXY",
+        about: "The HTTP proxy to reach Ledger's API and HSM through, as http://[USER:PASSWORD@]HOST[:PORT] (port 8080 by default). Defaults to the one set in HTTPS_PROXY, or ALL_PROXY.",
+    },
+    EnvDef {
+        name: "LEDGER_API_RETRIES",
+        about: "How many more times to try a request to Ledger's API, or connecting to its HSM, when it fails for a reason which may be temporary (a network blip, an overloaded server). The wait between attempts doubles every time, starting from about a second, unless the server tells how long to wait. 4 by default, 0 to never retry.",
+    },
+    EnvDef {
+        name: "LEDGER_API_INTERVAL",
+        about: "The least number of milliseconds between two requests to Ledger's API or connections to its HSM, to stay under its rate limit when updating many apps or devices. None by default.",
+    },
+    EnvDef {
+        name: "LEDGER_CACHE_TTL",
+        about: "How many seconds to reuse the answers of the Ledger API about its catalog (the apps, firmwares and language packs available, and their versions) rather than querying it again. 10 minutes by default, 0 not to cache them.",
+    },
+    EnvDef {
+        name: "LEDGER_PACKAGE_DIR",
+        about: "A directory of answers of the Ledger API fetched beforehand (such as the ledger_manager_api cache directory of a machine which ran the same commands online) to get the catalog and language packs from, never querying the API. Installing apps and firmwares, and the genuine check, still need a connection to Ledger's HSM.",
+    },
+    EnvDef {
+        name: "LEDGER_CA_BUNDLE",
+        about: "A PEM file of the certificate authorities to trust for the connections to Ledger's HSM, instead of those of the system. For instance that of a corporate proxy intercepting TLS connections. The queries to Ledger's API always trust the Mozilla set of authorities.",
+    },
+    EnvDef {
+        name: "LEDGER_CERT_PINS",
+        about: "A comma-separated list of SHA-256 fingerprints of certificates (as hex, optionally colon-separated). The connections to Ledger's HSM fail unless one of the certificates it presents, its own or an intermediate one, is among them. Only the HSM is pinned, not Ledger's API.",
+    },
+    EnvDef {
+        name: "LEDGER_REGISTRY",
+        about: "A JSON file of app entries (name, aliases, testnet_of, plugins, plugin_of) to add to or override the registry of known apps, for instance to declare the currency apps a new app calls into.",
+    },
+    EnvDef {
+        name: "LEDGER_TRANSPORT",
+        about: "How to reach the device: hid (over USB, the default), speculos:HOST:PORT to talk to a Speculos emulator (localhost:9999 by default) or remote:HOST:PORT to talk to a device served by the bridge command on another machine (port 9998 by default, or remote:PATH for a Unix socket). Every command can talk to the emulator, though those going through Ledger's HSM (such as installing apps or the genuine check) fail on an emulated device.",
+    },
+    EnvDef {
+        name: "LEDGER_TIMEOUT",
+        about: "How many seconds to wait for the device to answer a command over USB, including the time you take to confirm it on the device. Five minutes by default, 0 to wait forever.",
+    },
+    EnvDef {
+        name: "LEDGER_RETRIES",
+        about: "How many more times to try connecting to the device over USB, or sending it a command, when it fails. 3 by default.",
+    },
+    EnvDef {
+        name: "LEDGER_AUTO_QUIT",
+        about: "If an app is open on the device, quit it to go back to the dashboard rather than failing.",
+    },
+    EnvDef {
+        name: "LEDGER_UNLOCK_TIMEOUT",
+        about: "How many seconds to wait for a locked device to be unlocked with its PIN. One minute by default.",
+    },
+    EnvDef {
+        name: "LEDGER_NO_PAGER",
+        about: "Never show long listings through the pager.",
+    },
+];
@@ -1,11 +1,42 @@
-use std::{env, process};
+mod commands;
+mod output;
+
+use commands::{EnvDef, COMMANDS, COMMON_ENV};
+use output::{Progress, Table};
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process,
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
 
 use ledger_manager::{
-    genuine_check, install_app, install_bitcoin_app,
-    ledger_transport_hidapi::{hidapi::HidApi, TransportNativeHID},
-    list_installed_apps, open_app, open_bitcoin_app, update_app, update_bitcoin_app, DeviceInfo,
-    InstallErr, LedgerApp, UpdateErr,
+    available_apps, battery_charging, battery_level, battery_status, clear_lock_screen,
+    current_app, current_final_firmware, delete_app_by_name, device_memory, discontinued_apps,
+    download_package, enumerate_devices, firmware_status, firmware_update, flash_mcu,
+    genuine_check, get_device_name, get_firmware_info, install_app, install_final_firmware,
+    install_language, install_osu, language_package, language_packages, latest_firmware,
+    ledger_apdu::{APDUAnswer, APDUCommand},
+    list_installed_apps, list_installed_apps_raw, mcu_update_needed, open_app, open_app_by_name,
+    open_bitcoin_app, pending_firmware_update, plan_uninstall_all_except, post_open_info,
+    prelude::*,
+    quit_app, registry, select_socket_url, send_raw_apdu, serve_bridge, set_api_url, set_ca_bundle,
+    set_cache, set_certificate_pins, set_device_name, set_lock_screen, set_package_dir,
+    set_provider, set_proxy, set_registry, set_retry_policy, target_app, uninstall_apps,
+    uninstall_languages, update_app, wait_unlocked, AppRegistry, ChargingMode, FirmwareStage,
+    FirmwareUpdate, InstalledApp, LedgerDeviceHandle, McuFlash, RetryPolicy, TransportConfig,
+    TransportHid, TransportRemote, TransportSpeculos, BRIDGE_DEFAULT_PORT, DEFAULT_LANGUAGE,
+    LOCK_SCREEN_GRAY_LEVELS, MAX_DEVICE_NAME_LENGTH, PACKAGE_MANIFEST, SPECULOS_DEFAULT_PORT,
 };
+use serde_derive::{Deserialize, Serialize};
 
 // Print on stderr and exit with 1.
 macro_rules! error {
@@ -15,7 +46,7 @@ macro_rules! error {
     }};
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 enum Command {
     GetInfo,
     GenuineCheck,
@@ -26,9 +57,39 @@ enum Command {
     UpdateTestApp,
     OpenTestApp,
     InstallSolana,
+    InstallNamedApp(String),
+    UpdateNamedApp(String),
+    OpenNamedApp(String),
+    UninstallNamedApp(String),
     UpdateSolana,
     OpenSolana,
+    InstallEthereum,
+    UpdateEthereum,
+    OpenEthereum,
+    InstallExchange,
+    UpdateExchange,
     UpdateFirmware,
+    ResumeFirmware,
+    CheckFirmware,
+    ExportApps(PathBuf),
+    RestoreApps(PathBuf),
+    UninstallMainApp,
+    UninstallTestApp,
+    UninstallSolana,
+    UninstallEthereum,
+    UninstallExchange,
+    UninstallAllExcept(Vec<String>),
+    Apdu(Vec<u8>),
+    Rename(Option<String>),
+    Language(Option<String>),
+    LockScreen(Option<PathBuf>),
+    Watch(Option<String>),
+    WatchReleases,
+    Compare(PathBuf, Option<PathBuf>),
+    Bridge(String),
+    ListAvailable,
+    Setup(Vec<String>),
+    Download(PathBuf, Vec<String>),
 }
 
 impl Command {
@@ -36,6 +97,23 @@ impl Command {
     pub fn get() -> Option<Self> {
         let is_testnet = env::var("LEDGER_TESTNET").is_ok();
         let is_solana = env::var("LEDGER_SOLANA").is_ok();
+        let is_ethereum = env::var("LEDGER_ETHEREUM").is_ok();
+        let is_exchange = env::var("LEDGER_EXCHANGE").is_ok();
+        // The apps selected with a variable of their own are handled as if named in LEDGER_APP.
+        let currency_app = [
+            ("LEDGER_LITECOIN", LedgerApp::Litecoin),
+            ("LEDGER_CARDANO", LedgerApp::Cardano),
+            ("LEDGER_COSMOS", LedgerApp::Cosmos),
+            ("LEDGER_POLKADOT", LedgerApp::Polkadot),
+            ("LEDGER_MONERO", LedgerApp::Monero),
+            ("LEDGER_XRP", LedgerApp::Xrp),
+        ]
+        .into_iter()
+        .find(|(var, _)| env::var(var).is_ok())
+        .map(|(_, app)| String::from_utf8_lossy(app.open_name()).to_string());
+        let app_name = env::var("LEDGER_APP")
+            .ok()
+            .map(|name| registry().resolve(&name));
         let cmd_str = env::var("LEDGER_COMMAND").ok()?;
 
         if cmd_str == "getinfo" {
@@ -43,7 +121,15 @@ impl Command {
         } else if cmd_str == "genuinecheck" {
             Some(Self::GenuineCheck)
         } else if cmd_str == "installapp" {
-            if is_solana {
+            if let Some(name) = app_name {
+                Some(Self::InstallNamedApp(name))
+            } else if is_exchange {
+                Some(Self::InstallExchange)
+            } else if is_ethereum {
+                Some(Self::InstallEthereum)
+            } else if let Some(name) = currency_app {
+                Some(Self::InstallNamedApp(name))
+            } else if is_solana {
                 Some(Self::InstallSolana)
             } else if is_testnet {
                 Some(Self::InstallTestApp)
@@ -51,7 +137,15 @@ impl Command {
                 Some(Self::InstallMainApp)
             }
         } else if cmd_str == "updateapp" {
-            if is_solana {
+            if let Some(name) = app_name {
+                Some(Self::UpdateNamedApp(name))
+            } else if is_exchange {
+                Some(Self::UpdateExchange)
+            } else if is_ethereum {
+                Some(Self::UpdateEthereum)
+            } else if let Some(name) = currency_app {
+                Some(Self::UpdateNamedApp(name))
+            } else if is_solana {
                 Some(Self::UpdateSolana)
             } else if is_testnet {
                 Some(Self::UpdateTestApp)
@@ -59,7 +153,13 @@ impl Command {
                 Some(Self::UpdateMainApp)
             }
         } else if cmd_str == "openapp" {
-            if is_solana {
+            if let Some(name) = app_name {
+                Some(Self::OpenNamedApp(name))
+            } else if is_ethereum {
+                Some(Self::OpenEthereum)
+            } else if let Some(name) = currency_app {
+                Some(Self::OpenNamedApp(name))
+            } else if is_solana {
                 Some(Self::OpenSolana)
             } else if is_testnet {
                 Some(Self::OpenTestApp)
@@ -67,34 +167,569 @@ impl Command {
                 Some(Self::OpenMainApp)
             }
         } else if cmd_str == "updatefirm" {
-            Some(Self::UpdateFirmware)
+            if env::var("LEDGER_CHECK").is_ok() {
+                Some(Self::CheckFirmware)
+            } else if env::var("LEDGER_RESUME").is_ok() {
+                Some(Self::ResumeFirmware)
+            } else {
+                Some(Self::UpdateFirmware)
+            }
+        } else if cmd_str == "uninstallapp" {
+            if let Ok(keep) = env::var("LEDGER_ALL_EXCEPT") {
+                Some(Self::UninstallAllExcept(
+                    keep.split(',')
+                        .map(|name| name.trim().to_string())
+                        .filter(|name| !name.is_empty())
+                        .collect(),
+                ))
+            } else if let Some(name) = app_name {
+                Some(Self::UninstallNamedApp(name))
+            } else if is_exchange {
+                Some(Self::UninstallExchange)
+            } else if is_ethereum {
+                Some(Self::UninstallEthereum)
+            } else if let Some(name) = currency_app {
+                Some(Self::UninstallNamedApp(name))
+            } else if is_solana {
+                Some(Self::UninstallSolana)
+            } else if is_testnet {
+                Some(Self::UninstallTestApp)
+            } else {
+                Some(Self::UninstallMainApp)
+            }
+        } else if cmd_str == "apdu" {
+            let apdu_hex = match env::var("LEDGER_APDU") {
+                Ok(a) => a,
+                Err(_) => error!("The APDU to send must be set as hex in the LEDGER_APDU env var."),
+            };
+            match hex::decode(apdu_hex.trim()) {
+                Ok(apdu) => Some(Self::Apdu(apdu)),
+                Err(e) => error!("Invalid hex in LEDGER_APDU: {}.", e),
+            }
+        } else if cmd_str == "rename" {
+            Some(Self::Rename(env::var("LEDGER_DEVICE_NAME").ok()))
+        } else if cmd_str == "language" {
+            Some(Self::Language(env::var("LEDGER_LANGUAGE").ok()))
+        } else if cmd_str == "lockscreen" {
+            if env::var("LEDGER_CLEAR").is_ok() {
+                Some(Self::LockScreen(None))
+            } else {
+                match env::var("LEDGER_FILE") {
+                    Ok(f) => Some(Self::LockScreen(Some(PathBuf::from(f)))),
+                    Err(_) => error!("Set LEDGER_FILE to the picture to show on the lock screen, or LEDGER_CLEAR to restore the default one."),
+                }
+            }
+        } else if cmd_str == "watch" {
+            let action = env::var("LEDGER_WATCH_ACTION").ok();
+            if action.as_deref() == Some("watch") {
+                error!("The action to run when a device is plugged in can't be 'watch'.");
+            }
+            Some(Self::Watch(action))
+        } else if cmd_str == "listavailable" {
+            Some(Self::ListAvailable)
+        } else if cmd_str == "watchreleases" {
+            Some(Self::WatchReleases)
+        } else if cmd_str == "setup" {
+            let names = env::var("LEDGER_SETUP_APPS").unwrap_or_else(|_| "bitcoin".to_string());
+            let registry = registry();
+            let apps = names
+                .split(',')
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+                .map(|name| match registry.get(name) {
+                    Some(app) => app.name.clone(),
+                    None => error!("Unsupported app in LEDGER_SETUP_APPS: '{}'.", name),
+                })
+                .collect::<Vec<_>>();
+            Some(Self::Setup(registry.with_plugins(&apps)))
+        } else if cmd_str == "exportapps" {
+            Some(Self::ExportApps(backup_file()))
+        } else if cmd_str == "restoreapps" {
+            Some(Self::RestoreApps(backup_file()))
+        } else if cmd_str == "bridge" {
+            Some(Self::Bridge(
+                env::var("LEDGER_BRIDGE_LISTEN")
+                    .unwrap_or_else(|_| format!("127.0.0.1:{}", BRIDGE_DEFAULT_PORT)),
+            ))
+        } else if cmd_str == "compare" {
+            Some(Self::Compare(
+                backup_file(),
+                env::var("LEDGER_COMPARE_FILE").ok().map(PathBuf::from),
+            ))
+        } else if cmd_str == "download" {
+            let dir = match env::var("LEDGER_DOWNLOAD_DIR") {
+                Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+                _ => error!("LEDGER_DOWNLOAD_DIR must be set to the directory to download into."),
+            };
+            let apps = match env::var("LEDGER_DOWNLOAD_APPS") {
+                Ok(names) => names
+                    .split(',')
+                    .map(|name| name.trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect(),
+                Err(_) => match env::var("LEDGER_FILE") {
+                    Ok(_) => read_snapshot(&backup_file())
+                        .apps
+                        .into_iter()
+                        .map(|app| app.name)
+                        .collect(),
+                    Err(_) => Vec::new(),
+                },
+            };
+            Some(Self::Download(dir, apps))
         } else {
             None
         }
     }
 }
 
-fn ledger_api() -> TransportNativeHID {
-    let hid_api = match HidApi::new() {
-        Ok(a) => a,
-        Err(e) => error!("Error initializing HDI api: {}.", e),
+// The path to the file apps are exported to or restored from, as set in the LEDGER_FILE env var.
+fn backup_file() -> PathBuf {
+    match env::var("LEDGER_FILE") {
+        Ok(f) => PathBuf::from(f),
+        Err(_) => error!("The path to the apps file must be set in the LEDGER_FILE env var."),
+    }
+}
+
+// How often to probe again the HSM endpoints for the fastest one.
+const HSM_PROBE_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// How long to use the cached answers of the Ledger API about its catalog by default.
+const API_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+// The path to this file in the user's cache directory.
+fn cache_file(name: &str) -> PathBuf {
+    let cache_dir = env::var("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|_| env::var("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(|_| env::temp_dir());
+    cache_dir.join(name)
+}
+
+// Where to remember the HSM endpoint selected by the latency probe across runs.
+fn hsm_state_file() -> PathBuf {
+    cache_file("ledger_manager_hsm.json")
+}
+
+// Where to remember the firmware update in progress, to resume it if it's interrupted.
+fn firmware_state_file() -> PathBuf {
+    cache_file("ledger_manager_firmware.json")
+}
+
+// Use the fastest of the HSM endpoints set as a comma-separated list in LEDGER_HSM_URLS, if any.
+fn select_hsm() {
+    let urls: Vec<String> = match env::var("LEDGER_HSM_URLS") {
+        Ok(urls) => urls
+            .split(',')
+            .map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .collect(),
+        Err(_) => return,
     };
-    match TransportNativeHID::new(&hid_api) {
-        Ok(a) => a,
-        Err(e) => error!("Error connecting to Ledger device: {}.", e),
+    if let Err(e) = select_socket_url(&urls, &hsm_state_file(), HSM_PROBE_INTERVAL) {
+        error!("Error selecting the HSM endpoint: {}", e);
+    }
+}
+
+// Reach Ledger's API and HSM through the proxy set in LEDGER_PROXY, if any, rather than the one set
+// in HTTPS_PROXY.
+fn load_proxy() {
+    match env::var("LEDGER_PROXY") {
+        Ok(url) if !url.is_empty() => {
+            if let Err(e) = set_proxy(url) {
+                error!("{}", e);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Verify the certificate of Ledger's HSM against the authorities in LEDGER_CA_BUNDLE and the pins in
+// LEDGER_CERT_PINS, if set.
+fn load_tls() {
+    if let Ok(path) = env::var("LEDGER_CA_BUNDLE") {
+        if let Err(e) = set_ca_bundle(path) {
+            error!("{}", e);
+        }
+    }
+    if let Ok(pins) = env::var("LEDGER_CERT_PINS") {
+        let pins = pins.split(',').map(str::trim).filter(|pin| !pin.is_empty());
+        if let Err(e) = set_certificate_pins(pins) {
+            error!("{}", e);
+        }
+    }
+}
+
+// Retry the requests to Ledger's API and HSM as many times as set in LEDGER_API_RETRIES, and space
+// them out by the number of milliseconds set in LEDGER_API_INTERVAL, if set.
+fn load_retry_policy() {
+    let mut policy = RetryPolicy::default();
+    match env::var("LEDGER_API_RETRIES").map(|r| r.parse::<u32>()) {
+        Ok(Ok(retries)) => policy.retries = retries,
+        Ok(Err(e)) => error!("Invalid LEDGER_API_RETRIES: {}.", e),
+        Err(_) => {}
+    }
+    match env::var("LEDGER_API_INTERVAL").map(|i| i.parse::<u64>()) {
+        Ok(Ok(millis)) => policy.min_interval = Duration::from_millis(millis),
+        Ok(Err(e)) => error!("Invalid LEDGER_API_INTERVAL: {}.", e),
+        Err(_) => {}
+    }
+    set_retry_policy(policy);
+}
+
+// Query the Ledger API endpoint set in LEDGER_API_URL, if any, rather than the default one.
+fn load_api_url() {
+    match env::var("LEDGER_API_URL") {
+        Ok(url) if !url.is_empty() => set_api_url(url),
+        _ => {}
+    }
+}
+
+// Look up the apps and firmwares in the catalog of the provider set in LEDGER_PROVIDER, if any,
+// rather than the default one.
+fn load_provider() {
+    match env::var("LEDGER_PROVIDER").map(|p| p.parse::<u32>()) {
+        Ok(Ok(provider)) => set_provider(provider),
+        Ok(Err(e)) => error!("Invalid LEDGER_PROVIDER: {}.", e),
+        Err(_) => {}
+    }
+}
+
+// Cache the answers of the Ledger API about its catalog for as many seconds as set in
+// LEDGER_CACHE_TTL (10 minutes by default, 0 not to cache them).
+fn load_cache() {
+    let ttl = match env::var("LEDGER_CACHE_TTL").map(|t| t.parse::<u64>()) {
+        Ok(Ok(t)) => Duration::from_secs(t),
+        Ok(Err(e)) => error!("Invalid LEDGER_CACHE_TTL: {}.", e),
+        Err(_) => API_CACHE_TTL,
+    };
+    if !ttl.is_zero() {
+        set_cache(cache_file("ledger_manager_api"), ttl);
+    }
+}
+
+// Only get the catalog from the package directory set in LEDGER_PACKAGE_DIR, if set, rather than
+// from the Ledger API.
+fn load_package_dir() {
+    match env::var("LEDGER_PACKAGE_DIR") {
+        Ok(dir) if !dir.is_empty() => set_package_dir(dir),
+        _ => {}
+    }
+}
+
+// How to reach the device, as set in LEDGER_TRANSPORT.
+enum TransportKind {
+    // Over USB, the default.
+    Hid,
+    // The Speculos emulator listening on this address.
+    Speculos(String),
+    // The bridge on another machine listening on this address, or on this Unix socket.
+    Remote(String),
+}
+
+// Append the default port to this address if it has none.
+fn with_default_port(addr: &str, port: u16) -> String {
+    if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{}:{}", addr, port)
+    }
+}
+
+// Read LEDGER_TRANSPORT: hid, speculos:HOST:PORT (localhost and the default port if omitted) or
+// remote:HOST:PORT (the default port if omitted) or remote:PATH for a Unix socket.
+fn transport_kind() -> TransportKind {
+    let transport = match env::var("LEDGER_TRANSPORT") {
+        Ok(t) => t,
+        Err(_) => return TransportKind::Hid,
+    };
+    if transport == "hid" {
+        return TransportKind::Hid;
+    }
+    match transport.split_once(':') {
+        None if transport == "speculos" => TransportKind::Speculos(with_default_port(
+            "localhost",
+            SPECULOS_DEFAULT_PORT,
+        )),
+        Some(("speculos", addr)) => {
+            TransportKind::Speculos(with_default_port(addr, SPECULOS_DEFAULT_PORT))
+        }
+        Some(("remote", path)) if path.starts_with('/') => TransportKind::Remote(path.to_string()),
+        Some(("remote", addr)) if !addr.is_empty() => {
+            TransportKind::Remote(with_default_port(addr, BRIDGE_DEFAULT_PORT))
+        }
+        _ => error!(
+            "Invalid LEDGER_TRANSPORT '{}'. It must be hid, speculos:HOST:PORT or remote:HOST:PORT.",
+            transport
+        ),
+    }
+}
+
+// The settings of the USB transport, from LEDGER_TIMEOUT and LEDGER_RETRIES.
+fn transport_config() -> TransportConfig {
+    let mut config = TransportConfig::default();
+    match env::var("LEDGER_TIMEOUT").map(|t| t.parse::<u64>()) {
+        Ok(Ok(0)) => config.timeout = None,
+        Ok(Ok(t)) => config.timeout = Some(Duration::from_secs(t)),
+        Ok(Err(e)) => error!("Invalid LEDGER_TIMEOUT: {}.", e),
+        Err(_) => {}
+    }
+    match env::var("LEDGER_RETRIES").map(|r| r.parse::<u32>()) {
+        Ok(Ok(r)) => config.retries = r,
+        Ok(Err(e)) => error!("Invalid LEDGER_RETRIES: {}.", e),
+        Err(_) => {}
+    }
+    config
+}
+
+// The same, without retrying to connect when already polling for the device.
+fn polling_config() -> TransportConfig {
+    TransportConfig {
+        retries: 0,
+        ..transport_config()
+    }
+}
+
+fn ledger_api() -> Box<dyn LedgerTransport> {
+    match connect(transport_config()) {
+        Ok(t) => t,
+        Err(e) => error!("{}", e),
+    }
+}
+
+// Connect to the device over the transport set in LEDGER_TRANSPORT, with these settings if it's
+// over USB.
+fn connect(config: TransportConfig) -> Result<Box<dyn LedgerTransport + Send>, String> {
+    match transport_kind() {
+        TransportKind::Hid => match TransportHid::new(config) {
+            Ok(t) => Ok(Box::new(t)),
+            Err(e) => Err(format!("Error connecting to Ledger device: {}", e)),
+        },
+        TransportKind::Speculos(addr) => match TransportSpeculos::connect(&addr) {
+            Ok(t) => Ok(Box::new(t)),
+            Err(e) => Err(format!("Error connecting to Speculos at {}: {}", addr, e)),
+        },
+        TransportKind::Remote(addr) => {
+            #[cfg(unix)]
+            let res = if addr.starts_with('/') {
+                TransportRemote::connect_unix(&addr)
+            } else {
+                TransportRemote::connect(&addr)
+            };
+            #[cfg(not(unix))]
+            let res = TransportRemote::connect(&addr);
+            match res {
+                Ok(t) => Ok(Box::new(t)),
+                Err(e) => Err(format!("Error connecting to the bridge at {}: {}", addr, e)),
+            }
+        }
+    }
+}
+
+// Exit with guidance if the device was left in the middle of a firmware update, rather than failing
+// later on with a communication error.
+fn check_firmware_not_interrupted(ledger_api: &dyn LedgerTransport) {
+    let device_info = match DeviceInfo::new(ledger_api) {
+        Ok(i) => i,
+        // Let the command report the error.
+        Err(_) => return,
+    };
+    let state = if device_info.is_bootloader {
+        "it's in bootloader mode"
+    } else if device_info.is_osu() {
+        "it runs the firmware updater"
+    } else {
+        return;
+    };
+    error!("Your device was left in the middle of a firmware update ({}). Run the updatefirm command with LEDGER_RESUME set to complete it.", state);
+}
+
+// Ask the user to unlock the device and wait for it, for the number of seconds set in
+// LEDGER_UNLOCK_TIMEOUT (a minute by default).
+fn wait_for_unlock(ledger_api: &dyn LedgerTransport) -> Result<DeviceInfo, Error> {
+    let timeout = match env::var("LEDGER_UNLOCK_TIMEOUT").map(|t| t.parse::<u64>()) {
+        Ok(Ok(t)) => Duration::from_secs(t),
+        Ok(Err(e)) => error!("Invalid LEDGER_UNLOCK_TIMEOUT: {}.", e),
+        Err(_) => Duration::from_secs(60),
+    };
+    println!("Your device is locked. Unlock it with your PIN to continue.");
+    match wait_unlocked(ledger_api, timeout) {
+        Err(Error::LockedDevice) => {
+            error!("Your device is still locked. Unlock it with your PIN and try again.")
+        }
+        res => res,
+    }
+}
+
+// Run this operation, again once the device is unlocked if it failed because the device locked
+// itself meanwhile. Fail telling how to quit the app open on the device if it needs the dashboard.
+fn when_unlocked<T, E>(
+    ledger_api: &dyn LedgerTransport,
+    mut op: impl FnMut() -> Result<T, E>,
+    is_locked_device: impl Fn(&E) -> bool,
+    app_open: impl Fn(&E) -> Option<&str>,
+) -> Result<T, E> {
+    loop {
+        match op() {
+            Err(e) if is_locked_device(&e) => {
+                if let Err(e) = wait_for_unlock(ledger_api) {
+                    error!("Error fetching device info: {}", e);
+                }
+            }
+            Err(e) => match app_open(&e) {
+                Some(name) => app_open_error(name),
+                None => return Err(e),
+            },
+            res => return res,
+        }
     }
 }
 
-fn device_info(ledger_api: &TransportNativeHID) -> DeviceInfo {
-    match DeviceInfo::new(ledger_api) {
+// Get the device info. If the device is locked, ask the user to unlock it and wait for it.
+fn device_info(ledger_api: &dyn LedgerTransport) -> DeviceInfo {
+    let res = match DeviceInfo::new(ledger_api) {
+        Err(Error::LockedDevice) => wait_for_unlock(ledger_api),
+        res => res,
+    };
+    match res {
         Ok(i) => i,
-        Err(e) => error!("Error fetching device info: {}", e),
+        Err(e) => match bootloader_model() {
+            Some(model) => error!("Error fetching device info: {}\nYour {} seems to be in bootloader mode. Run the updatefirm command with LEDGER_RESUME set to recover it.", e, model),
+            None => error!("Error fetching device info: {}", e),
+        },
+    }
+}
+
+// The model of the connected Ledger device if it enumerates in bootloader mode, as told by its USB
+// product id.
+fn bootloader_model() -> Option<DeviceModel> {
+    let hid_api = HidApi::new().ok()?;
+    enumerate_devices(&hid_api)
+        .into_iter()
+        .find(|d| d.is_bootloader())
+        .and_then(|d| d.model)
+}
+
+// The USB serial number of the connected Ledger device, unless it's reached otherwise.
+fn device_serial() -> Option<String> {
+    if !matches!(transport_kind(), TransportKind::Hid) {
+        return None;
+    }
+    let hid_api = HidApi::new().ok()?;
+    enumerate_devices(&hid_api)
+        .into_iter()
+        .find_map(|d| d.serial)
+}
+
+// Print the versions of the components of the device and its identifiers.
+fn print_hardware_report(ledger_api: &dyn LedgerTransport, device_info: &DeviceInfo) {
+    let unknown = || "unknown".to_string();
+    let rows = [
+        ("Connection", ledger_api.describe()),
+        ("Target id", format!("{:#010x}", device_info.target_id)),
+        (
+            "Firmware (SE)",
+            device_info.se_version.clone().unwrap_or_else(unknown),
+        ),
+        (
+            "MCU firmware",
+            device_info.mcu_version.clone().unwrap_or_else(unknown),
+        ),
+        (
+            "MCU bootloader",
+            device_info
+                .mcu_bootloader_version
+                .clone()
+                .unwrap_or_else(unknown),
+        ),
+        (
+            "Hardware version",
+            device_info
+                .hardware_version
+                .map(|v| v.to_string())
+                .unwrap_or_else(unknown),
+        ),
+        ("Serial number", device_serial().unwrap_or_else(unknown)),
+        ("Flags", format!("0x{}", hex::encode(&device_info.flags))),
+    ];
+    println!("Device:");
+    for (name, value) in rows {
+        println!("  {:<18}{}", format!("{}:", name), value);
     }
 }
 
-fn print_ledger_info(ledger_api: &TransportNativeHID) {
+// Tell the user how to recover a device left in bootloader mode.
+fn print_bootloader_info(device_info: &DeviceInfo) {
+    println!(
+        "Your device is in bootloader mode (MCU bootloader version {}).",
+        device_info.version
+    );
+    if let Some(version) = &device_info.se_version {
+        println!("Its Secure Element runs firmware {}.", version);
+    }
+    println!("Its apps can't be managed in this mode. This usually happens when a firmware update was interrupted. Run the updatefirm command with LEDGER_RESUME set to recover it.");
+}
+
+fn print_ledger_info(ledger_api: &dyn LedgerTransport) {
     let device_info = device_info(ledger_api);
-    println!("Information about the device: {:#?}", device_info);
+    print_hardware_report(ledger_api, &device_info);
+    if device_info.is_bootloader {
+        print_bootloader_info(&device_info);
+        return;
+    }
+    if let Some(model) = device_info.model() {
+        let features = [
+            (model.has_touchscreen(), "touchscreen"),
+            (model.has_battery(), "battery"),
+            (model.has_bluetooth(), "Bluetooth"),
+        ];
+        let features: Vec<_> = features
+            .iter()
+            .filter(|(has, _)| *has)
+            .map(|(_, name)| *name)
+            .collect();
+        println!(
+            "Model: {} ({}fits up to about {} apps).",
+            model,
+            if features.is_empty() {
+                String::new()
+            } else {
+                format!("{}, ", features.join(", "))
+            },
+            model.max_app_count_estimate()
+        );
+    }
+    if let Some(settings) = device_info.settings() {
+        let yes_no = |b| if b { "yes" } else { "no" };
+        println!(
+            "Settings: seed set up: {}, PIN entered: {}, manager allowed: {}, recovery mode: {}.",
+            yes_no(settings.onboarded),
+            yes_no(settings.pin_validated),
+            yes_no(settings.manager_allowed),
+            yes_no(settings.recovery_mode)
+        );
+    }
+    match battery_status(ledger_api, &device_info) {
+        Ok(Some(battery)) => println!(
+            "Battery: {} ({} mV, {}).",
+            battery
+                .percentage
+                .map(|p| format!("{}%", p))
+                .unwrap_or_else(|| "unknown charge".to_string()),
+            battery.voltage,
+            match battery.charging {
+                ChargingMode::None => "not charging",
+                ChargingMode::Usb => "charging over USB",
+                ChargingMode::Qi => "charging wirelessly",
+            }
+        ),
+        Ok(None) => {}
+        Err(e) => eprintln!("Error querying the battery of the device: {}.", e),
+    }
+    if device_info.settings().is_some_and(|s| !s.onboarded) {
+        println!("Your device isn't set up yet: it has no seed and no apps. Set up or restore a seed on it before installing apps.");
+        return;
+    }
 
     println!("Querying installed applications from your Ledger. You might have to confirm on your device.");
     let apps = match list_installed_apps(ledger_api) {
@@ -102,12 +737,80 @@ fn print_ledger_info(ledger_api: &TransportNativeHID) {
         Err(e) => error!("Error listing installed applications: {}.", e),
     };
     println!("Installed applications:");
+    let mut table = Table::new(&["NAME", "VERSION", "SIZE", "HASH"]);
+    let mut unknown = Vec::new();
+    let mut known = Vec::new();
+    let names: Vec<_> = apps.iter().map(|app| app.name.clone()).collect();
+    for app in apps {
+        table.row(vec![
+            app.name.clone(),
+            app.version.clone().unwrap_or_else(|| "unknown".to_string()),
+            match app.bytes {
+                Some(bytes) => format!("{} bytes", bytes),
+                None => format!("{} blocks", app.blocks),
+            },
+            hex::encode(&app.hash),
+        ]);
+        match app.info {
+            Some(info) => known.push(info),
+            None => unknown.push(app.name),
+        }
+    }
+    table.print();
+
+    // Warn about the apps which can't work fully because the apps they call into are missing.
+    let registry = registry();
+    for name in &names {
+        let missing = registry.missing_plugins(name, &names);
+        if !missing.is_empty() {
+            println!(
+                "Warning: the {} app needs these applications, which are not installed: {}. Install them with the installapp command.",
+                name,
+                missing.join(", ")
+            );
+        }
+    }
+
+    // Warn about the apps which would not survive a firmware update.
+    match discontinued_apps(&device_info, &known) {
+        Ok(discontinued) => unknown.extend(discontinued),
+        Err(e) => eprintln!("Error checking for discontinued applications: {}.", e),
+    }
+    if !unknown.is_empty() {
+        println!(
+            "Warning: these applications are not available in Ledger's catalog for your device anymore: {}. They were likely discontinued and may be removed by a firmware update, after which they could not be reinstalled.",
+            unknown.join(", ")
+        );
+    }
+}
+
+// List the apps available in Ledger's catalog for this device.
+fn list_available(ledger_api: &dyn LedgerTransport) {
+    let device_info = device_info(ledger_api);
+    let mut apps = match available_apps(&device_info) {
+        Ok(a) => a,
+        Err(e) => error!("Error querying the apps catalog: {}.", e),
+    };
+    apps.sort_by_key(|a| a.name.to_lowercase());
+    let mut table = Table::new(&["NAME", "VERSION", "SIZE", "DEPENDS ON"]);
     for app in apps {
-        println!("  - {:?}", app);
+        table.row(vec![
+            app.name,
+            app.version,
+            app.bytes
+                .map(|b| format!("{} bytes", b))
+                .unwrap_or_default(),
+            app.dependencies.join(", "),
+        ]);
+    }
+    if table.is_empty() {
+        println!("No application available for your device.");
+    } else {
+        table.print();
     }
 }
 
-fn perform_genuine_check(ledger_api: &TransportNativeHID) {
+fn perform_genuine_check(ledger_api: &dyn LedgerTransport) {
     println!("Querying Ledger's remote HSM to perform the genuine check. You might have to confirm the operation on your device.");
     if let Err(e) = genuine_check(ledger_api) {
         error!("Error when performing genuine check: {}", e);
@@ -115,114 +818,1923 @@ fn perform_genuine_check(ledger_api: &TransportNativeHID) {
     println!("Success. Your Ledger is genuine.");
 }
 
+// Get the strategy to install apps' dependencies with. Set LEDGER_APP_ONLY to only install the
+// app binary.
+fn install_strategy(ledger_api: &dyn LedgerTransport) -> InstallStrategy {
+    if env::var("LEDGER_APP_ONLY").is_ok() {
+        return InstallStrategy::AppOnly;
+    }
+    if device_info(ledger_api).is_nano_s() {
+        println!("Tip: storage is scarce on the Nano S. Set LEDGER_APP_ONLY to never install the libraries an app depends on.");
+    }
+    InstallStrategy::WithDependencies
+}
+
+// Get the options to install or update an app with. Set LEDGER_FORCE to reinstall an app already
+// installed (or already up to date).
+fn install_options(app: impl Into<InstallOptions>, strategy: InstallStrategy) -> InstallOptions {
+    app.into()
+        .strategy(strategy)
+        .force(env::var("LEDGER_FORCE").is_ok())
+        .allow_downgrade(env::var("LEDGER_ALLOW_DOWNGRADE").is_ok())
+}
+
+// Set LEDGER_RELEASE_NOTES to be shown the description Ledger publishes for the version of the app
+// about to be flashed, and confirm it.
+fn confirm_release_notes(ledger_api: &dyn LedgerTransport, options: &InstallOptions) {
+    if env::var("LEDGER_RELEASE_NOTES").is_err() {
+        return;
+    }
+    let app = match target_app(&device_info(ledger_api), options) {
+        Ok(Some(app)) => app,
+        // Let the install report it.
+        Ok(None) => return,
+        Err(e) => error!("Error querying the catalog: {}", e),
+    };
+    println!("{} app version {}:", app.version_name, app.version);
+    match app.description.as_deref().filter(|d| !d.trim().is_empty()) {
+        Some(description) => println!("{}", description.trim()),
+        None => println!("No release notes were published for this version."),
+    }
+    if let Some(warning) = app.warning.as_deref().filter(|w| !w.trim().is_empty()) {
+        println!("Warning: {}", warning.trim());
+    }
+    if !confirm("Flash this version?") {
+        error!("Aborted.");
+    }
+}
+
 // Install the Bitcoin app on the device.
-fn install_bitcoin(ledger_api: &TransportNativeHID, is_testnet: bool) {
+fn install_bitcoin(ledger_api: &dyn LedgerTransport, is_testnet: bool) {
+    let app = if is_testnet {
+        LedgerApp::BitcoinTest
+    } else {
+        LedgerApp::Bitcoin
+    };
+    let strategy = install_strategy(ledger_api);
     println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to install the app.");
-    match install_bitcoin_app(ledger_api, is_testnet) {
+    let options = install_options(app, strategy);
+    confirm_release_notes(ledger_api, &options);
+    match install_app(ledger_api, options) {
         Ok(()) => println!("Successfully installed the app."),
         Err(InstallErr::AlreadyInstalled) => {
             error!("Bitcoin app already installed. Use the update command to update it.")
         }
         Err(InstallErr::AppNotFound) => error!("Could not get info about Bitcoin app."),
+        Err(InstallErr::MissingDependency { name, chain }) => {
+            error!(
+                "Bitcoin app requires the '{}' app to be installed first ({}).",
+                name,
+                chain.join(" -> ")
+            )
+        }
+        Err(
+            e @ InstallErr::NotEnoughSpace {
+                required,
+                available,
+            },
+        ) => error!(
+            "Error installing Bitcoin app: {}{}",
+            e,
+            free_space_hint(ledger_api, required, available)
+        ),
+        Err(e @ InstallErr::NotOnboarded) => error!("Error installing Bitcoin app: {}", e),
         Err(InstallErr::Any(e)) => error!("Error installing Bitcoin app: {}.", e),
     }
 }
 
-fn update_bitcoin(ledger_api: &TransportNativeHID, is_testnet: bool) {
+fn update_bitcoin(ledger_api: &dyn LedgerTransport, is_testnet: bool) {
     println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to install the app.");
-    match update_bitcoin_app(ledger_api, is_testnet) {
+    let app = if is_testnet {
+        LedgerApp::BitcoinTest
+    } else {
+        LedgerApp::Bitcoin
+    };
+    let options = install_options(app, InstallStrategy::default());
+    confirm_release_notes(ledger_api, &options);
+    match update_app(ledger_api, options) {
         Ok(()) => println!("Successfully updated the app."),
         Err(UpdateErr::NotInstalled) => {
             error!("Bitcoin app isn't installed. Use the install command instead.")
         }
         Err(UpdateErr::AppNotFound) => error!("Could not get info about Bitcoin app."),
         Err(UpdateErr::AlreadyLatest) => error!("Bitcoin app is already at the latest version."),
+        Err(
+            e @ UpdateErr::NotEnoughSpace {
+                required,
+                available,
+            },
+        ) => error!(
+            "Error updating Bitcoin app: {}{}",
+            e,
+            free_space_hint(ledger_api, required, available)
+        ),
+        Err(e @ UpdateErr::Downgrade { .. }) => error!(
+            "Error updating Bitcoin app: {} Set LEDGER_ALLOW_DOWNGRADE to install it anyway.",
+            e
+        ),
         Err(UpdateErr::Any(e)) => error!("Error installing Bitcoin app: {}.", e),
     }
 }
 
-fn open_bitcoin(ledger_api: &TransportNativeHID, is_testnet: bool) {
-    if let Err(e) = open_bitcoin_app(ledger_api, is_testnet) {
-        error!("Error opening Bitcoin app: {}", e);
+// The minimum charge of the battery to start a firmware update, on models with a battery.
+const MIN_FIRMWARE_UPDATE_BATTERY: u8 = 20;
+
+// How long to wait for the device to restart between the stages of a firmware update. Installing the
+// firmware takes a few minutes.
+const FIRMWARE_RESTART_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Remember the firmware update in progress, to resume it if it's interrupted.
+fn save_firmware_state(update: &FirmwareUpdate) {
+    let state_file = firmware_state_file();
+    if let Err(e) = serde_json::to_string(update)
+        .map_err(|e| e.to_string())
+        .and_then(|s| fs::write(&state_file, s).map_err(|e| e.to_string()))
+    {
+        error!(
+            "Error writing the firmware update state to {}: {}",
+            state_file.display(),
+            e
+        );
     }
 }
 
-// Install the Solana app on the device.
-fn install_solana(ledger_api: &TransportNativeHID) {
-    println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to install the app.");
-    match install_app(ledger_api, LedgerApp::Solana) {
-        Ok(()) => println!("Successfully installed the Solana app."),
-        Err(InstallErr::AlreadyInstalled) => {
-            error!("Solana app already installed. Use the update command to update it.")
+// Update the firmware of the device to the latest version. The update happens in stages, with the
+// device restarting in between. The update in progress is remembered, so an interrupted update can be
+// resumed by running the command again. A device left running the firmware updater tells which
+// update it was in the middle of, so it can be completed even if it wasn't remembered. With
+// `resume_only`, don't start a new update.
+fn update_firmware(ledger_api: &dyn LedgerTransport, resume_only: bool) {
+    let state_file = firmware_state_file();
+    let mut device_info = device_info(ledger_api);
+
+    let saved = fs::read_to_string(&state_file)
+        .ok()
+        .and_then(|s| serde_json::from_str::<FirmwareUpdate>(&s).ok());
+    let pending = match saved {
+        Some(update) => Some(update),
+        None if device_info.is_osu() => match pending_firmware_update(&device_info) {
+            Ok(update) => {
+                if let Some(update) = &update {
+                    save_firmware_state(update);
+                }
+                update
+            }
+            Err(e) => error!(
+                "Error querying the firmware update your device was left in: {}",
+                e
+            ),
+        },
+        None => None,
+    };
+    let update = match pending {
+        Some(update) => {
+            println!(
+                "Resuming the update to firmware {}.",
+                update.final_firmware.name
+            );
+            update
+        }
+        None => {
+            if device_info.is_bootloader {
+                recover_firmware(ledger_api, device_info);
+                return;
+            }
+            if resume_only {
+                error!(
+                    "No interrupted firmware update to resume. Your device runs firmware {}.",
+                    device_info.version
+                );
+            }
+            let update = match firmware_update(&device_info) {
+                Ok(Some(update)) => update,
+                Ok(None) => {
+                    println!("Your firmware is up to date.");
+                    return;
+                }
+                Err(e) => error!("Error querying the latest firmware: {}", e),
+            };
+            println!(
+                "Firmware {} is available, your device runs {}.",
+                update.final_firmware.name, device_info.version
+            );
+            firmware_update_checks(ledger_api, &device_info, &update);
+            match update.notes() {
+                Some(notes) => println!("Release notes:\n{}", notes.trim()),
+                None => println!("No release notes were published for this firmware."),
+            }
+
+            announce_confirmations(&[
+                "allow the Ledger manager to install the update".to_string(),
+                "confirm the firmware update, after which the device restarts (possibly several times)".to_string(),
+            ]);
+            println!("Keep your device plugged in during the whole update. Make sure you have your recovery phrase at hand.");
+            if !confirm("Start the update?") {
+                error!("Aborted.");
+            }
+            save_firmware_state(&update);
+            update
         }
-        Err(InstallErr::AppNotFound) => error!("Could not get info about Solana app."),
-        Err(InstallErr::Any(e)) => error!("Error installing Solana app: {}.", e),
+    };
+
+    if update.is_downgrade(&device_info) && env::var("LEDGER_ALLOW_DOWNGRADE").is_err() {
+        let _ = fs::remove_file(&state_file);
+        error!("Firmware {} is older than the firmware {} your device runs. Refusing to downgrade it, set LEDGER_ALLOW_DOWNGRADE to do it anyway.", update.final_firmware.name, device_info.version);
     }
-}
 
-fn update_solana(ledger_api: &TransportNativeHID) {
-    println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to install the app.");
-    match update_app(ledger_api, LedgerApp::Solana) {
-        Ok(()) => println!("Successfully updated the Solana app."),
-        Err(UpdateErr::NotInstalled) => {
-            error!("Solana app isn't installed. Use the install command instead.")
+    let interrupted = |e: Error| -> ! {
+        error!("Error updating the firmware: {}\nThe update was interrupted. This is recoverable: reconnect your device (it may be in bootloader mode, that's expected) and run the updatefirm command again with LEDGER_RESUME set to resume the update.", e)
+    };
+    let mut transport = None;
+    let mut previous = None;
+    loop {
+        let ledger_api = transport.as_deref().unwrap_or(ledger_api);
+        let stage = FirmwareStage::of(&device_info, &update.final_firmware);
+        if previous == Some(stage) {
+            match stage {
+                // The MCU stage is repeated when the MCU bootloader is updated first.
+                FirmwareStage::Mcu => {}
+                FirmwareStage::Done => error!("Your device didn't restart in bootloader mode. Once it's in bootloader mode, run the updatefirm command again with LEDGER_RESUME set to update its MCU."),
+                _ => {
+                    let _ = fs::remove_file(&state_file);
+                    error!("The firmware update wasn't applied by the device. Was it refused on the device? Run the updatefirm command again to retry.");
+                }
+            }
+        }
+        match stage {
+            FirmwareStage::Osu => {
+                println!(
+                    "Installing the firmware update. Allow the Ledger manager on your device."
+                );
+                let mut progress = Progress::new("Flashing the firmware update");
+                let res = install_osu(ledger_api, &device_info, &update.osu, |f| progress.set(f));
+                progress.finish();
+                if let Err(e) = res {
+                    interrupted(e);
+                }
+                println!("Now confirm the firmware update on your device.");
+            }
+            FirmwareStage::Mcu => {
+                println!("Your device is in bootloader mode. Flashing its MCU.");
+                let mut progress = Progress::new("Flashing the MCU");
+                let res = flash_mcu(ledger_api, &device_info, &update.final_firmware, |f| {
+                    progress.set(f)
+                });
+                progress.finish();
+                match res {
+                    Ok(McuFlash::Bootloader) => {
+                        println!("Updated the MCU bootloader first, the MCU will be flashed next.")
+                    }
+                    Ok(McuFlash::Mcu) => println!("Flashed the MCU."),
+                    Err(e) => interrupted(e),
+                }
+            }
+            FirmwareStage::Final => {
+                println!(
+                    "Installing the final firmware. You may have to confirm it on your device."
+                );
+                let mut progress = Progress::new("Flashing the final firmware");
+                let res =
+                    install_final_firmware(ledger_api, &device_info, &update.final_firmware, |f| {
+                        progress.set(f)
+                    });
+                progress.finish();
+                if let Err(e) = res {
+                    interrupted(e);
+                }
+            }
+            FirmwareStage::Done => {
+                match mcu_update_needed(&device_info, &update.final_firmware) {
+                    Ok(false) => {
+                        let _ = fs::remove_file(&state_file);
+                        println!(
+                            "Your device now runs firmware {}.",
+                            update.final_firmware.name
+                        );
+                        return;
+                    }
+                    // Some devices don't restart in bootloader mode on their own.
+                    Ok(true) => println!("The MCU of your device must be updated for the new firmware. Unplug your device and plug it back in bootloader mode (see Ledger's support for how to with your model)."),
+                    Err(e) => interrupted(e),
+                }
+            }
+        }
+        previous = Some(stage);
+
+        if stage != FirmwareStage::Done {
+            println!("Waiting for your device to restart. Don't unplug it.");
         }
-        Err(UpdateErr::AppNotFound) => error!("Could not get info about Solana app."),
-        Err(UpdateErr::AlreadyLatest) => error!("Solana app is already at the latest version."),
-        Err(UpdateErr::Any(e)) => error!("Error updating Solana app: {}.", e),
+        let new_transport = match wait_restart(ledger_api) {
+            Some(t) => t,
+            None => error!("Timed out waiting for your device to restart. If it's stuck, unplug it and plug it back in, then run the updatefirm command again with LEDGER_RESUME set to resume the update."),
+        };
+        device_info = match DeviceInfo::new(&new_transport) {
+            Ok(info) => info,
+            Err(e) => interrupted(e),
+        };
+        transport = Some(new_transport);
     }
 }
 
-fn open_solana(ledger_api: &TransportNativeHID) {
-    if let Err(e) = open_app(ledger_api, LedgerApp::Solana) {
-        error!("Error opening Solana app: {}", e);
+// Print the firmware versions of the device and the latest firmware available for it, without
+// installing anything.
+fn check_firmware(ledger_api: &dyn LedgerTransport) {
+    let status = match firmware_status(&device_info(ledger_api)) {
+        Ok(s) => s,
+        Err(e) => error!("Error querying the firmware of your Ledger: {}.", e),
+    };
+    if status.is_bootloader {
+        println!("Your device is in bootloader mode.");
+    }
+    if let Some(version) = &status.se_version {
+        println!("Secure Element firmware: {}", version);
+    }
+    if let Some(version) = &status.mcu_version {
+        println!("MCU firmware: {}", version);
+    }
+    if let Some(version) = &status.bootloader_version {
+        println!("MCU bootloader: {}", version);
+    }
+    if status.is_bootloader {
+        return;
+    }
+    match status.latest_version {
+        Some(latest) => println!(
+            "Firmware {} is available. Update to it with the updatefirm command.",
+            latest
+        ),
+        None => println!("Your firmware is up to date."),
     }
 }
 
-fn main() {
-    let command = if let Some(cmd) = Command::get() {
-        cmd
-    } else {
-        error!("Invalid or no command specified. The command must be passed through the LEDGER_COMMAND env var. Set LEDGER_TESTNET to use the Bitcoin testnet app instead where applicable.");
+// Recover a device stuck in bootloader mode, for which no firmware update in progress was remembered,
+// by flashing the MCU firmware matching the firmware of its Secure Element.
+// Like the repair of Ledger Live, see https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/firmwareUpdate-repair.ts
+fn recover_firmware(ledger_api: &dyn LedgerTransport, mut device_info: DeviceInfo) {
+    print_bootloader_info(&device_info);
+    let final_firmware = match current_final_firmware(&device_info) {
+        Ok(f) => f,
+        Err(e) => error!("Error querying the firmware of your device: {}\nUse Ledger Live's firmware repair to recover it.", e),
     };
+    println!(
+        "Its MCU will be flashed for firmware {}. Keep your device plugged in.",
+        final_firmware.name
+    );
+    if !confirm("Recover the device?") {
+        error!("Aborted.");
+    }
 
-    let ledger_api = ledger_api();
-    match command {
-        Command::GetInfo => {
-            print_ledger_info(&ledger_api);
+    let mut transport = None;
+    let mut flashed = None;
+    while device_info.is_bootloader {
+        let ledger_api = transport.as_deref().unwrap_or(ledger_api);
+        if flashed == Some(McuFlash::Mcu) {
+            error!("Your device is still in bootloader mode after flashing its MCU. Use Ledger Live's firmware repair to recover it.");
         }
-        Command::GenuineCheck => {
-            perform_genuine_check(&ledger_api);
+        let mut progress = Progress::new("Flashing the MCU");
+        let res = flash_mcu(ledger_api, &device_info, &final_firmware, |f| {
+            progress.set(f)
+        });
+        progress.finish();
+        flashed = match res {
+            Ok(flash) => Some(flash),
+            Err(e) => error!("Error flashing the MCU of your device: {}", e),
+        };
+
+        println!("Waiting for your device to restart. Don't unplug it.");
+        let new_transport = match wait_restart(ledger_api) {
+            Some(t) => t,
+            None => error!("Timed out waiting for your device to restart. Unplug it and plug it back in, then run the updatefirm command again with LEDGER_RESUME set."),
+        };
+        device_info = match DeviceInfo::new(&new_transport) {
+            Ok(info) => info,
+            Err(e) => error!("Error fetching device info: {}", e),
+        };
+        transport = Some(new_transport);
+    }
+    println!(
+        "Your device was recovered, it runs firmware {}.",
+        device_info.version
+    );
+}
+
+// Check the device can safely be updated to this firmware: it must be genuine, charged enough and
+// have enough storage left for the update.
+fn firmware_update_checks(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    update: &FirmwareUpdate,
+) {
+    perform_genuine_check(ledger_api);
+
+    // Losing power while flashing could brick the device. It's fine if it's powered over USB.
+    match battery_level(ledger_api, device_info) {
+        Ok(Some(level)) if level < MIN_FIRMWARE_UPDATE_BATTERY => {
+            match battery_charging(ledger_api, device_info) {
+                Ok(Some(ChargingMode::Usb)) => println!(
+                    "Warning: the battery of your device is at {}%. Keep it plugged in during the whole update.",
+                    level
+                ),
+                Ok(_) => error!(
+                    "The battery of your device is at {}%. Charge it to at least {}%, or plug it in over USB, before updating the firmware.",
+                    level, MIN_FIRMWARE_UPDATE_BATTERY
+                ),
+                Err(e) => error!("Error querying the battery of the device: {}", e),
+            }
         }
-        Command::InstallMainApp => {
-            install_bitcoin(&ledger_api, false);
+        Ok(_) => {}
+        Err(e) => error!("Error querying the battery of the device: {}", e),
+    }
+
+    if let Some(bytes) = update.final_firmware.bytes {
+        println!("Checking the storage left on your device. You may have to allow listing the installed apps on your device.");
+        match device_memory(ledger_api) {
+            Ok(Some(memory)) if memory.free < bytes => error!(
+                "The update needs {} more KB of storage on your device. Uninstall some apps first, you can reinstall them afterward (see the exportapps and restoreapps commands).",
+                (bytes - memory.free).div_ceil(1024)
+            ),
+            Ok(_) => {}
+            Err(e) => error!("Error querying the storage of the device: {}", e),
         }
-        Command::InstallTestApp => {
-            install_bitcoin(&ledger_api, true);
+    }
+}
+
+// Wait for the device to disconnect and connect again, for instance when it restarts, showing how
+// long it's been. `None` if it didn't in time.
+fn wait_restart(ledger_api: &dyn LedgerTransport) -> Option<Box<dyn LedgerTransport>> {
+    let start = Instant::now();
+    let mut progress = Progress::new("Restarting...");
+    while DeviceInfo::new(ledger_api).is_ok() {
+        if start.elapsed() > FIRMWARE_RESTART_TIMEOUT {
+            progress.finish();
+            return None;
         }
-        Command::OpenMainApp => {
-            open_bitcoin(&ledger_api, false);
+        progress.elapsed(start.elapsed());
+        thread::sleep(Duration::from_secs(1));
+    }
+    let mut transport = None;
+    while transport.is_none() && start.elapsed() < FIRMWARE_RESTART_TIMEOUT {
+        progress.elapsed(start.elapsed());
+        transport = connect(polling_config())
+            .ok()
+            .map(|t| t as Box<dyn LedgerTransport>);
+        thread::sleep(Duration::from_secs(1));
+    }
+    progress.finish();
+    transport
+}
+
+// The device re-enumerates when switching apps. Wait for it to be available again, for up to 30
+// seconds.
+fn try_reconnect() -> Option<Box<dyn LedgerTransport>> {
+    for _ in 0..30 {
+        thread::sleep(Duration::from_secs(1));
+        if let Ok(transport) = connect(polling_config()) {
+            return Some(transport);
         }
-        Command::OpenTestApp => {
-            open_bitcoin(&ledger_api, true);
+    }
+    None
+}
+
+// The commands need the dashboard to be shown on the device. If an app is open, quit it when
+// LEDGER_AUTO_QUIT is set and fail otherwise.
+fn ensure_dashboard(ledger_api: Box<dyn LedgerTransport>) -> Box<dyn LedgerTransport> {
+    let app = match current_app(&ledger_api) {
+        Ok(app) if !app.is_dashboard() => app,
+        // Let the command report the error, for instance if the device is locked.
+        _ => return ledger_api,
+    };
+    if env::var("LEDGER_AUTO_QUIT").is_err() {
+        app_open_error(&app.name);
+    }
+    println!("Quitting the {} app on your device.", app.name);
+    if let Err(e) = quit_app(&ledger_api) {
+        error!("Error quitting the {} app: {}", app.name, e);
+    }
+    drop(ledger_api);
+    reconnect()
+}
+
+// Fail because this app is open on the device while the command needs the dashboard.
+fn app_open_error(name: &str) -> ! {
+    error!(
+        "The {} app is open on your device. Quit it to go back to the dashboard, or set LEDGER_AUTO_QUIT to quit it automatically.",
+        name
+    )
+}
+
+fn reconnect() -> Box<dyn LedgerTransport> {
+    match try_reconnect() {
+        Some(transport) => transport,
+        None => error!("Timed out waiting for the Ledger device to reconnect."),
+    }
+}
+
+// Set LEDGER_WAIT_CLOSE to block until the user quits the app on the device, and report how long
+// it was open. For instance for a signing station which must be locked again afterward.
+fn wait_app_closed(app: LedgerApp) {
+    if env::var("LEDGER_WAIT_CLOSE").is_err() {
+        return;
+    }
+    let name = String::from_utf8_lossy(app.open_name()).to_string();
+    println!("Waiting for the {} app to be quit on the device.", name);
+    let start = Instant::now();
+    let mut transport = reconnect();
+    loop {
+        match current_app(&transport) {
+            Ok(running) if !running.is(app) => break,
+            // The app keeps running while the device is locked.
+            Ok(_) | Err(Error::LockedDevice) => thread::sleep(Duration::from_secs(1)),
+            // The device re-enumerates when the app is quit. Consider it closed if the device was
+            // unplugged.
+            Err(_) => match try_reconnect() {
+                Some(t) => transport = t,
+                None => break,
+            },
         }
-        Command::UpdateMainApp => {
-            update_bitcoin(&ledger_api, false);
+    }
+    let elapsed = start.elapsed().as_secs();
+    println!(
+        "The {} app was closed after {} min {} s.",
+        name,
+        elapsed / 60,
+        elapsed % 60
+    );
+}
+
+fn open_bitcoin(ledger_api: &dyn LedgerTransport, is_testnet: bool) {
+    if let Err(e) = open_bitcoin_app(ledger_api, is_testnet) {
+        error!("Error opening Bitcoin app: {}", e);
+    }
+
+    let app = if is_testnet {
+        LedgerApp::BitcoinTest
+    } else {
+        LedgerApp::Bitcoin
+    };
+    show_post_open_info(app);
+    wait_app_closed(app);
+}
+
+// Optionally display app-specific information once the app is open, so the user can check the
+// device holds the expected seed.
+fn show_post_open_info(app: LedgerApp) {
+    if env::var("LEDGER_SHOW_KEY").is_err() {
+        return;
+    }
+    let transport = reconnect();
+    match post_open_info(&transport, app) {
+        Ok(Some(info)) => println!("{}", info),
+        Ok(None) => {}
+        Err(e) => error!("Error querying the app: {}", e),
+    }
+}
+
+// Install this app on the device. It may be any app of the catalog, set by name. The apps it calls
+// into, as listed in the registry, are installed along with it.
+fn install(ledger_api: &dyn LedgerTransport, app: impl Into<InstallOptions>) {
+    let app = app.into();
+    let name = app.name().to_string();
+    let apps = registry().with_plugins(std::slice::from_ref(&name));
+    if apps.len() > 1 {
+        return install_with_plugins(ledger_api, &apps);
+    }
+    let strategy = install_strategy(ledger_api);
+    println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to install the app.");
+    let options = install_options(app, strategy);
+    confirm_release_notes(ledger_api, &options);
+    match when_unlocked(
+        ledger_api,
+        || install_app(ledger_api, options.clone()),
+        InstallErr::is_locked_device,
+        InstallErr::app_open,
+    ) {
+        Ok(()) => println!("Successfully installed the {} app.", name),
+        Err(InstallErr::AlreadyInstalled) => {
+            error!(
+                "{} app already installed. Use the update command to update it.",
+                name
+            )
         }
-        Command::UpdateTestApp => {
+        Err(InstallErr::AppNotFound) => error!("Could not get info about {} app.", name),
+        Err(InstallErr::MissingDependency { name: dep, chain }) => {
+            error!(
+                "{} app requires the '{}' app to be installed first ({}).",
+                name,
+                dep,
+                chain.join(" -> ")
+            )
+        }
+        Err(
+            e @ InstallErr::NotEnoughSpace {
+                required,
+                available,
+            },
+        ) => error!(
+            "Error installing {} app: {}{}",
+            name,
+            e,
+            free_space_hint(ledger_api, required, available)
+        ),
+        Err(e @ InstallErr::NotOnboarded) => error!("Error installing {} app: {}", name, e),
+        Err(InstallErr::Any(e)) => error!("Error installing {} app: {}.", name, e),
+    }
+}
+
+// Update this app, along with the apps it calls into as listed in the registry.
+fn update(ledger_api: &dyn LedgerTransport, app: impl Into<InstallOptions>) {
+    let app = app.into();
+    let name = app.name().to_string();
+    let apps = registry().with_plugins(std::slice::from_ref(&name));
+    if apps.len() > 1 {
+        return update_with_plugins(ledger_api, &apps);
+    }
+    println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to install the app.");
+    let options = install_options(app, InstallStrategy::default());
+    confirm_release_notes(ledger_api, &options);
+    match when_unlocked(
+        ledger_api,
+        || update_app(ledger_api, options.clone()),
+        UpdateErr::is_locked_device,
+        UpdateErr::app_open,
+    ) {
+        Ok(()) => println!("Successfully updated the {} app.", name),
+        Err(UpdateErr::NotInstalled) => {
+            error!(
+                "{} app isn't installed. Use the install command instead.",
+                name
+            )
+        }
+        Err(UpdateErr::AppNotFound) => error!("Could not get info about {} app.", name),
+        Err(UpdateErr::AlreadyLatest) => {
+            error!("{} app is already at the latest version.", name)
+        }
+        Err(
+            e @ UpdateErr::NotEnoughSpace {
+                required,
+                available,
+            },
+        ) => error!(
+            "Error updating {} app: {}{}",
+            name,
+            e,
+            free_space_hint(ledger_api, required, available)
+        ),
+        Err(e @ UpdateErr::Downgrade { .. }) => error!(
+            "Error updating {} app: {} Set LEDGER_ALLOW_DOWNGRADE to install it anyway.",
+            name, e
+        ),
+        Err(UpdateErr::Any(e)) => error!("Error updating {} app: {}.", name, e),
+    }
+}
+
+fn open(ledger_api: &dyn LedgerTransport, app: LedgerApp) {
+    if let Err(e) = when_unlocked(
+        ledger_api,
+        || open_app(ledger_api, app),
+        OpenAppErr::is_locked_device,
+        OpenAppErr::app_open,
+    ) {
+        error!(
+            "Error opening {} app: {}",
+            String::from_utf8_lossy(app.open_name()),
+            e
+        );
+    }
+    show_post_open_info(app);
+    wait_app_closed(app);
+}
+
+// Open the app with this name, as spelled on the device.
+fn open_named(ledger_api: &dyn LedgerTransport, name: &str) {
+    if let Some(app) = LedgerApp::from_app_name(name) {
+        return open(ledger_api, app);
+    }
+    if let Err(e) = open_app_by_name(ledger_api, name) {
+        error!("Error opening {} app: {}", name, e);
+    }
+}
+
+// Install these apps, an app along with the apps it calls into, for instance the Exchange app
+// along with the currency apps it needs to perform swaps.
+fn install_with_plugins(ledger_api: &dyn LedgerTransport, apps: &[String]) {
+    let confirmations: Vec<_> = apps
+        .iter()
+        .flat_map(|app| install_confirmations(app))
+        .collect();
+    announce_confirmations(&confirmations);
+    install_apps(ledger_api, apps);
+}
+
+// Update these apps, an app along with the apps it calls into, since it may fail with outdated
+// ones. Those which are missing are installed.
+fn update_with_plugins(ledger_api: &dyn LedgerTransport, apps: &[String]) {
+    if let Some((app, plugins)) = apps.split_first() {
+        update_apps(ledger_api, std::slice::from_ref(app), false);
+        update_apps(ledger_api, plugins, true);
+    }
+}
+
+// Use the registry of the known apps shipped with the library, overridden by the JSON file set in
+// LEDGER_REGISTRY if any.
+fn load_registry() {
+    let overrides = env::var("LEDGER_REGISTRY").ok().map(PathBuf::from);
+    match AppRegistry::load(overrides.as_deref()) {
+        Ok(r) => set_registry(r),
+        Err(e) => error!("Error loading the app registry: {}", e),
+    }
+}
+
+// Install these apps one after the other, skipping those already installed.
+fn install_apps(ledger_api: &dyn LedgerTransport, apps: &[String]) {
+    let strategy = install_strategy(ledger_api);
+    for name in apps {
+        println!(
+            "Installing the {} app. You may have to allow the Ledger manager on your device.",
+            name
+        );
+        match install_app(ledger_api, InstallOptions::by_name(name).strategy(strategy)) {
+            Ok(()) => println!("Successfully installed the {} app.", name),
+            Err(InstallErr::AlreadyInstalled) => println!("{} app already installed.", name),
+            Err(InstallErr::AppNotFound) => error!("Could not get info about {} app.", name),
+            Err(InstallErr::MissingDependency { name: dep, chain }) => {
+                error!(
+                    "{} app requires the '{}' app to be installed first ({}).",
+                    name,
+                    dep,
+                    chain.join(" -> ")
+                )
+            }
+            Err(
+                e @ InstallErr::NotEnoughSpace {
+                    required,
+                    available,
+                },
+            ) => error!(
+                "Error installing {} app: {}{}",
+                name,
+                e,
+                free_space_hint(ledger_api, required, available)
+            ),
+            Err(e @ InstallErr::NotOnboarded) => error!("Error installing {} app: {}", name, e),
+            Err(InstallErr::Any(e)) => error!("Error installing {} app: {}.", name, e),
+        }
+    }
+}
+
+// Update these apps one after the other, skipping those already up to date. Set `install_missing`
+// to install those which aren't installed yet rather than failing.
+fn update_apps(ledger_api: &dyn LedgerTransport, apps: &[String], install_missing: bool) {
+    for name in apps {
+        println!(
+            "Updating the {} app. You may have to allow the Ledger manager on your device.",
+            name
+        );
+        match update_app(ledger_api, InstallOptions::by_name(name)) {
+            Ok(()) => println!("Successfully updated the {} app.", name),
+            Err(UpdateErr::AlreadyLatest) => {
+                println!("{} app is already at the latest version.", name)
+            }
+            Err(UpdateErr::NotInstalled) if install_missing => {
+                install_apps(ledger_api, std::slice::from_ref(name))
+            }
+            Err(UpdateErr::NotInstalled) => {
+                error!(
+                    "{} app isn't installed. Use the install command instead.",
+                    name
+                )
+            }
+            Err(UpdateErr::AppNotFound) => error!("Could not get info about {} app.", name),
+            Err(
+                e @ UpdateErr::NotEnoughSpace {
+                    required,
+                    available,
+                },
+            ) => error!(
+                "Error updating {} app: {}{}",
+                name,
+                e,
+                free_space_hint(ledger_api, required, available)
+            ),
+            Err(e @ UpdateErr::Downgrade { .. }) => error!("Error updating {} app: {}", name, e),
+            Err(UpdateErr::Any(e)) => error!("Error updating {} app: {}.", name, e),
+        }
+    }
+}
+
+// Ask the user a yes/no question on the terminal. Defaults to no.
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    if io::stdout().flush().is_err() {
+        return false;
+    }
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// Tell the user upfront which operations they may have to confirm on their device during a batch
+// run, and in which order, so they can stay by their device.
+fn announce_confirmations(steps: &[String]) {
+    if steps.is_empty() {
+        return;
+    }
+    println!(
+        "You may have to confirm up to {} operation(s) on your device, in this order:",
+        steps.len()
+    );
+    for (i, step) in steps.iter().enumerate() {
+        println!("  {}. {}", i + 1, step);
+    }
+}
+
+// The operations to confirm on the device to install this app: every install lists the installed
+// apps first.
+fn install_confirmations(name: &str) -> [String; 2] {
+    [
+        format!(
+            "allow listing the installed apps (before installing {})",
+            name
+        ),
+        format!("allow the Ledger manager to install {}", name),
+    ]
+}
+
+// Suggest which apps to uninstall to free the storage missing on the device for this much to fit,
+// the largest first and leaving out the protected ones. Empty if it can't be told.
+fn free_space_hint(ledger_api: &dyn LedgerTransport, required: u64, available: u64) -> String {
+    let missing = required.saturating_sub(available);
+    // The device reported it's full though the estimate fits: how much to free can't be told.
+    if missing == 0 {
+        return String::new();
+    }
+    let (device_info, installed) = match (
+        DeviceInfo::new(ledger_api),
+        list_installed_apps_raw(ledger_api),
+    ) {
+        (Ok(device_info), Ok(installed)) => (device_info, installed),
+        _ => return String::new(),
+    };
+    match apps_to_free(
+        installed,
+        device_info.block_size(),
+        missing,
+        &protected_apps(),
+    ) {
+        Some(names) => format!(
+            "\nUninstalling {} would free enough storage.",
+            names.join(", ")
+        ),
+        None => String::new(),
+    }
+}
+
+// The names of the fewest of these installed apps, the largest first, to uninstall to free this
+// much storage, leaving out the protected ones. `None` if they can't free enough.
+fn apps_to_free(
+    mut installed: Vec<InstalledApp>,
+    block_size: u64,
+    missing: u64,
+    protected: &[String],
+) -> Option<Vec<String>> {
+    installed.retain(|app| !protected.iter().any(|p| p.eq_ignore_ascii_case(&app.name)));
+    installed.sort_by_key(|app| std::cmp::Reverse(app.blocks));
+    let mut freed = 0;
+    let mut names = Vec::new();
+    for app in installed {
+        if freed >= missing {
+            break;
+        }
+        freed += u64::from(app.blocks) * block_size;
+        names.push(app.name);
+    }
+    (freed >= missing).then_some(names)
+}
+
+// The names of the apps which must never be uninstalled, as set in LEDGER_PROTECTED_APPS.
+fn protected_apps() -> Vec<String> {
+    env::var("LEDGER_PROTECTED_APPS")
+        .map(|names| {
+            names
+                .split(',')
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn uninstall(ledger_api: &dyn LedgerTransport, app: LedgerApp) {
+    uninstall_named(ledger_api, &String::from_utf8_lossy(app.open_name()));
+}
+
+// Uninstall the app with this name. It may be any app of the catalog.
+fn uninstall_named(ledger_api: &dyn LedgerTransport, name: &str) {
+    if protected_apps()
+        .iter()
+        .any(|p| p.to_lowercase() == name.to_lowercase())
+    {
+        error!(
+            "The {} app is protected. Remove it from LEDGER_PROTECTED_APPS to uninstall it.",
+            name
+        );
+    }
+    for caller in registry().callers(name) {
+        println!(
+            "Warning: the {} app, if installed, calls into the {} app. It won't fully work without it.",
+            caller.name, name
+        );
+    }
+    println!("You may have to allow on your device 1) listing installed apps 2) the Ledger manager to uninstall the app.");
+    match when_unlocked(
+        ledger_api,
+        || delete_app_by_name(ledger_api, name),
+        UninstallErr::is_locked_device,
+        UninstallErr::app_open,
+    ) {
+        Ok(freed) => println!(
+            "Successfully uninstalled the {} app, freeing {} bytes.",
+            name, freed
+        ),
+        Err(UninstallErr::NotInstalled) => error!("{} app isn't installed.", name),
+        Err(UninstallErr::AppNotFound) => error!("Could not get info about {} app.", name),
+        Err(e @ UninstallErr::RequiredBy { .. }) => error!("{}", e),
+        Err(UninstallErr::Any(e)) => error!("Error uninstalling {} app: {}.", name, e),
+    }
+}
+
+fn uninstall_all_except(ledger_api: &dyn LedgerTransport, keep: &[String]) {
+    println!("Querying installed applications from your Ledger. You might have to confirm on your device.");
+    let plan = match plan_uninstall_all_except(ledger_api, keep, &protected_apps()) {
+        Ok(p) => p,
+        Err(e) => error!("Error computing the apps to uninstall: {}.", e),
+    };
+
+    if !plan.kept.is_empty() {
+        println!("Apps which will be kept: {}.", plan.kept.join(", "));
+    }
+    if !plan.protected.is_empty() {
+        println!(
+            "Of which kept because they are protected by LEDGER_PROTECTED_APPS: {}.",
+            plan.protected.join(", ")
+        );
+    }
+    if !plan.unknown.is_empty() {
+        println!(
+            "Apps unknown to the Ledger API, which can't be uninstalled: {}.",
+            plan.unknown.join(", ")
+        );
+    }
+    if plan.to_remove.is_empty() {
+        println!("No app to uninstall.");
+        return;
+    }
+    println!("Apps which will be uninstalled, in this order:");
+    for app in &plan.to_remove {
+        println!("  - {} {}", app.version_name, app.version);
+    }
+    if !confirm("Uninstall those apps?") {
+        error!("Aborted.");
+    }
+
+    let steps: Vec<_> = plan
+        .to_remove
+        .iter()
+        .map(|app| format!("allow the Ledger manager to uninstall {}", app.version_name))
+        .collect();
+    announce_confirmations(&steps);
+    if let Err(e) = uninstall_apps(ledger_api, &plan.to_remove) {
+        error!("Error uninstalling the apps: {}", e);
+    }
+    println!("Successfully uninstalled {} app(s).", plan.to_remove.len());
+}
+
+// Print the response to a raw APDU.
+fn print_apdu_response(res: Result<(Vec<u8>, u16), Error>) {
+    match res {
+        Ok((data, status)) => {
+            println!("Response data: {}", hex::encode(data));
+            println!("Status word: {:#06x}", status);
+        }
+        Err(e) => error!("Error sending APDU: {}.", e),
+    }
+}
+
+// Print the name of the device, or rename it if a new name is given.
+fn rename(ledger_api: &dyn LedgerTransport, name: Option<&str>) {
+    let device_info = device_info(ledger_api);
+    if !device_info.model().is_some_and(|m| m.has_bluetooth()) {
+        error!("Only the models with Bluetooth have a name.");
+    }
+    let name = match name {
+        Some(name) => name.trim(),
+        None => {
+            match get_device_name(ledger_api) {
+                Ok(name) => println!("Your device is named '{}'.", name),
+                Err(e) => error!("Error getting the name of the device: {}", e),
+            }
+            return;
+        }
+    };
+    if name.is_empty() || name.len() > MAX_DEVICE_NAME_LENGTH {
+        error!(
+            "The name of the device must be 1 to {} characters long.",
+            MAX_DEVICE_NAME_LENGTH
+        );
+    }
+    println!("Confirm the new name on your device.");
+    match set_device_name(ledger_api, name) {
+        Ok(()) => println!("Your device is now named '{}'.", name),
+        Err(e) => error!("Error renaming the device: {}", e),
+    }
+}
+
+// List the language packs available for the device, or switch it to this language.
+fn switch_language(ledger_api: &dyn LedgerTransport, language: Option<&str>) {
+    let device_info = device_info(ledger_api);
+    let language = match language {
+        Some(language) => language.trim(),
+        None => {
+            let mut packages = match language_packages(&device_info) {
+                Ok(p) => p,
+                Err(e) => error!("Error querying the language packs: {}.", e),
+            };
+            packages.sort_by(|a, b| a.language.cmp(&b.language));
+            let mut table = Table::new(&["LANGUAGE", "VERSION", "SIZE"]);
+            for package in packages {
+                table.row(vec![
+                    package.language,
+                    package.version,
+                    format!("{} bytes", package.bytes),
+                ]);
+            }
+            if table.is_empty() {
+                println!("No language pack available for your device, it only displays English.");
+            } else {
+                table.print();
+            }
+            return;
+        }
+    };
+
+    if language.eq_ignore_ascii_case(DEFAULT_LANGUAGE) {
+        if let Err(e) = uninstall_languages(ledger_api) {
+            error!("Error removing the language pack: {}", e);
+        }
+        println!("Removed the language pack. Your device now displays English.");
+        return;
+    }
+    let package = match language_package(&device_info, language) {
+        Ok(Some(p)) => p,
+        Ok(None) => error!(
+            "No {} language pack available for your device. Run the language command without LEDGER_LANGUAGE to list them.",
+            language
+        ),
+        Err(e) => error!("Error querying the language packs: {}.", e),
+    };
+    let mut progress = Progress::new("Installing the language pack");
+    let res = install_language(ledger_api, &package, |f| progress.set(f));
+    progress.finish();
+    if let Err(e) = res {
+        error!("Error installing the language pack: {}", e);
+    }
+    println!(
+        "Installed the {} language pack (version {}).",
+        package.language, package.version
+    );
+}
+
+// Show this picture on the lock screen of the device, or restore the default lock screen.
+fn lock_screen(ledger_api: &dyn LedgerTransport, picture: Option<&Path>) {
+    let device_info = device_info(ledger_api);
+    let model = match device_info.model() {
+        Some(m) if m.has_custom_lock_screen() => m,
+        _ => error!("Only the Ledger Stax and Flex have a customizable lock screen."),
+    };
+    let picture = match picture {
+        Some(picture) => picture,
+        None => {
+            if let Err(e) = clear_lock_screen(ledger_api) {
+                error!("Error restoring the default lock screen: {}", e);
+            }
+            println!("Restored the default lock screen.");
+            return;
+        }
+    };
+
+    // Crop the picture to the proportions of the screen, then scale it down to its size and
+    // convert it to the shades of gray the device can display.
+    let (width, height) = match model.screen_size() {
+        Some(size) => size,
+        None => error!("Unknown screen size for the {}.", model),
+    };
+    let image = match image::open(picture) {
+        Ok(i) => i,
+        Err(e) => error!("Error reading picture '{}': {}.", picture.display(), e),
+    };
+    let pixels: Vec<u8> = image
+        .resize_to_fill(width, height, image::imageops::FilterType::Lanczos3)
+        .to_luma8()
+        .into_raw()
+        .into_iter()
+        .map(|luma| (luma as u16 * LOCK_SCREEN_GRAY_LEVELS as u16 / 256) as u8)
+        .collect();
+
+    println!("Loading the picture. Confirm it on your device once loaded.");
+    let mut progress = Progress::new("Loading the picture");
+    let res = set_lock_screen(ledger_api, model, &pixels, |f| progress.set(f));
+    progress.finish();
+    if let Err(e) = res {
+        error!("Error setting the lock screen: {}", e);
+    }
+    println!("Your device now shows this picture while locked.");
+}
+
+// A human readable description of a connected Ledger device.
+fn describe_device(device: &LedgerDeviceHandle) -> String {
+    let mut desc = format!(
+        "{} (product id: {:#06x}, serial: {})",
+        device
+            .model
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| "Unknown Ledger device".to_string()),
+        device.product_id,
+        device.serial.as_deref().unwrap_or("unknown"),
+    );
+    // The device may well be locked, in which case we just don't know its version.
+    if let Ok(info) = TransportHid::connect(device, polling_config())
+        .and_then(|transport| DeviceInfo::new(&transport))
+    {
+        desc += &format!(", firmware version: {}", info.version);
+    }
+    if device.is_bootloader() {
+        desc += ", in bootloader mode";
+    }
+    desc
+}
+
+// Run the given command as a separate process, so a failure doesn't stop the watch.
+fn run_action(action: &str) {
+    let exe = match env::current_exe() {
+        Ok(e) => e,
+        Err(e) => {
+            eprintln!("Error getting the path to the current executable: {}.", e);
+            return;
+        }
+    };
+    println!("Running '{}'.", action);
+    match process::Command::new(exe)
+        .env("LEDGER_COMMAND", action)
+        .status()
+    {
+        Ok(status) if status.success() => println!("'{}' succeeded.", action),
+        Ok(status) => eprintln!("'{}' failed ({}).", action, status),
+        Err(e) => eprintln!("Error running '{}': {}.", action, e),
+    }
+}
+
+// The device served by the bridge, shared by its clients. It's connected anew after a failed
+// exchange, as it re-enumerates when it restarts or opens or quits an app.
+struct BridgedDevice(Mutex<Option<Box<dyn LedgerTransport + Send>>>);
+
+impl LedgerTransport for BridgedDevice {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        // A panic while holding the lock doesn't leave the connection in an inconsistent state, at
+        // worst the channel is desynchronized as after any interrupted exchange.
+        let mut device = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        if device.is_none() {
+            *device = connect(transport_config()).ok();
+        }
+        let res = match device.as_ref() {
+            Some(d) => d.exchange_apdu(command),
+            None => Err(Error::Transport(LedgerHIDError::DeviceNotFound)),
+        };
+        if res.is_err() {
+            *device = None;
+        }
+        res
+    }
+
+    fn describe(&self) -> String {
+        match self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_deref()
+        {
+            Some(d) => d.describe(),
+            None => "not connected".to_string(),
+        }
+    }
+}
+
+// Serve the device to the clients of the bridge connecting on this address (or Unix socket), until
+// interrupted. Every client is served in its own thread, as a client may connect again before
+// closing its previous connection.
+fn bridge(listen: &str) {
+    let device = BridgedDevice(Mutex::new(None));
+    println!(
+        "Serving your device on {}. Anyone able to connect to it can manage your device.",
+        listen
+    );
+    thread::scope(|s| {
+        #[cfg(unix)]
+        if listen.starts_with('/') {
+            let listener = match UnixListener::bind(listen) {
+                Ok(l) => l,
+                Err(e) => error!("Error listening on {}: {}", listen, e),
+            };
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let device = &device;
+                        s.spawn(move || serve_bridge_client(stream, "Client", device));
+                    }
+                    Err(e) => eprintln!("Error accepting a connection: {}", e),
+                }
+            }
+            return;
+        }
+        let listener = match TcpListener::bind(listen) {
+            Ok(l) => l,
+            Err(e) => error!("Error listening on {}: {}", listen, e),
+        };
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let peer = match stream.peer_addr() {
+                        Ok(addr) => format!("Client {}", addr),
+                        Err(_) => "Client".to_string(),
+                    };
+                    let device = &device;
+                    s.spawn(move || serve_bridge_client(stream, &peer, device));
+                }
+                Err(e) => eprintln!("Error accepting a connection: {}", e),
+            }
+        }
+    });
+}
+
+fn serve_bridge_client(stream: impl io::Read + Write, peer: &str, device: &BridgedDevice) {
+    println!("{} connected.", peer);
+    match serve_bridge(stream, device) {
+        Ok(()) => println!("{} disconnected.", peer),
+        Err(e) => eprintln!("{} disconnected: {}", peer, e),
+    }
+}
+
+// Poll the connected HID devices and print when a Ledger is connected or disconnected, running the
+// given command every time one is connected.
+fn watch(action: Option<&str>) {
+    let mut hid_api = match HidApi::new() {
+        Ok(a) => a,
+        Err(e) => error!("Error initializing HDI api: {}.", e),
+    };
+    println!("Watching for Ledger devices. Press Ctrl-C to stop.");
+
+    let mut known: Vec<(std::ffi::CString, String)> = Vec::new();
+    loop {
+        if let Err(e) = hid_api.refresh_devices() {
+            eprintln!("Error listing HID devices: {}.", e);
+        }
+        let connected = enumerate_devices(&hid_api);
+
+        for (path, desc) in &known {
+            if !connected.iter().any(|d| d.path == *path) {
+                println!("Disconnected: {}.", desc);
+            }
+        }
+        known.retain(|(path, _)| connected.iter().any(|d| d.path == *path));
+
+        let new_devices: Vec<_> = connected
+            .into_iter()
+            .filter(|d| !known.iter().any(|(path, _)| *path == d.path))
+            .collect();
+        for device in new_devices {
+            let desc = describe_device(&device);
+            println!("Connected: {}.", desc);
+            known.push((device.path, desc));
+            if let Some(action) = action {
+                run_action(action);
+            }
+        }
+
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+// The device to watch releases for. Either the model configured through LEDGER_TARGET_ID (decimal or
+// 0x-prefixed hex) and LEDGER_FIRMWARE_VERSION, or the connected device.
+fn release_device() -> DeviceInfo {
+    match (env::var("LEDGER_TARGET_ID"), env::var("LEDGER_FIRMWARE_VERSION")) {
+        (Ok(target_id), Ok(version)) => {
+            let target_id = match target_id.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => target_id.parse(),
+            };
+            match target_id {
+                Ok(id) => DeviceInfo::for_model(id, version),
+                Err(e) => error!("Invalid LEDGER_TARGET_ID: {}.", e),
+            }
+        }
+        (Err(_), Err(_)) => device_info(&ledger_api()),
+        _ => error!("Both LEDGER_TARGET_ID and LEDGER_FIRMWARE_VERSION must be set to configure the device model."),
+    }
+}
+
+// Query the latest firmware and app versions available for this device, by name.
+fn releases(device_info: &DeviceInfo) -> Result<BTreeMap<String, String>, String> {
+    let mut releases = BTreeMap::new();
+    let apps = available_apps(device_info)
+        .map_err(|e| format!("Error querying the apps catalog: {}", e))?;
+    for app in apps {
+        releases.insert(app.name, app.version);
+    }
+    let firmware = latest_firmware(device_info)
+        .map_err(|e| format!("Error querying the latest firmware: {}", e))?;
+    releases.insert(
+        "firmware".to_string(),
+        firmware.unwrap_or_else(|| device_info.version.clone()),
+    );
+    Ok(releases)
+}
+
+// Download what's needed from Ledger's API to later provision devices of a model with these apps
+// without access to it, into a package directory to set in LEDGER_PACKAGE_DIR then.
+fn download(dir: &Path, apps: &[String]) {
+    let device_info = release_device();
+    println!(
+        "Downloading the catalog for the {} running firmware {} into '{}'.",
+        device_info
+            .model()
+            .map(|model| model.name().to_string())
+            .unwrap_or_else(|| format!("device {:#x}", device_info.target_id)),
+        device_info.version,
+        dir.display()
+    );
+    let manifest = match download_package(&device_info, apps, dir) {
+        Ok(m) => m,
+        Err(e) => error!("Error downloading the package: {}.", e),
+    };
+    for app in &manifest.apps {
+        println!("{} {} ({})", app.name, app.version, app.hash);
+    }
+    println!(
+        "Downloaded {} file(s), listed with their hashes in '{}'. Installing apps and firmwares will still need a connection to Ledger's HSM.",
+        manifest.files.len(),
+        dir.join(PACKAGE_MANIFEST).display()
+    );
+}
+
+// Poll the catalog for new firmware and app versions for a device model, and print the changes. Set
+// LEDGER_FILE to persist the versions seen across runs, and LEDGER_ONCE to only check once (for
+// instance from cron). The interval between checks is set in seconds in LEDGER_POLL_INTERVAL.
+fn watch_releases() {
+    let device_info = release_device();
+    let interval = match env::var("LEDGER_POLL_INTERVAL").map(|i| i.parse::<u64>()) {
+        Ok(Ok(i)) => Duration::from_secs(i),
+        Ok(Err(e)) => error!("Invalid LEDGER_POLL_INTERVAL: {}.", e),
+        Err(_) => Duration::from_secs(3600),
+    };
+    let state_file = env::var("LEDGER_FILE").ok().map(PathBuf::from);
+    let mut known: Option<BTreeMap<String, String>> = state_file
+        .as_ref()
+        .and_then(|f| fs::read_to_string(f).ok())
+        .and_then(|content| serde_json::from_str(&content).ok());
+    println!(
+        "Watching releases for device {:#x} running firmware {}.",
+        device_info.target_id, device_info.version
+    );
+
+    loop {
+        match releases(&device_info) {
+            Ok(latest) => {
+                match known {
+                    Some(ref known) => {
+                        for (name, version) in &latest {
+                            match known.get(name) {
+                                None => println!("New: {} {}.", name, version),
+                                Some(v) if v != version => {
+                                    println!("Updated: {} {} -> {}.", name, v, version)
+                                }
+                                _ => {}
+                            }
+                        }
+                        for name in known.keys().filter(|n| !latest.contains_key(*n)) {
+                            println!("Removed: {}.", name);
+                        }
+                    }
+                    None => {
+                        let mut table = Table::new(&["NAME", "VERSION"]);
+                        for (name, version) in &latest {
+                            table.row(vec![name.clone(), version.clone()]);
+                        }
+                        // Don't page, not to block the next checks.
+                        print!("{}", table);
+                    }
+                }
+                if let Some(ref path) = state_file {
+                    match serde_json::to_string_pretty(&latest) {
+                        Ok(json) => {
+                            if let Err(e) = fs::write(path, json) {
+                                eprintln!("Error writing to '{}': {}.", path.display(), e);
+                            }
+                        }
+                        Err(e) => eprintln!("Error serializing the releases: {}.", e),
+                    }
+                }
+                known = Some(latest);
+            }
+            Err(e) => eprintln!("{}.", e),
+        }
+
+        if env::var("LEDGER_ONCE").is_ok() {
+            return;
+        }
+        thread::sleep(interval);
+    }
+}
+
+// Walk the user through the first steps with a brand new device: make sure it's genuine, check its
+// firmware and install a set of apps.
+fn setup(ledger_api: &dyn LedgerTransport, apps: &[String]) {
+    const STEPS: usize = 3;
+
+    let mut confirmations = vec!["allow the genuine check".to_string()];
+    for app in apps {
+        confirmations.extend(install_confirmations(app));
+    }
+    announce_confirmations(&confirmations);
+
+    println!("Step 1/{}: checking your Ledger is genuine.", STEPS);
+    perform_genuine_check(ledger_api);
+
+    println!("Step 2/{}: checking the firmware of your Ledger.", STEPS);
+    let info = match get_firmware_info(ledger_api) {
+        Ok(i) => i,
+        Err(e) => error!("Error querying the firmware of your Ledger: {}.", e),
+    };
+    if let Some(version) = info.se_version {
+        println!("Your Ledger runs firmware version {}.", version);
+    }
+    match info.latest_osu {
+        Some(latest) => println!("A firmware update ({}) is available. Update it with the updatefirm command before going further.", latest),
+        None => println!("Your firmware is up to date."),
+    }
+    if !confirm("Continue?") {
+        error!("Aborted.");
+    }
+
+    println!("Step 3/{}: installing the apps.", STEPS);
+    if apps.is_empty() {
+        println!("No app to install.");
+    } else {
+        println!("The following apps will be installed: {}.", apps.join(", "));
+        if !confirm("Continue?") {
+            error!("Aborted.");
+        }
+        install_apps(ledger_api, apps);
+    }
+
+    println!("All done. Your Ledger is ready to be used.");
+}
+
+/// An installed application as written to an export file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExportedApp {
+    name: String,
+    version: Option<String>,
+}
+
+/// The state of a device as written to an export file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Snapshot {
+    /// The firmware version. Not present in files exported by older versions.
+    #[serde(default)]
+    firmware: Option<String>,
+    apps: Vec<ExportedApp>,
+}
+
+// Query the current state of the device.
+fn device_snapshot(ledger_api: &dyn LedgerTransport) -> Snapshot {
+    let firmware = device_info(ledger_api).version;
+    println!("Querying installed applications from your Ledger. You might have to confirm on your device.");
+    let installed = match list_installed_apps(ledger_api) {
+        Ok(a) => a,
+        Err(e) => error!("Error listing installed applications: {}.", e),
+    };
+    let apps = installed
+        .into_iter()
+        .map(|app| ExportedApp {
+            name: app.name,
+            version: app.version,
+        })
+        .collect();
+    Snapshot {
+        firmware: Some(firmware),
+        apps,
+    }
+}
+
+// Read a device state from an export file. Files exported by older versions only contain the list
+// of apps.
+fn read_snapshot(path: &Path) -> Snapshot {
+    let content = match fs::read_to_string(path) {
+        Ok(c) => c,
+        Err(e) => error!("Error reading '{}': {}.", path.display(), e),
+    };
+    if let Ok(apps) = serde_json::from_str::<Vec<ExportedApp>>(&content) {
+        return Snapshot {
+            firmware: None,
+            apps,
+        };
+    }
+    match serde_json::from_str(&content) {
+        Ok(s) => s,
+        Err(e) => error!("Invalid apps file '{}': {}.", path.display(), e),
+    }
+}
+
+fn export_apps(ledger_api: &dyn LedgerTransport, path: &Path) {
+    let snapshot = device_snapshot(ledger_api);
+    let json = match serde_json::to_string_pretty(&snapshot) {
+        Ok(j) => j,
+        Err(e) => error!("Error serializing the list of applications: {}.", e),
+    };
+    if let Err(e) = fs::write(path, json) {
+        error!("Error writing to '{}': {}.", path.display(), e);
+    }
+    println!(
+        "Exported {} application(s) to '{}'.",
+        snapshot.apps.len(),
+        path.display()
+    );
+}
+
+// Print the differences in firmware and apps between two device states. Returns whether they
+// differ.
+fn print_differences(a: &Snapshot, b: &Snapshot) -> bool {
+    let mut differ = false;
+    if let (Some(firm_a), Some(firm_b)) = (&a.firmware, &b.firmware) {
+        if firm_a != firm_b {
+            println!("Firmware: {} -> {}.", firm_a, firm_b);
+            differ = true;
+        }
+    }
+
+    let find = |apps: &[ExportedApp], name: &str| {
+        let name = name.to_lowercase();
+        apps.iter()
+            .find(|app| app.name.to_lowercase() == name)
+            .cloned()
+    };
+    let unknown = "unknown version".to_string();
+    for app in &a.apps {
+        match find(&b.apps, &app.name) {
+            None => println!("Only in the first: {}.", app.name),
+            Some(other) if other.version != app.version => println!(
+                "Version of {}: {} -> {}.",
+                app.name,
+                app.version.as_ref().unwrap_or(&unknown),
+                other.version.as_ref().unwrap_or(&unknown)
+            ),
+            Some(_) => continue,
+        }
+        differ = true;
+    }
+    for app in &b.apps {
+        if find(&a.apps, &app.name).is_none() {
+            println!("Only in the second: {}.", app.name);
+            differ = true;
+        }
+    }
+    differ
+}
+
+// Compare a snapshot to another one, or to the connected device.
+fn compare(ledger_api: Option<&dyn LedgerTransport>, first: &Path, second: Option<&Path>) {
+    let a = read_snapshot(first);
+    let b = match (second, ledger_api) {
+        (Some(path), _) => read_snapshot(path),
+        (None, Some(ledger_api)) => device_snapshot(ledger_api),
+        (None, None) => unreachable!("A device is connected when comparing to the device."),
+    };
+    if a.firmware.is_none() || b.firmware.is_none() {
+        println!("Note: the firmware version is not known for both, only comparing apps.");
+    }
+    if print_differences(&a, &b) {
+        process::exit(1);
+    }
+    println!("No difference.");
+}
+
+fn restore_apps(ledger_api: &dyn LedgerTransport, path: &Path) {
+    let apps = read_snapshot(path).apps;
+
+    let strategy = install_strategy(ledger_api);
+    let steps: Vec<_> = apps
+        .iter()
+        .flat_map(|app| install_confirmations(&app.name))
+        .collect();
+    announce_confirmations(&steps);
+    let (mut installed, mut skipped, mut failed) = (0, 0, 0);
+    for exported in apps {
+        // We can only install the latest version from the catalog.
+        if let Some(version) = &exported.version {
+            println!(
+                "Installing {} (exported version: {}, the latest version will be installed).",
+                exported.name, version
+            );
+        } else {
+            println!("Installing {}.", exported.name);
+        }
+        match install_app(
+            ledger_api,
+            InstallOptions::by_name(&exported.name).strategy(strategy),
+        ) {
+            Ok(()) => installed += 1,
+            Err(InstallErr::AlreadyInstalled) => {
+                println!("{} is already installed.", exported.name);
+                skipped += 1;
+            }
+            Err(InstallErr::AppNotFound) => {
+                eprintln!("Could not get info about {} app.", exported.name);
+                failed += 1;
+            }
+            Err(InstallErr::MissingDependency { name, chain }) => {
+                eprintln!(
+                    "{} app requires the '{}' app to be installed first ({}).",
+                    exported.name,
+                    name,
+                    chain.join(" -> ")
+                );
+                failed += 1;
+            }
+            Err(e @ (InstallErr::NotEnoughSpace { .. } | InstallErr::NotOnboarded)) => {
+                eprintln!("Error installing {} app: {}", exported.name, e);
+                failed += 1;
+            }
+            Err(InstallErr::Any(e)) => {
+                eprintln!("Error installing {} app: {}.", exported.name, e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "Restore done. Installed: {}, skipped: {}, failed: {}.",
+        installed, skipped, failed
+    );
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+// The man page, generated at build time from the command definitions.
+const MAN_PAGE: &str = include_str!(concat!(env!("OUT_DIR"), "/ledger_manager_cli.1"));
+
+// Print the help for all the commands and their environment variables.
+fn print_long_help() {
+    fn print_env(vars: &[EnvDef], indent: &str) {
+        for var in vars {
+            println!("{}{}", indent, var.name);
+            println!("{}    {}", indent, var.about);
+        }
+    }
+
+    println!("Usage: LEDGER_COMMAND=<command> [VARIABLE=value ...] ledger_manager_cli");
+    println!();
+    println!("Commands:");
+    for command in COMMANDS {
+        println!("  {}", command.name);
+        println!("      {}", command.about);
+        print_env(command.env, "      ");
+    }
+    println!();
+    println!("Environment:");
+    print_env(COMMON_ENV, "  ");
+}
+
+fn main() {
+    match env::args().nth(1).as_deref() {
+        Some("--help-all") => {
+            print_long_help();
+            return;
+        }
+        Some("--man") => {
+            print!("{}", MAN_PAGE);
+            return;
+        }
+        _ => {}
+    }
+
+    load_registry();
+    load_proxy();
+    load_tls();
+    load_retry_policy();
+    load_api_url();
+    load_provider();
+    load_cache();
+    load_package_dir();
+    let command = if let Some(cmd) = Command::get() {
+        cmd
+    } else {
+        let names: Vec<_> = COMMANDS.iter().map(|c| c.name).collect();
+        error!("Invalid or no command specified. The command must be passed through the LEDGER_COMMAND env var, one of: {}. Set LEDGER_TESTNET to use the Bitcoin testnet app instead where applicable. Run with --help-all for details.", names.join(", "));
+    };
+
+    // Watching doesn't need a device to be connected.
+    if let Command::Watch(action) = &command {
+        watch(action.as_deref());
+        return;
+    }
+    // Neither does watching releases if the device model is configured.
+    if let Command::WatchReleases = command {
+        watch_releases();
+        return;
+    }
+    // Nor does downloading a package if the device model is configured.
+    if let Command::Download(dir, apps) = &command {
+        download(dir, apps);
+        return;
+    }
+    // Nor does comparing two snapshots.
+    if let Command::Compare(first, Some(second)) = &command {
+        compare(None, first, Some(second));
+        return;
+    }
+    // The bridge connects to the device as its clients need it.
+    if let Command::Bridge(listen) = &command {
+        bridge(listen);
+        return;
+    }
+
+    select_hsm();
+    let ledger_api = ledger_api();
+    // A device left in the middle of a firmware update can't be managed until it's completed.
+    if !matches!(
+        command,
+        Command::GetInfo
+            | Command::UpdateFirmware
+            | Command::ResumeFirmware
+            | Command::CheckFirmware
+            | Command::Apdu(_)
+    ) {
+        check_firmware_not_interrupted(&ledger_api);
+    }
+    let ledger_api = match command {
+        Command::Apdu(_) => ledger_api,
+        _ => ensure_dashboard(ledger_api),
+    };
+    match command {
+        Command::GetInfo => {
+            print_ledger_info(&ledger_api);
+        }
+        Command::GenuineCheck => {
+            perform_genuine_check(&ledger_api);
+        }
+        Command::InstallMainApp => {
+            install_bitcoin(&ledger_api, false);
+        }
+        Command::InstallTestApp => {
+            install_bitcoin(&ledger_api, true);
+        }
+        Command::OpenMainApp => {
+            open_bitcoin(&ledger_api, false);
+        }
+        Command::OpenTestApp => {
+            open_bitcoin(&ledger_api, true);
+        }
+        Command::UpdateMainApp => {
+            update_bitcoin(&ledger_api, false);
+        }
+        Command::UpdateTestApp => {
             update_bitcoin(&ledger_api, true);
         }
         Command::InstallSolana => {
-            install_solana(&ledger_api);
+            install(&ledger_api, LedgerApp::Solana);
         }
         Command::UpdateSolana => {
-            update_solana(&ledger_api);
+            update(&ledger_api, LedgerApp::Solana);
         }
         Command::OpenSolana => {
-            open_solana(&ledger_api);
+            open(&ledger_api, LedgerApp::Solana);
+        }
+        Command::InstallNamedApp(name) => {
+            install(&ledger_api, InstallOptions::by_name(name));
+        }
+        Command::UpdateNamedApp(name) => {
+            update(&ledger_api, InstallOptions::by_name(name));
+        }
+        Command::OpenNamedApp(name) => {
+            open_named(&ledger_api, &name);
+        }
+        Command::UninstallNamedApp(name) => {
+            uninstall_named(&ledger_api, &name);
+        }
+        Command::InstallEthereum => {
+            install(&ledger_api, LedgerApp::Ethereum);
+        }
+        Command::UpdateEthereum => {
+            update(&ledger_api, LedgerApp::Ethereum);
+        }
+        Command::OpenEthereum => {
+            open(&ledger_api, LedgerApp::Ethereum);
+        }
+        Command::InstallExchange => {
+            install(&ledger_api, LedgerApp::Exchange);
+        }
+        Command::UpdateExchange => {
+            update(&ledger_api, LedgerApp::Exchange);
         }
         Command::UpdateFirmware => {
-            unimplemented!()
+            update_firmware(&ledger_api, false);
+        }
+        Command::ResumeFirmware => {
+            update_firmware(&ledger_api, true);
+        }
+        Command::CheckFirmware => {
+            check_firmware(&ledger_api);
+        }
+        Command::UninstallMainApp => {
+            uninstall(&ledger_api, LedgerApp::Bitcoin);
+        }
+        Command::UninstallTestApp => {
+            uninstall(&ledger_api, LedgerApp::BitcoinTest);
+        }
+        Command::UninstallSolana => {
+            uninstall(&ledger_api, LedgerApp::Solana);
         }
+        Command::UninstallEthereum => {
+            uninstall(&ledger_api, LedgerApp::Ethereum);
+        }
+        Command::UninstallExchange => {
+            uninstall(&ledger_api, LedgerApp::Exchange);
+        }
+        Command::UninstallAllExcept(keep) => {
+            uninstall_all_except(&ledger_api, &keep);
+        }
+        Command::Apdu(apdu) => {
+            print_apdu_response(send_raw_apdu(&ledger_api, &apdu));
+        }
+        Command::Rename(name) => {
+            rename(&ledger_api, name.as_deref());
+        }
+        Command::Language(language) => {
+            switch_language(&ledger_api, language.as_deref());
+        }
+        Command::LockScreen(picture) => {
+            lock_screen(&ledger_api, picture.as_deref());
+        }
+        Command::Setup(apps) => {
+            setup(&ledger_api, &apps);
+        }
+        Command::ListAvailable => {
+            list_available(&ledger_api);
+        }
+        Command::Watch(_) | Command::WatchReleases | Command::Bridge(_) | Command::Download(..) => {
+            unreachable!("Handled above.")
+        }
+        Command::ExportApps(path) => {
+            export_apps(&ledger_api, &path);
+        }
+        Command::RestoreApps(path) => {
+            restore_apps(&ledger_api, &path);
+        }
+        Command::Compare(first, second) => {
+            compare(Some(&ledger_api), &first, second.as_deref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str, blocks: u16) -> InstalledApp {
+        InstalledApp {
+            name: name.to_string(),
+            hash: Vec::new(),
+            hash_code_data: Vec::new(),
+            blocks,
+            flags: 0,
+        }
+    }
+
+    #[test]
+    fn picks_largest_unprotected_apps_to_free() {
+        let installed = vec![app("Small", 1), app("Large", 4), app("Protected", 8)];
+        let protected = ["protected".to_string()];
+        assert_eq!(
+            apps_to_free(installed.clone(), 1024, 2048, &protected),
+            Some(vec!["Large".to_string()])
+        );
+        assert_eq!(
+            apps_to_free(installed.clone(), 1024, 5 * 1024, &protected),
+            Some(vec!["Large".to_string(), "Small".to_string()])
+        );
+        assert_eq!(apps_to_free(installed, 1024, 6 * 1024, &protected), None);
     }
 }
@@ -0,0 +1,174 @@
+//! Printing of the listings produced by the commands, as tables laid out for the terminal and paged
+//! when they don't fit on the screen. Set LEDGER_NO_PAGER to never page the output. Long operations
+//! report their progress on a single line of the terminal instead.
+
+use std::{
+    env, fmt,
+    io::{self, IsTerminal, Write},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+// The terminal dimensions to assume when they aren't set by the shell.
+const DEFAULT_WIDTH: usize = 80;
+const DEFAULT_HEIGHT: usize = 24;
+
+// A dimension of the terminal as exported by the shell, if set.
+fn terminal_dimension(var: &str, default: usize) -> usize {
+    env::var(var)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// A table with one column per field, each wide enough for its longest cell.
+pub struct Table {
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(header: &[&str]) -> Self {
+        Self {
+            header: header.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Add a row. It must have as many cells as there are columns.
+    pub fn row(&mut self, cells: Vec<String>) {
+        debug_assert_eq!(cells.len(), self.header.len());
+        self.rows.push(cells);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    // Render the table, truncating the last column for the lines to fit in this width.
+    fn render(&self, width: usize) -> String {
+        let mut widths: Vec<usize> = self.header.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (w, cell) in widths.iter_mut().zip(row) {
+                *w = (*w).max(cell.chars().count());
+            }
+        }
+
+        let mut out = String::new();
+        for row in std::iter::once(&self.header).chain(&self.rows) {
+            let mut line = String::new();
+            for (i, (cell, w)) in row.iter().zip(&widths).enumerate() {
+                if i + 1 == row.len() {
+                    line.push_str(cell);
+                } else {
+                    line.push_str(&format!("{:<w$}  ", cell, w = w));
+                }
+            }
+            if line.chars().count() > width {
+                line = line.chars().take(width.saturating_sub(1)).collect();
+                line.push('…');
+            }
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Print the table to stdout, through a pager if it doesn't fit on the terminal.
+    pub fn print(&self) {
+        if !io::stdout().is_terminal() {
+            print!("{}", self);
+            return;
+        }
+        let text = self.render(terminal_dimension("COLUMNS", DEFAULT_WIDTH));
+        if env::var("LEDGER_NO_PAGER").is_ok()
+            || text.lines().count() < terminal_dimension("LINES", DEFAULT_HEIGHT)
+            || page(&text).is_err()
+        {
+            print!("{}", text);
+        }
+    }
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(usize::MAX))
+    }
+}
+
+// The width of the bar drawn by `Progress::set`.
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// The progress of a long operation, redrawn in place on a line of the terminal. Nothing is drawn
+/// when stdout isn't a terminal.
+pub struct Progress {
+    label: String,
+    // What was last drawn, to avoid redrawing the same line.
+    drawn: Option<String>,
+}
+
+impl Progress {
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            drawn: None,
+        }
+    }
+
+    // Replace the line with this text.
+    fn draw(&mut self, text: String) {
+        if !io::stdout().is_terminal() || self.drawn.as_ref() == Some(&text) {
+            return;
+        }
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "\r\x1b[K{}", text);
+        let _ = stdout.flush();
+        self.drawn = Some(text);
+    }
+
+    /// Draw a bar for this fraction (between 0 and 1) of the operation done.
+    pub fn set(&mut self, fraction: f32) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let filled = (fraction * PROGRESS_BAR_WIDTH as f32) as usize;
+        self.draw(format!(
+            "{} [{}{}] {:>3}%",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(PROGRESS_BAR_WIDTH - filled),
+            (fraction * 100.0) as u32
+        ));
+    }
+
+    /// Show how long the operation has been going on, for operations whose progress is unknown.
+    pub fn elapsed(&mut self, elapsed: Duration) {
+        self.draw(format!("{} {}s", self.label, elapsed.as_secs()));
+    }
+
+    /// Terminate the line, for the next messages to be printed below it.
+    pub fn finish(mut self) {
+        if self.drawn.take().is_some() {
+            println!();
+        }
+    }
+}
+
+// Show this text through the user's pager, `less` by default.
+fn page(text: &str) -> io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    let mut args = pager.split_whitespace();
+    let program = args.next().unwrap_or("less");
+    let mut child = Command::new(program)
+        .args(args)
+        .env(
+            "LESS",
+            env::var("LESS").unwrap_or_else(|_| "FRX".to_string()),
+        )
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // The user may quit the pager before reading everything.
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
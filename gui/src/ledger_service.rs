@@ -6,9 +6,9 @@ use form_urlencoded::Serializer as UrlSerializer;
 use ledger_manager::{
     bitcoin_latest_app, genuine_check, get_latest_apps,
     install_app as ledger_install_app, latest_app,
-    ledger_transport_hidapi::{hidapi::HidApi, TransportNativeHID},
+    prelude::{HidApi, TransportNativeHID},
     list_installed_apps, query_via_websocket, update_app as ledger_update_app, DeviceInfo,
-    InstallErr, LedgerApp, UpdateErr, BASE_SOCKET_URL,
+    socket_url, InstallErr, LedgerApp, UpdateErr,
 };
 use std::fmt::{Display, Formatter};
 use std::time::Duration;
@@ -34,7 +34,7 @@ where
         Ok(apps) => {
             log::debug!("List installed apps:ok");
             msg_callback("List installed apps...", false);
-            for app in apps.into_iter().flatten() {
+            for app in apps.into_iter().filter_map(|a| a.info) {
                 log::debug!("  [{}]", &app.version_name);
                 if app.version_name == "Bitcoin" {
                     mainnet = Version::Installed(app.version);
@@ -57,7 +57,7 @@ where
                 &format!("Error listing installed applications: {}.", e),
                 true,
             );
-            return Err(e);
+            return Err(e.into());
         }
     }
     Ok((model, mainnet, testnet, solana))
@@ -119,7 +119,7 @@ where
         );
         // Now install the app by connecting through their websocket thing to their HSM. Make sure to
         // properly escape the parameters in the request's parameter.
-        let install_ws_url = UrlSerializer::new(format!("{}/install?", BASE_SOCKET_URL))
+        let install_ws_url = UrlSerializer::new(format!("{}/install?", socket_url()))
             .append_pair("targetId", &device_info.target_id.to_string())
             .append_pair("perso", &bitcoin_app.perso)
             .append_pair("deleteKey", &bitcoin_app.delete_key)
@@ -278,6 +278,8 @@ pub enum Model {
     NanoS,
     NanoSP,
     NanoX,
+    Stax,
+    Flex,
     Unknown,
 }
 
@@ -293,6 +295,12 @@ impl Display for Model {
             Model::NanoX => {
                 write!(f, "Nano X")
             }
+            Model::Stax => {
+                write!(f, "Stax")
+            }
+            Model::Flex => {
+                write!(f, "Flex")
+            }
             _ => {
                 write!(f, "")
             }
@@ -313,6 +321,10 @@ impl Model {
                 // i guess `nanox` for the nano x but i don't have device to test
             } else if model == "nanox" {
                 Model::NanoX
+            } else if model == "stax" {
+                Model::Stax
+            } else if model == "flex" {
+                Model::Flex
             } else {
                 Model::Unknown
             }
@@ -575,6 +587,20 @@ impl LedgerService {
                 Err(InstallErr::AppNotFound) => {
                     Self::display_message(&sender, "Could not find Solana app in catalog.", true);
                 }
+                Err(InstallErr::MissingDependency { name, chain }) => {
+                    Self::display_message(
+                        &sender,
+                        &format!(
+                            "Solana app requires the '{}' app to be installed first ({}).",
+                            name,
+                            chain.join(" -> ")
+                        ),
+                        true,
+                    );
+                }
+                Err(e @ (InstallErr::NotEnoughSpace { .. } | InstallErr::NotOnboarded)) => {
+                    Self::display_message(&sender, &format!("Error installing Solana app: {}", e), true);
+                }
                 Err(InstallErr::Any(e)) => {
                     Self::display_message(&sender, &format!("Error installing Solana app: {}.", e), true);
                 }
@@ -606,6 +632,9 @@ impl LedgerService {
                 Err(UpdateErr::AlreadyLatest) => {
                     Self::display_message(&sender, "Solana app is already at the latest version.", true);
                 }
+                Err(e @ (UpdateErr::NotEnoughSpace { .. } | UpdateErr::Downgrade { .. })) => {
+                    Self::display_message(&sender, &format!("Error updating Solana app: {}", e), true);
+                }
                 Err(UpdateErr::Any(e)) => {
                     Self::display_message(&sender, &format!("Error updating Solana app: {}.", e), true);
                 }
@@ -0,0 +1,181 @@
+//! A bridge to manage a device plugged into another machine, for instance a headless signer or a
+//! virtual machine without USB passthrough. The machine with the device serves its APDUs over a TCP
+//! or Unix socket with [`serve_bridge`], and the other one talks to it with [`TransportRemote`].
+//!
+//! The bridge doesn't authenticate nor encrypt anything: whoever can reach its socket can manage
+//! the device. Expose it on a trusted network only, or through an SSH tunnel.
+//
+// Both ways, every message is a frame prefixed with its length as a big-endian u32. A request is a
+// serialized APDU. The answer starts with a tag byte: ANSWER_OK followed by the answer of the device
+// (its data then its status word), or ANSWER_ERROR followed by the reason the bridge couldn't
+// exchange the APDU with the device.
+
+use crate::{apdu_command_from_bytes, Error, LedgerTransport};
+
+use ledger_apdu::{APDUAnswer, APDUCommand};
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+
+/// The port a bridge listens on by default.
+pub const BRIDGE_DEFAULT_PORT: u16 = 9998;
+
+const ANSWER_OK: u8 = 0;
+const ANSWER_ERROR: u8 = 1;
+// No APDU nor answer comes anywhere close.
+const MAX_FRAME_SIZE: usize = 64 * 1024;
+
+// A connection to a bridge.
+trait Stream: Read + Write + Send {}
+impl<T: Read + Write + Send> Stream for T {}
+
+/// A connection to a device served by a bridge on another machine.
+pub struct TransportRemote {
+    stream: Mutex<Box<dyn Stream>>,
+    peer: String,
+}
+
+impl TransportRemote {
+    /// Connect to a bridge listening on this TCP address.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr)?;
+        let peer = stream.peer_addr()?.to_string();
+        Ok(Self {
+            stream: Mutex::new(Box::new(stream)),
+            peer,
+        })
+    }
+
+    /// Connect to a bridge listening on this Unix socket.
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let stream = std::os::unix::net::UnixStream::connect(&path)?;
+        Ok(Self {
+            stream: Mutex::new(Box::new(stream)),
+            peer: path.as_ref().display().to_string(),
+        })
+    }
+}
+
+impl LedgerTransport for TransportRemote {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        let mut stream = self
+            .stream
+            .lock()
+            .map_err(|_| Error::InvalidResponse("poisoned bridge connection"))?;
+        write_frame(&mut *stream, &command.serialize())?;
+        let frame = read_frame(&mut *stream)?.ok_or(Error::Io(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the bridge closed the connection",
+        )))?;
+        match frame.split_first() {
+            Some((&ANSWER_OK, answer)) => APDUAnswer::from_answer(answer.to_vec())
+                .map_err(|_| Error::InvalidResponse("answer too short")),
+            Some((&ANSWER_ERROR, reason)) => {
+                Err(Error::Remote(String::from_utf8_lossy(reason).to_string()))
+            }
+            _ => Err(Error::InvalidResponse("invalid answer from the bridge")),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("bridge at {}", self.peer)
+    }
+}
+
+// Write this message, prefixed with its length.
+fn write_frame(stream: &mut dyn Write, data: &[u8]) -> Result<(), Error> {
+    stream.write_all(&(data.len() as u32).to_be_bytes())?;
+    stream.write_all(data)?;
+    stream.flush()?;
+    Ok(())
+}
+
+// Read a message. `None` if the connection was closed in between messages.
+fn read_frame(stream: &mut dyn Read) -> Result<Option<Vec<u8>>, Error> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_SIZE {
+        return Err(Error::InvalidResponse("bridge message too large"));
+    }
+    let mut data = vec![0; len];
+    stream.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+/// Serve the device to the client of a bridge connected on this stream: pass the APDUs it sends to
+/// the device and send it back the answers, until it disconnects. Failing to exchange an APDU with
+/// the device is reported to the client, not returned.
+pub fn serve_bridge(
+    mut stream: impl Read + Write,
+    ledger_api: &dyn LedgerTransport,
+) -> Result<(), Error> {
+    while let Some(request) = read_frame(&mut stream)? {
+        let answer =
+            apdu_command_from_bytes(&request).and_then(|command| ledger_api.exchange(&command));
+        let mut frame = Vec::new();
+        match answer {
+            Ok(answer) => {
+                frame.push(ANSWER_OK);
+                frame.extend_from_slice(answer.data());
+                frame.extend_from_slice(&answer.retcode().to_be_bytes());
+            }
+            Err(e) => {
+                log::warn!("Error exchanging an APDU for the bridge: {}", e);
+                frame.push(ANSWER_ERROR);
+                frame.extend_from_slice(e.to_string().as_bytes());
+            }
+        }
+        write_frame(&mut stream, &frame)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn round_trips_frames() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, &[0xe0, 0x01, 0x00, 0x00, 0x00]).expect("Written");
+        write_frame(&mut buf, &[]).expect("Written");
+        assert_eq!(&buf[..4], [0, 0, 0, 5]);
+
+        let mut stream = Cursor::new(buf);
+        assert_eq!(
+            read_frame(&mut stream).expect("Read"),
+            Some(vec![0xe0, 0x01, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(read_frame(&mut stream).expect("Read"), Some(Vec::new()));
+        assert_eq!(read_frame(&mut stream).expect("Read"), None);
+    }
+
+    #[test]
+    fn rejects_truncated_frame() {
+        let mut stream = Cursor::new(vec![0, 0, 0, 5, 0xe0, 0x01]);
+        assert!(matches!(
+            read_frame(&mut stream),
+            Err(Error::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn rejects_oversized_frame() {
+        let len = (MAX_FRAME_SIZE as u32 + 1).to_be_bytes();
+        let mut stream = Cursor::new(len.to_vec());
+        assert!(matches!(
+            read_frame(&mut stream),
+            Err(Error::InvalidResponse(_))
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+//! A cache of the answers of Ledger's API about its catalog (the apps, firmwares and language packs
+//! available for a device, and their versions). Repeatedly checking a device for updates then
+//! doesn't query the API every time, and keeps working for a little while without a network
+//! connection. Once an answer is too old, the API is asked to send it again only if it changed
+//! (conditional requests with `If-None-Match` and `If-Modified-Since`), which spares downloading
+//! the catalog again when provisioning many devices. Only used once enabled with [`set_cache`].
+//!
+//! The answers cached on a machine with access to the API can also be used on one without, as a
+//! package directory (see [`set_package_dir`]), or be downloaded on purpose into one with
+//! [`crate::download_package`].
+
+use crate::{http::ApiRequest, mirror::now, Error, PackageManifest, PACKAGE_MANIFEST};
+
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::Duration,
+};
+
+// Where to cache the answers, and for how long they are used.
+#[derive(Debug, Clone)]
+struct CacheSettings {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+static CACHE: RwLock<Option<CacheSettings>> = RwLock::new(None);
+
+/// Cache the answers of Ledger's API about its catalog in this directory, and use them rather than
+/// querying the API again for `ttl`, for all the operations of this process from now on. The
+/// directory is created if missing. Failing to write to it isn't an error, the API is just queried
+/// again next time.
+pub fn set_cache(dir: impl Into<PathBuf>, ttl: Duration) {
+    if let Ok(mut current) = CACHE.write() {
+        *current = Some(CacheSettings {
+            dir: dir.into(),
+            ttl,
+        });
+    }
+}
+
+// The directory of answers fetched beforehand to use instead of querying the API, if set.
+static PACKAGE_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Answer the requests to Ledger's API about its catalog, and get the language packs, only from
+/// this directory of answers fetched beforehand, never from the network, for all the operations of
+/// this process from now on. This allows provisioning devices from a machine without access to the
+/// API. The directory is in the format of the cache (see [`set_cache`]): for instance the cache
+/// directory of a machine which ran the same operations online, for the same device models and
+/// firmwares. Its answers are used however old they are.
+///
+/// Installing apps and firmwares, and the genuine check, still need a connection to Ledger's HSM:
+/// it encrypts what is sent to the device for the secure channel it opens with it during the
+/// operation, which can't be prepared beforehand. Only the catalog lookups, and installing language
+/// packs, work fully offline.
+pub fn set_package_dir(dir: impl Into<PathBuf>) {
+    if let Ok(mut current) = PACKAGE_DIR.write() {
+        *current = Some(dir.into());
+    }
+}
+
+/// The directory of answers fetched beforehand set with [`set_package_dir`], if any.
+pub fn package_dir() -> Option<PathBuf> {
+    PACKAGE_DIR.read().ok().and_then(|dir| dir.clone())
+}
+
+// The package directory the answers are downloaded into by this thread, if it's downloading one.
+thread_local! {
+    static DOWNLOAD_DIR: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+}
+
+/// Run this operation, downloading the answers to all the requests about the catalog it sends
+/// afresh into this directory, in the format of a package directory.
+pub(crate) fn download_into<T>(
+    dir: &Path,
+    op: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    DOWNLOAD_DIR.with(|current| *current.borrow_mut() = Some(dir.to_path_buf()));
+    let res = op();
+    DOWNLOAD_DIR.with(|current| *current.borrow_mut() = None);
+    res
+}
+
+// Where the answer to this request is cached in this directory.
+fn entry_path(dir: &Path, request: &ApiRequest) -> PathBuf {
+    let key = ring::digest::digest(&ring::digest::SHA256, request.key().as_bytes());
+    dir.join(format!("{}.json", hex::encode(key.as_ref())))
+}
+
+// Read the answer cached in this file, if any.
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    serde_json::from_slice(&fs::read(path).ok()?).ok()
+}
+
+// Read the answer to this request from the package directory, checking its integrity if the
+// directory has a manifest.
+fn read_package_entry(dir: &Path, request: &ApiRequest) -> Result<CacheEntry, Error> {
+    let path = entry_path(dir, request);
+    let bytes = fs::read(&path).map_err(|_| {
+        Error::MissingPackage(format!("'{}' in '{}'", request.url(), dir.display()))
+    })?;
+    let invalid = |reason: &str| {
+        Error::InvalidPackage(format!(
+            "'{}' in '{}' {}",
+            request.url(),
+            dir.display(),
+            reason
+        ))
+    };
+    if let Ok(manifest) = fs::read(dir.join(PACKAGE_MANIFEST)) {
+        let manifest: PackageManifest = serde_json::from_slice(&manifest)?;
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let digest = ring::digest::digest(&ring::digest::SHA256, &bytes);
+        match manifest.files.get(&name) {
+            Some(hash) if hash.eq_ignore_ascii_case(&hex::encode(digest.as_ref())) => {}
+            Some(_) => return Err(invalid("was modified since it was downloaded")),
+            None => return Err(invalid("isn't listed in its manifest")),
+        }
+    }
+    serde_json::from_slice(&bytes).map_err(|_| invalid("is corrupted"))
+}
+
+// An answer of the API, as cached.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    /// When the answer was received, or last confirmed unchanged, as a UNIX timestamp.
+    fetched_at: u64,
+    body: String,
+    /// The validators of the answer, to only download it again if it changed.
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+}
+
+impl CacheEntry {
+    fn try_write(&self, dir: &Path, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(path, serde_json::to_vec(self)?)
+    }
+
+    fn write(&self, dir: &Path, path: &Path) {
+        if let Err(e) = self.try_write(dir, path) {
+            log::warn!("Error caching the answer of the Ledger API: {}", e);
+        }
+    }
+}
+
+/// The answer of Ledger's API to a request, fresh or cached.
+pub(crate) struct CachedResponse {
+    body: String,
+}
+
+impl CachedResponse {
+    /// The answer, as text.
+    pub(crate) fn as_str(&self) -> &str {
+        &self.body
+    }
+
+    /// Parse the answer as JSON.
+    pub(crate) fn json<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_str(&self.body)
+    }
+}
+
+impl ApiRequest {
+    /// Send this request about the catalog, with retries, unless the answer to an identical one is
+    /// cached and not older than set with [`set_cache`]. Only look for the answer in the package
+    /// directory if one is set (see [`set_package_dir`]). While downloading a package directory,
+    /// always send the request and write its answer there.
+    pub(crate) fn send_cached(&self) -> Result<CachedResponse, Error> {
+        if let Some(dir) = DOWNLOAD_DIR.with(|dir| dir.borrow().clone()) {
+            let response = self.check_status(self.send_with_retries()?)?;
+            let entry = CacheEntry {
+                fetched_at: now(),
+                body: response.as_str()?.to_string(),
+                etag: None,
+                last_modified: None,
+            };
+            entry.try_write(&dir, &entry_path(&dir, self))?;
+            return Ok(CachedResponse { body: entry.body });
+        }
+        if let Some(dir) = package_dir() {
+            return Ok(CachedResponse {
+                body: read_package_entry(&dir, self)?.body,
+            });
+        }
+        let settings = match CACHE.read().ok().and_then(|cache| cache.clone()) {
+            Some(settings) => settings,
+            None => {
+                return Ok(CachedResponse {
+                    body: self
+                        .check_status(self.send_with_retries()?)?
+                        .as_str()?
+                        .to_string(),
+                })
+            }
+        };
+
+        let path = entry_path(&settings.dir, self);
+        let cached = read_entry(&path);
+        let mut request = self.clone();
+        if let Some(entry) = &cached {
+            if now().saturating_sub(entry.fetched_at) < settings.ttl.as_secs() {
+                log::debug!("Using the answer cached in '{}'.", path.display());
+                return Ok(CachedResponse {
+                    body: entry.body.clone(),
+                });
+            }
+            // Only download the answer again if it changed since it was cached.
+            if let Some(etag) = &entry.etag {
+                request = request.with_header("If-None-Match", etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.with_header("If-Modified-Since", last_modified);
+            }
+        }
+
+        let response = request.send_with_retries()?;
+        match cached {
+            Some(mut entry) if response.status_code == 304 => {
+                log::debug!("The answer cached in '{}' didn't change.", path.display());
+                entry.fetched_at = now();
+                entry.write(&settings.dir, &path);
+                Ok(CachedResponse { body: entry.body })
+            }
+            _ => {
+                let response = self.check_status(response)?;
+                let entry = CacheEntry {
+                    fetched_at: now(),
+                    body: response.as_str()?.to_string(),
+                    etag: response.headers.get("etag").cloned(),
+                    last_modified: response.headers.get("last-modified").cloned(),
+                };
+                entry.write(&settings.dir, &path);
+                Ok(CachedResponse { body: entry.body })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+    use crate::http::http_request;
+
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    #[test]
+    fn refreshes_unchanged_answer() {
+        // A server answering that the cached answer didn't change, if asked with its validator.
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Free port");
+        let url = format!("http://{}/apps", listener.local_addr().expect("Bound"));
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("Connection");
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).expect("Request");
+                assert!(read > 0, "Incomplete request");
+                request.extend_from_slice(&buf[..read]);
+            }
+            let request = String::from_utf8_lossy(&request).to_lowercase();
+            assert!(request.contains("if-none-match: \"v1\""));
+            stream
+                .write_all(b"HTTP/1.1 304 Not Modified\r\nContent-Length: 0\r\n\r\n")
+                .expect("Answer");
+        });
+
+        let dir = std::env::temp_dir().join(format!("ledger_manager_cache_{}", std::process::id()));
+        let request = http_request(minreq::Method::Get, url);
+        let path = entry_path(&dir, &request);
+        CacheEntry {
+            fetched_at: 0,
+            body: "[\"Bitcoin\"]".to_string(),
+            etag: Some("\"v1\"".to_string()),
+            last_modified: None,
+        }
+        .write(&dir, &path);
+        set_cache(&dir, Duration::from_secs(60));
+
+        let before = now();
+        let response = request.send_cached().expect("Cached answer");
+        server.join().expect("Server");
+        assert_eq!(response.as_str(), "[\"Bitcoin\"]");
+        let entry = read_entry(&path).expect("Cached entry");
+        assert!(entry.fetched_at >= before);
+        assert_eq!(entry.body, "[\"Bitcoin\"]");
+        fs::remove_dir_all(&dir).expect("Removed cache");
+    }
+}
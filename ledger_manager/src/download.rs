@@ -0,0 +1,106 @@
+//! Downloading beforehand, into a package directory, everything needed from Ledger's API to later
+//! provision devices of a model from a machine without access to it (see
+//! [`crate::set_package_dir`]): the catalog of apps and firmwares, and the language packs.
+
+use crate::{
+    apps_by_target, cache::download_into, current_final_firmware, firmware_update,
+    language::package_apdus, language_packages, mirror::now, DeviceInfo, Error, FirmwareInfo,
+};
+
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// The name of the manifest of a package directory.
+pub const PACKAGE_MANIFEST: &str = "manifest.json";
+
+/// What a package directory was downloaded for, and the hashes to check its integrity. Written to
+/// [`PACKAGE_MANIFEST`] in the directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackageManifest {
+    /// The model of the devices (target id) the package is for.
+    pub target_id: u32,
+    /// The firmware version the devices run.
+    pub firmware_version: String,
+    /// When the package was downloaded, as a UNIX timestamp.
+    pub downloaded_at: u64,
+    /// The apps the package was downloaded for, as available in the catalog then.
+    pub apps: Vec<PackagedApp>,
+    /// The SHA-256 (as hex) of every file of the package, by name.
+    pub files: BTreeMap<String, String>,
+}
+
+/// An app a package directory was downloaded for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackagedApp {
+    pub name: String,
+    pub version: String,
+    /// The hash of the app binary, as hex.
+    pub hash: String,
+}
+
+/// Download into this directory, created if missing, the answers of Ledger's API needed to later
+/// check for updates, look up these apps (by name, case-insensitive) and install language packs on
+/// devices of this model running this firmware, without a network connection. A manifest listing
+/// the hash of every file is written along, against which the answers are checked when used.
+///
+/// The apps and firmwares themselves can't be downloaded: Ledger's HSM encrypts them for the secure
+/// channel it opens with the device while installing, so installing them still needs a connection
+/// to it. Neither can the lookups of the apps installed on a device, which depend on the device.
+pub fn download_package(
+    device_info: &DeviceInfo,
+    apps: &[String],
+    dir: impl AsRef<Path>,
+) -> Result<PackageManifest, Error> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    let apps = download_into(dir, || {
+        FirmwareInfo::from_device(device_info)?;
+        current_final_firmware(device_info)?;
+        firmware_update(device_info)?;
+        for package in language_packages(device_info)? {
+            package_apdus(&package)?;
+        }
+        let catalog = apps_by_target(device_info)?;
+        apps.iter()
+            .map(|name| {
+                catalog
+                    .iter()
+                    .find(|app| app.version_name.eq_ignore_ascii_case(name))
+                    .map(|app| PackagedApp {
+                        name: app.version_name.clone(),
+                        version: app.version.clone(),
+                        hash: app.hash.clone(),
+                    })
+                    .ok_or_else(|| {
+                        Error::Catalog(format!("no app named '{}' for this device", name))
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = match path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        if name == PACKAGE_MANIFEST || !name.ends_with(".json") {
+            continue;
+        }
+        let digest = ring::digest::digest(&ring::digest::SHA256, &fs::read(&path)?);
+        files.insert(name, hex::encode(digest.as_ref()));
+    }
+    let manifest = PackageManifest {
+        target_id: device_info.target_id,
+        firmware_version: device_info.version.clone(),
+        downloaded_at: now(),
+        apps,
+        files,
+    };
+    fs::write(
+        dir.join(PACKAGE_MANIFEST),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    Ok(manifest)
+}
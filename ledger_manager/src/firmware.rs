@@ -0,0 +1,387 @@
+//! Updating the firmware of a device. Like for apps, the update is scripted by Ledger's HSM. It
+//! happens in stages, each of them requiring the user's confirmation on the device:
+//! - the OSU ("operating system updater") is installed while the device runs its current firmware.
+//!   Once confirmed, the device restarts to install the final firmware of the Secure Element;
+//! - on some models the device then restarts in bootloader mode, where the MCU firmware (and
+//!   possibly the MCU bootloader first) must be flashed. If the device doesn't but its MCU firmware
+//!   isn't compatible with the new firmware (see [`mcu_update_needed`]), the user must restart it in
+//!   bootloader mode themselves;
+//! - devices which don't install the final firmware on their own, such as older Nano S, report an
+//!   "-osu" version afterward and the final firmware must be installed.
+//!
+//! The device restarts between stages, so each stage is a separate function and the caller must
+//! reconnect to the device in between. See [`FirmwareStage`] to find out where a device stands.
+//! Flashing takes minutes, so the stage functions report their progress to a callback, with the
+//! fraction of the firmware written to the device (between 0 and 1).
+// Adapted from https://github.com/LedgerHQ/ledger-live/tree/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw
+// (firmwareUpdate-prepare.ts, firmwareUpdate-main.ts, installFinalFirmware.ts and flashMcu.ts).
+
+use crate::{
+    api_url, http_request, is_downgrade, provider, query_via_websocket_with_progress, socket_url,
+    version_key, DeviceInfo, DeviceVersion, Error, LedgerTransport, LIVE_COMMON_VERSION,
+};
+
+use form_urlencoded::Serializer as UrlSerializer;
+use serde_derive::{Deserialize, Serialize};
+
+/// The firmware update (OSU) available for a device, as published by the Ledger API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OsuFirmware {
+    pub id: i64,
+    /// The version of the firmware once updated, with an "-osu" suffix.
+    pub name: String,
+    /// The release notes of the update, if published.
+    #[serde(default)]
+    pub notes: Option<String>,
+    pub perso: String,
+    pub firmware: String,
+    pub firmware_key: String,
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// The id of the final firmware this update installs.
+    pub next_se_firmware_final_version: i64,
+}
+
+/// The final firmware of the Secure Element installed by a firmware update.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FinalFirmware {
+    pub id: i64,
+    /// The version of the firmware, as reported by the device once updated.
+    pub name: String,
+    /// The release notes of the firmware, if published.
+    #[serde(default)]
+    pub notes: Option<String>,
+    pub perso: String,
+    pub firmware: String,
+    pub firmware_key: String,
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// The size of the firmware, if returned by the API.
+    #[serde(default)]
+    pub bytes: Option<u64>,
+    /// The ids of the MCU firmware versions compatible with this firmware.
+    #[serde(default)]
+    pub mcu_versions: Vec<i64>,
+}
+
+/// A version of the MCU firmware.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct McuVersion {
+    pub id: i64,
+    pub name: String,
+    /// The version of the MCU bootloader required to flash this MCU firmware. "0.0" if any will
+    /// do.
+    pub from_bootloader_version: String,
+}
+
+/// A firmware update for a device: the OSU to install and the final firmware it leads to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareUpdate {
+    pub osu: OsuFirmware,
+    pub final_firmware: FinalFirmware,
+}
+
+impl FirmwareUpdate {
+    /// Whether this update installs an older firmware than the one this device runs, for instance
+    /// when resuming a stale update. `false` for a device in bootloader mode.
+    pub fn is_downgrade(&self, device_info: &DeviceInfo) -> bool {
+        !device_info.is_bootloader && is_downgrade(&self.final_firmware.name, &device_info.version)
+    }
+
+    /// The release notes of this update, if published. Ledger usually publishes them with the OSU.
+    pub fn notes(&self) -> Option<&str> {
+        self.osu
+            .notes
+            .as_deref()
+            .or(self.final_firmware.notes.as_deref())
+            .filter(|notes| !notes.trim().is_empty())
+    }
+}
+
+/// Where a device stands in a firmware update, given the final firmware being installed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FirmwareStage {
+    /// The OSU must be installed. See [`install_osu`].
+    Osu,
+    /// The device restarted in bootloader mode, the MCU must be flashed. See [`flash_mcu`].
+    Mcu,
+    /// The OSU is installed but the device didn't install the final firmware on its own. See
+    /// [`install_final_firmware`].
+    Final,
+    /// The device runs the final firmware.
+    Done,
+}
+
+impl FirmwareStage {
+    /// Find out where this device stands in the update to this final firmware.
+    pub fn of(device_info: &DeviceInfo, final_firmware: &FinalFirmware) -> Self {
+        if device_info.is_bootloader {
+            Self::Mcu
+        } else if device_info.version == final_firmware.name {
+            Self::Done
+        } else if device_info.is_osu() {
+            Self::Final
+        } else {
+            Self::Osu
+        }
+    }
+}
+
+/// What was flashed by [`flash_mcu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum McuFlash {
+    /// The MCU bootloader was too old for the MCU firmware, and was updated. The device restarts
+    /// in bootloader mode, after which [`flash_mcu`] must be called again.
+    Bootloader,
+    /// The MCU firmware was flashed. The device restarts.
+    Mcu,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CurrentFirmware {
+    id: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LatestFirmware {
+    se_firmware_osu_version: Option<OsuFirmware>,
+}
+
+// Get the id in the Ledger API of the model with this Secure Element target id. Outside of
+// bootloader mode, it's the target id reported by the device.
+pub(crate) fn device_version(target_id: u32) -> Result<DeviceVersion, Error> {
+    Ok(http_request(
+        minreq::Method::Post,
+        format!("{}/get_device_version", api_url()),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .with_json(&serde_json::json!({
+        "provider": provider(),
+        "target_id": target_id,
+    }))?
+    .send_cached()?
+    .json::<DeviceVersion>()?)
+}
+
+// Get the id in the Ledger API of the firmware this device runs.
+pub(crate) fn current_firmware_id(
+    device_info: &DeviceInfo,
+    device_version: &DeviceVersion,
+) -> Result<i64, Error> {
+    Ok(http_request(
+        minreq::Method::Post,
+        format!("{}/get_firmware_version", api_url()),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .with_json(&serde_json::json!({
+        "provider": provider(),
+        "device_version": device_version.id,
+        "version_name": &device_info.version,
+    }))?
+    .send_cached()?
+    .json::<CurrentFirmware>()?
+    .id)
+}
+
+// Get the latest firmware update (OSU) available for this device, if there is a newer firmware
+// than the one it runs.
+pub(crate) fn latest_osu(device_info: &DeviceInfo) -> Result<Option<OsuFirmware>, Error> {
+    let device_version = device_version(device_info.se_target_id)?;
+    let current_firmware_id = current_firmware_id(device_info, &device_version)?;
+
+    // The API answers with a "null" result, and no OSU version, when the firmware is up to date.
+    let latest = http_request(
+        minreq::Method::Post,
+        format!("{}/get_latest_firmware", api_url()),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .with_json(&serde_json::json!({
+        "provider": provider(),
+        "device_version": device_version.id,
+        "current_se_firmware_final_version": current_firmware_id,
+    }))?
+    .send_cached()?
+    .json::<LatestFirmware>()?;
+    Ok(latest.se_firmware_osu_version)
+}
+
+/// Get the final firmware the Secure Element of this device runs. For a device stuck in bootloader
+/// mode, it's the firmware whose MCU must be flashed to recover it.
+pub fn current_final_firmware(device_info: &DeviceInfo) -> Result<FinalFirmware, Error> {
+    // In bootloader mode, the device reports the firmware of its Secure Element separately.
+    let version = device_info.se_version.as_ref().ok_or_else(|| {
+        Error::Catalog("The device doesn't report the firmware of its Secure Element.".to_string())
+    })?;
+    let device_version = device_version(device_info.se_target_id)?;
+    Ok(http_request(
+        minreq::Method::Post,
+        format!("{}/get_firmware_version", api_url()),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .with_json(&serde_json::json!({
+        "provider": provider(),
+        "device_version": device_version.id,
+        "version_name": version,
+    }))?
+    .send_cached()?
+    .json::<FinalFirmware>()?)
+}
+
+/// Get the final firmware with this id from the Ledger API.
+pub fn final_firmware(id: i64) -> Result<FinalFirmware, Error> {
+    Ok(http_request(
+        minreq::Method::Get,
+        format!("{}/firmware_final_versions/{}", api_url(), id),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .send_cached()?
+    .json::<FinalFirmware>()?)
+}
+
+// Get all the MCU firmware versions from the Ledger API.
+fn mcu_versions() -> Result<Vec<McuVersion>, Error> {
+    Ok(
+        http_request(minreq::Method::Get, format!("{}/mcu_versions", api_url()))
+            .with_param("livecommonversion", LIVE_COMMON_VERSION)
+            .send_cached()?
+            .json::<Vec<McuVersion>>()?,
+    )
+}
+
+/// Get the firmware update available for this device, if it doesn't run the latest firmware.
+pub fn firmware_update(device_info: &DeviceInfo) -> Result<Option<FirmwareUpdate>, Error> {
+    if device_info.is_bootloader {
+        return Err(Error::WrongMode("the device is in bootloader mode"));
+    }
+    latest_osu(device_info)?
+        .map(|osu| {
+            let final_firmware = final_firmware(osu.next_se_firmware_final_version)?;
+            Ok(FirmwareUpdate {
+                osu,
+                final_firmware,
+            })
+        })
+        .transpose()
+}
+
+/// Get the firmware update this device was left in the middle of, if it runs a firmware updater
+/// (OSU) because the update was interrupted. The update can then be completed by installing its
+/// final firmware. A device in bootloader mode doesn't report its firmware, so the update it was
+/// left in can't be found out.
+pub fn pending_firmware_update(device_info: &DeviceInfo) -> Result<Option<FirmwareUpdate>, Error> {
+    if device_info.is_bootloader {
+        return Err(Error::WrongMode("the device is in bootloader mode"));
+    }
+    if !device_info.is_osu() {
+        return Ok(None);
+    }
+    // See getCurrentOSU in https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/manager/api.ts
+    let device_version = device_version(device_info.se_target_id)?;
+    let osu = http_request(
+        minreq::Method::Post,
+        format!("{}/get_osu_version", api_url()),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .with_json(&serde_json::json!({
+        "provider": provider(),
+        "device_version": device_version.id,
+        "version_name": &device_info.version,
+    }))?
+    .send_cached()?
+    .json::<OsuFirmware>()?;
+    let final_firmware = final_firmware(osu.next_se_firmware_final_version)?;
+    Ok(Some(FirmwareUpdate {
+        osu,
+        final_firmware,
+    }))
+}
+
+/// Install the OSU of this update. The user is asked to confirm the update on the device, which
+/// then restarts to install the final firmware.
+pub fn install_osu(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    osu: &OsuFirmware,
+    progress: impl FnMut(f32),
+) -> Result<(), Error> {
+    let mut url = UrlSerializer::new(format!("{}/install?", socket_url()));
+    url.append_pair("targetId", &device_info.target_id.to_string())
+        .append_pair("perso", &osu.perso)
+        .append_pair("firmware", &osu.firmware)
+        .append_pair("firmwareKey", &osu.firmware_key);
+    if let Some(hash) = &osu.hash {
+        url.append_pair("hash", hash);
+    }
+    query_via_websocket_with_progress(ledger_api, &url.finish(), progress)
+}
+
+/// Install this final firmware, on a device which runs the OSU but didn't install it on its own.
+pub fn install_final_firmware(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    final_firmware: &FinalFirmware,
+    progress: impl FnMut(f32),
+) -> Result<(), Error> {
+    let url = UrlSerializer::new(format!("{}/install?", socket_url()))
+        .append_pair("targetId", &device_info.target_id.to_string())
+        .append_pair("perso", &final_firmware.perso)
+        .append_pair("firmware", &final_firmware.firmware)
+        .append_pair("firmwareKey", &final_firmware.firmware_key)
+        .finish();
+    query_via_websocket_with_progress(ledger_api, &url, progress)
+}
+
+/// Whether the MCU of this device, running this final firmware, must be flashed: its MCU firmware
+/// isn't one of those compatible with the final firmware. `false` if the device doesn't report the
+/// version of its MCU firmware.
+pub fn mcu_update_needed(
+    device_info: &DeviceInfo,
+    final_firmware: &FinalFirmware,
+) -> Result<bool, Error> {
+    let current = match &device_info.mcu_version {
+        Some(version) => version,
+        None => return Ok(false),
+    };
+    Ok(!mcu_versions()?
+        .iter()
+        .filter(|mcu| final_firmware.mcu_versions.contains(&mcu.id))
+        .any(|mcu| &mcu.name == current))
+}
+
+/// Flash the latest MCU firmware compatible with this final firmware, on a device in bootloader
+/// mode. If its MCU bootloader is too old, it's updated instead, see [`McuFlash`].
+pub fn flash_mcu(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    final_firmware: &FinalFirmware,
+    progress: impl FnMut(f32),
+) -> Result<McuFlash, Error> {
+    if !device_info.is_bootloader {
+        return Err(Error::WrongMode("the device must be in bootloader mode"));
+    }
+    let mcu = mcu_versions()?
+        .into_iter()
+        .filter(|mcu| final_firmware.mcu_versions.contains(&mcu.id))
+        .max_by_key(|mcu| version_key(&mcu.name))
+        .ok_or_else(|| {
+            Error::Catalog(format!(
+                "No MCU firmware found for firmware {}.",
+                final_firmware.name
+            ))
+        })?;
+
+    // In bootloader mode the device reports the version of its MCU bootloader.
+    let (version, flash) = if mcu.from_bootloader_version != "0.0"
+        && mcu.from_bootloader_version != device_info.version
+    {
+        (mcu.from_bootloader_version, McuFlash::Bootloader)
+    } else {
+        (mcu.name, McuFlash::Mcu)
+    };
+    let url = UrlSerializer::new(format!("{}/mcu?", socket_url()))
+        .append_pair("targetId", &device_info.target_id.to_string())
+        .append_pair("version", &version)
+        .finish();
+    query_via_websocket_with_progress(ledger_api, &url, progress)?;
+    Ok(flash)
+}
@@ -0,0 +1,403 @@
+//! The Ledger devices connected over USB: listing them, and a transport to them like
+//! [`TransportNativeHID`] but with configurable timeouts and retries. `TransportNativeHID` waits
+//! for hours for the answer of the device, so a command the user never confirms hangs, and gives up
+//! on the first failure to reach the device.
+// The framing is that of https://github.com/Zondax/ledger-rs/blob/master/ledger-transport-hid/src/lib.rs
+
+use crate::{DeviceModel, Error, LedgerTransport};
+
+use ledger_apdu::{APDUAnswer, APDUCommand};
+use ledger_transport_hidapi::{
+    hidapi::{DeviceInfo as HidDeviceInfo, HidApi, HidDevice},
+    LedgerHIDError, TransportNativeHID,
+};
+use std::{
+    ffi::CString,
+    sync::{Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+const CHANNEL: u16 = 0x0101;
+const TAG_APDU: u8 = 0x05;
+// The reports are prefixed with their id (always 0) when written.
+const WRITE_PACKET_SIZE: usize = 65;
+const READ_PACKET_SIZE: usize = 64;
+
+/// The settings of a [`TransportHid`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransportConfig {
+    /// How long to wait for the answer of the device to a command, including the time the user
+    /// takes to confirm it on the device. `None` to wait forever. hidapi can't time out writes,
+    /// which only block if the device stops reading.
+    pub timeout: Option<Duration>,
+    /// How many more times to try connecting to the device, or sending it a command, if it fails.
+    /// A command is only sent again if the device couldn't have received it.
+    pub retries: u32,
+    /// How long to wait before trying again.
+    pub retry_interval: Duration,
+}
+
+impl Default for TransportConfig {
+    /// Wait for the answer of the device for up to 5 minutes, enough for the user to review and
+    /// confirm a command, and try 3 more times every second.
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(5 * 60)),
+            retries: 3,
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl TransportConfig {
+    // Run this operation, again after a pause if it fails, as many times as configured.
+    fn retry<T>(&self, mut op: impl FnMut() -> Result<T, Error>) -> Result<T, Error> {
+        let mut attempts = 0;
+        loop {
+            match op() {
+                Err(e) if attempts < self.retries => {
+                    log::debug!("Retrying after error: {}", e);
+                    attempts += 1;
+                    thread::sleep(self.retry_interval);
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+/// A Ledger device connected over USB, as listed by [`enumerate_devices`]. Connect to it with
+/// [`TransportHid::connect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedgerDeviceHandle {
+    /// The model of the device, as told by its USB product id. `None` for unknown models.
+    pub model: Option<DeviceModel>,
+    /// The USB product id of the device.
+    pub product_id: u16,
+    /// The USB serial number of the device, if it reports one.
+    pub serial: Option<String>,
+    /// The path of the device, as named by the platform. It tells the device apart from the other
+    /// ones connected, but changes when the device is plugged in again or restarts.
+    pub path: CString,
+}
+
+impl LedgerDeviceHandle {
+    fn new(device: &HidDeviceInfo) -> Self {
+        Self {
+            model: DeviceModel::from_product_id(device.product_id()),
+            product_id: device.product_id(),
+            serial: device.serial_number().map(str::to_string),
+            path: device.path().to_owned(),
+        }
+    }
+
+    /// Whether the device is in bootloader mode, as told by its USB product id.
+    pub fn is_bootloader(&self) -> bool {
+        DeviceModel::from_bootloader_product_id(self.product_id).is_some()
+    }
+}
+
+/// List the Ledger devices connected over USB. The devices plugged in after `hid_api` was created
+/// or last refreshed aren't listed.
+pub fn enumerate_devices(hid_api: &HidApi) -> Vec<LedgerDeviceHandle> {
+    TransportNativeHID::list_ledgers(hid_api)
+        .map(LedgerDeviceHandle::new)
+        .collect()
+}
+
+/// A connection to a Ledger device over USB, with the timeouts and retries of a
+/// [`TransportConfig`].
+pub struct TransportHid {
+    device: Mutex<HidDevice>,
+    // The path of the device, to tell whether it's gone when it can't be reached.
+    path: CString,
+    config: TransportConfig,
+}
+
+impl TransportHid {
+    /// Connect to the first Ledger device found.
+    pub fn new(config: TransportConfig) -> Result<Self, Error> {
+        config.retry(|| {
+            // The devices are only enumerated when the API is initialized.
+            let hid_api = HidApi::new().map_err(LedgerHIDError::from)?;
+            let device = TransportNativeHID::list_ledgers(&hid_api)
+                .next()
+                .ok_or(LedgerHIDError::DeviceNotFound)?;
+            Self::open(&hid_api, device, config)
+        })
+    }
+
+    /// Connect to this device, as listed by [`enumerate_devices`].
+    pub fn connect(device: &LedgerDeviceHandle, config: TransportConfig) -> Result<Self, Error> {
+        config.retry(|| {
+            let hid_api = HidApi::new().map_err(LedgerHIDError::from)?;
+            let hid_device = hid_api
+                .open_path(&device.path)
+                .map_err(LedgerHIDError::from)?;
+            Self::with_device(hid_device, device.path.clone(), config)
+        })
+    }
+
+    /// Connect to this Ledger device.
+    pub fn open_device(
+        hid_api: &HidApi,
+        device: &HidDeviceInfo,
+        config: TransportConfig,
+    ) -> Result<Self, Error> {
+        config.retry(|| Self::open(hid_api, device, config))
+    }
+
+    fn open(
+        hid_api: &HidApi,
+        device: &HidDeviceInfo,
+        config: TransportConfig,
+    ) -> Result<Self, Error> {
+        let hid_device = device.open_device(hid_api).map_err(LedgerHIDError::from)?;
+        Self::with_device(hid_device, device.path().to_owned(), config)
+    }
+
+    fn with_device(
+        device: HidDevice,
+        path: CString,
+        config: TransportConfig,
+    ) -> Result<Self, Error> {
+        device
+            .set_blocking_mode(true)
+            .map_err(LedgerHIDError::from)?;
+        Ok(Self {
+            device: Mutex::new(device),
+            path,
+            config,
+        })
+    }
+
+    /// The settings of this transport.
+    pub fn config(&self) -> &TransportConfig {
+        &self.config
+    }
+
+    // Tell a failure to read from or write to the device apart when the device is gone, for
+    // instance unplugged: it isn't listed anymore.
+    fn vanished_or(&self, e: Error) -> Error {
+        if !matches!(
+            e,
+            Error::Transport(LedgerHIDError::Hid(_) | LedgerHIDError::Io(_))
+        ) {
+            return e;
+        }
+        match HidApi::new() {
+            Ok(hid_api)
+                if !hid_api
+                    .device_list()
+                    .any(|d| d.path() == self.path.as_c_str()) =>
+            {
+                LedgerHIDError::DeviceNotFound.into()
+            }
+            _ => e,
+        }
+    }
+}
+
+// Split this APDU into the packets to write.
+fn apdu_packets(apdu: &[u8]) -> Vec<Vec<u8>> {
+    let mut data = Vec::with_capacity(apdu.len() + 2);
+    data.extend_from_slice(&(apdu.len() as u16).to_be_bytes());
+    data.extend_from_slice(apdu);
+
+    data.chunks(WRITE_PACKET_SIZE - 6)
+        .enumerate()
+        .map(|(seq, chunk)| {
+            let mut packet = vec![0; WRITE_PACKET_SIZE];
+            packet[1..3].copy_from_slice(&CHANNEL.to_be_bytes());
+            packet[3] = TAG_APDU;
+            packet[4..6].copy_from_slice(&(seq as u16).to_be_bytes());
+            packet[6..6 + chunk.len()].copy_from_slice(chunk);
+            packet
+        })
+        .collect()
+}
+
+fn write_packet(device: &HidDevice, packet: &[u8]) -> Result<(), Error> {
+    let written = device.write(packet).map_err(LedgerHIDError::from)?;
+    if written < packet.len() {
+        return Err(LedgerHIDError::Comm("USB write error. Could not send whole message").into());
+    }
+    Ok(())
+}
+
+// Read the answer to an APDU, reassembled from its packets, until the deadline if any.
+fn read_answer(device: &HidDevice, deadline: Option<Instant>) -> Result<Vec<u8>, Error> {
+    reassemble_answer(
+        |packet, timeout| {
+            Ok(device
+                .read_timeout(packet, timeout)
+                .map_err(LedgerHIDError::from)?)
+        },
+        deadline,
+    )
+}
+
+// Reassemble the answer to an APDU from the packets read with `read` (into the buffer, waiting at
+// most the timeout in milliseconds, -1 to wait forever), until the deadline if any.
+fn reassemble_answer(
+    mut read: impl FnMut(&mut [u8], i32) -> Result<usize, Error>,
+    deadline: Option<Instant>,
+) -> Result<Vec<u8>, Error> {
+    let mut answer = Vec::new();
+    let mut expected_len = 0;
+    let mut seq = 0u16;
+    loop {
+        let mut packet = [0; READ_PACKET_SIZE];
+        let timeout = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                // A timeout of 0 wouldn't block at all.
+                i32::try_from(left.as_millis()).unwrap_or(i32::MAX).max(1)
+            }
+            None => -1,
+        };
+        let read = read(&mut packet, timeout)?;
+        if read == 0 {
+            return Err(Error::Timeout);
+        }
+        let header_len = if seq == 0 { 7 } else { 5 };
+        if read < header_len {
+            return Err(LedgerHIDError::Comm("Read error. Incomplete header").into());
+        }
+        if packet[0..2] != CHANNEL.to_be_bytes() {
+            return Err(LedgerHIDError::Comm("Invalid channel").into());
+        }
+        if packet[2] != TAG_APDU {
+            return Err(LedgerHIDError::Comm("Invalid tag").into());
+        }
+        if packet[3..5] != seq.to_be_bytes() {
+            return Err(LedgerHIDError::Comm("Invalid sequence idx").into());
+        }
+        if seq == 0 {
+            expected_len = u16::from_be_bytes([packet[5], packet[6]]) as usize;
+        }
+        let missing = expected_len - answer.len();
+        let chunk = &packet[header_len..];
+        answer.extend_from_slice(&chunk[..chunk.len().min(missing)]);
+        if answer.len() >= expected_len {
+            return Ok(answer);
+        }
+        seq = seq.wrapping_add(1);
+    }
+}
+
+impl LedgerTransport for TransportHid {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        // A panic while holding the lock doesn't leave the connection in an inconsistent state, at
+        // worst the channel is desynchronized as after any interrupted exchange, which the drain
+        // below takes care of.
+        let device = self.device.lock().unwrap_or_else(PoisonError::into_inner);
+        // Drop what's left of an answer which timed out, so it isn't taken for the answer to this
+        // command.
+        let mut packet = [0; READ_PACKET_SIZE];
+        while device
+            .read_timeout(&mut packet, 0)
+            .map_err(|e| self.vanished_or(LedgerHIDError::from(e).into()))?
+            > 0
+        {}
+
+        let packets = apdu_packets(&command.serialize());
+        // Only a failed write of the first packet is retried: once part of the command went out,
+        // resending it from the start would be taken for the rest of it, and once it is received,
+        // the device may act on it.
+        self.config
+            .retry(|| write_packet(&device, &packets[0]))
+            .map_err(|e| self.vanished_or(e))?;
+        for packet in &packets[1..] {
+            write_packet(&device, packet).map_err(|e| self.vanished_or(e))?;
+        }
+        let deadline = self
+            .config
+            .timeout
+            .and_then(|t| Instant::now().checked_add(t));
+        let answer = read_answer(&device, deadline).map_err(|e| self.vanished_or(e))?;
+        APDUAnswer::from_answer(answer)
+            .map_err(|_| LedgerHIDError::Comm("response was too short").into())
+    }
+
+    fn describe(&self) -> String {
+        "USB".to_string()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    // Reassemble the answer from these packets, as read from the device.
+    fn reassemble(packets: &[Vec<u8>]) -> Result<Vec<u8>, Error> {
+        let mut packets = packets.iter();
+        reassemble_answer(
+            |buf, _| {
+                let packet = packets.next().ok_or(Error::Timeout)?;
+                buf[..packet.len()].copy_from_slice(packet);
+                Ok(packet.len())
+            },
+            None,
+        )
+    }
+
+    // The packets the device would send this answer in: those written without the report id.
+    fn answer_packets(answer: &[u8]) -> Vec<Vec<u8>> {
+        apdu_packets(answer)
+            .into_iter()
+            .map(|packet| packet[1..].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn splits_apdu_into_packets() {
+        let apdu: Vec<u8> = (0..=255).collect();
+        let packets = apdu_packets(&apdu);
+        // The length, then the APDU, 59 bytes per packet.
+        assert_eq!(packets.len(), 5);
+        for (seq, packet) in packets.iter().enumerate() {
+            assert_eq!(packet.len(), WRITE_PACKET_SIZE);
+            assert_eq!(packet[..6], [0, 0x01, 0x01, TAG_APDU, 0, seq as u8]);
+        }
+        assert_eq!(packets[0][6..8], [0x01, 0x00]);
+        assert_eq!(packets[0][8..], apdu[..57]);
+        assert_eq!(packets[1][6..], apdu[57..116]);
+        assert_eq!(packets[4][6..6 + 22], apdu[234..]);
+        assert!(packets[4][6 + 22..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn reassembles_answer() {
+        let answer: Vec<u8> = (0..100).collect();
+        assert_eq!(
+            reassemble(&answer_packets(&answer)).expect("Valid answer"),
+            answer
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_channel() {
+        let mut packets = answer_packets(&[0x90, 0x00]);
+        packets[0][0] = 0x02;
+        assert!(matches!(
+            reassemble(&packets),
+            Err(Error::Transport(LedgerHIDError::Comm("Invalid channel")))
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_sequence() {
+        let answer: Vec<u8> = (0..100).collect();
+        let mut packets = answer_packets(&answer);
+        packets[1][4] = 2;
+        assert!(matches!(
+            reassemble(&packets),
+            Err(Error::Transport(LedgerHIDError::Comm(
+                "Invalid sequence idx"
+            )))
+        ));
+    }
+}
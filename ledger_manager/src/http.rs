@@ -0,0 +1,124 @@
+//! The requests to Ledger's API, through the proxy if any (see [`crate::set_proxy`]) and retried
+//! as set with [`crate::set_retry_policy`].
+
+use crate::{
+    proxy::minreq_proxy,
+    retry::{is_retryable_status, parse_retry_after, retry_policy},
+    Error,
+};
+
+use serde::Serialize;
+
+/// A request to Ledger's API, built like a [`minreq::Request`] but which can tell apart identical
+/// requests, to cache their answers.
+#[derive(Debug, Clone)]
+pub(crate) struct ApiRequest {
+    method: minreq::Method,
+    url: String,
+    params: Vec<(String, String)>,
+    // The headers don't tell requests apart: they only change how the answer is sent.
+    headers: Vec<(String, String)>,
+    json: Option<String>,
+}
+
+/// A request to this URL of the Ledger API.
+pub(crate) fn http_request(method: minreq::Method, url: impl Into<String>) -> ApiRequest {
+    ApiRequest {
+        method,
+        url: url.into(),
+        params: Vec::new(),
+        headers: Vec::new(),
+        json: None,
+    }
+}
+
+impl ApiRequest {
+    /// Add this parameter to the query string.
+    pub(crate) fn with_param(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    /// Add this header.
+    pub(crate) fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Send this value as the JSON body.
+    pub(crate) fn with_json<T: Serialize>(mut self, body: &T) -> Result<Self, serde_json::Error> {
+        self.json = Some(serde_json::to_string(body)?);
+        Ok(self)
+    }
+
+    /// The URL the request is sent to, without its parameters.
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// A key telling this request apart from different ones.
+    pub(crate) fn key(&self) -> String {
+        let mut key = format!("{} {}", self.method, self.url);
+        for (name, value) in &self.params {
+            key.push_str(&format!(" {}={}", name, value));
+        }
+        if let Some(json) = &self.json {
+            key.push(' ');
+            key.push_str(json);
+        }
+        key
+    }
+
+    fn to_minreq(&self) -> minreq::Request {
+        let mut request = minreq::Request::new(self.method.clone(), self.url.clone());
+        for (name, value) in &self.params {
+            request = request.with_param(name, value);
+        }
+        for (name, value) in &self.headers {
+            request = request.with_header(name, value);
+        }
+        if let Some(json) = &self.json {
+            request = request
+                .with_header("Content-Type", "application/json; charset=UTF-8")
+                .with_body(json.clone());
+        }
+        match minreq_proxy(&self.url) {
+            Some(proxy) => request.with_proxy(proxy),
+            None => request,
+        }
+    }
+
+    /// Send the request, again if it fails for a reason which may be temporary. The answer of a
+    /// server still failing after the last retry is returned, not an error.
+    pub(crate) fn send_with_retries(&self) -> Result<minreq::Response, Error> {
+        retry_policy()
+            .run(
+                || Ok(self.to_minreq().send()?),
+                |response| is_retryable_status(response.status_code),
+                |response| {
+                    response
+                        .headers
+                        .get("retry-after")
+                        .and_then(|value| parse_retry_after(value))
+                },
+            )
+            .map_err(Error::classify)
+    }
+
+    /// The answer to this request, or an error if the server answered with another status than a
+    /// success.
+    pub(crate) fn check_status(
+        &self,
+        response: minreq::Response,
+    ) -> Result<minreq::Response, Error> {
+        if (200..300).contains(&response.status_code) {
+            Ok(response)
+        } else {
+            Err(Error::from_status(
+                &self.url,
+                response.status_code,
+                &response.reason_phrase,
+            ))
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Localizing the device. Recent firmwares are in English but can display another language, from a
+//! language pack published by the Ledger API for every firmware version. Unlike apps, a language
+//! pack isn't scripted by the HSM: its APDUs are downloaded and passed to the device as they are.
+//! A device holds a single language pack, so installing one replaces the previous one.
+// Adapted from https://github.com/LedgerHQ/ledger-live/tree/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw
+// (installLanguage.ts and uninstallLanguage.ts) and ../manager/api.ts (getLanguagePackagesForDevice).
+
+use crate::{
+    api_url, deser_apdu_command, exchange_checked,
+    firmware::{current_firmware_id, device_version},
+    http_request, DeviceInfo, Error, LedgerTransport, LIVE_COMMON_VERSION,
+};
+
+use ledger_apdu::APDUCommand;
+use serde_derive::{Deserialize, Serialize};
+
+// Delete a language pack from the device. P1 is the id of the language, or 0xff for all of them.
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/uninstallLanguage.ts
+const DELETE_LANGUAGE_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x33,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+const ALL_LANGUAGES: u8 = 0xff;
+
+/// The language built into every firmware. It needs no language pack.
+pub const DEFAULT_LANGUAGE: &str = "english";
+
+/// A language pack, as published by the Ledger API.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguagePackage {
+    /// The name of the language, in English and lowercase (for instance "french").
+    pub language: String,
+    #[serde(rename = "language_package_version_id")]
+    pub id: i64,
+    pub version: String,
+    pub apdu_install_url: String,
+    pub apdu_uninstall_url: String,
+    /// The size of the pack on the device.
+    pub bytes: u64,
+    /// The ids of the device models this pack can be installed on.
+    pub device_versions: Vec<i64>,
+    /// The ids of the firmwares this pack can be installed on.
+    pub se_firmware_final_versions: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LanguageResponse {
+    language: String,
+    language_package_version: Vec<PackageVersion>,
+}
+
+// A language pack, without the language it's for.
+#[derive(Debug, Clone, Deserialize)]
+struct PackageVersion {
+    language_package_version_id: i64,
+    version: String,
+    apdu_install_url: String,
+    apdu_uninstall_url: String,
+    bytes: u64,
+    device_versions: Vec<i64>,
+    se_firmware_final_versions: Vec<i64>,
+}
+
+/// Get the language packs which can be installed on this device, for the firmware it runs.
+pub fn language_packages(device_info: &DeviceInfo) -> Result<Vec<LanguagePackage>, Error> {
+    if device_info.is_bootloader {
+        return Err(Error::WrongMode("the device is in bootloader mode"));
+    }
+    let device_version = device_version(device_info.se_target_id)?;
+    let firmware_id = current_firmware_id(device_info, &device_version)?;
+
+    let languages = http_request(
+        minreq::Method::Get,
+        format!("{}/language-package", api_url()),
+    )
+    .with_param("livecommonversion", LIVE_COMMON_VERSION)
+    .send_cached()?
+    .json::<Vec<LanguageResponse>>()?;
+    Ok(languages
+        .into_iter()
+        .flat_map(|l| {
+            l.language_package_version
+                .into_iter()
+                .map(move |p| LanguagePackage {
+                    language: l.language.clone(),
+                    id: p.language_package_version_id,
+                    version: p.version,
+                    apdu_install_url: p.apdu_install_url,
+                    apdu_uninstall_url: p.apdu_uninstall_url,
+                    bytes: p.bytes,
+                    device_versions: p.device_versions,
+                    se_firmware_final_versions: p.se_firmware_final_versions,
+                })
+        })
+        .filter(|p| {
+            p.device_versions.contains(&device_version.id)
+                && p.se_firmware_final_versions.contains(&firmware_id)
+        })
+        .collect())
+}
+
+/// Get the language pack for this language (see [`LanguagePackage::language`]) which can be
+/// installed on this device, if any.
+pub fn language_package(
+    device_info: &DeviceInfo,
+    language: &str,
+) -> Result<Option<LanguagePackage>, Error> {
+    Ok(language_packages(device_info)?
+        .into_iter()
+        .find(|p| p.language.eq_ignore_ascii_case(language)))
+}
+
+/// Get the APDUs installing this language pack.
+pub(crate) fn package_apdus(package: &LanguagePackage) -> Result<Vec<APDUCommand<Vec<u8>>>, Error> {
+    // One APDU per line, as hex.
+    http_request(minreq::Method::Get, &package.apdu_install_url)
+        .send_cached()?
+        .as_str()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(deser_apdu_command)
+        .collect()
+}
+
+/// Install this language pack, replacing the one installed if any. The device then displays this
+/// language. `progress` is called with the fraction (between 0 and 1) of the pack written to the
+/// device.
+pub fn install_language(
+    ledger_api: &dyn LedgerTransport,
+    package: &LanguagePackage,
+    mut progress: impl FnMut(f32),
+) -> Result<(), Error> {
+    let apdus = package_apdus(package)?;
+    uninstall_languages(ledger_api)?;
+    for (i, command) in apdus.iter().enumerate() {
+        exchange_checked(ledger_api, command)?;
+        progress((i + 1) as f32 / apdus.len() as f32);
+    }
+    Ok(())
+}
+
+/// Delete the language pack installed on the device, if any. The device then displays the
+/// [`DEFAULT_LANGUAGE`].
+pub fn uninstall_languages(ledger_api: &dyn LedgerTransport) -> Result<(), Error> {
+    let mut command = DELETE_LANGUAGE_COMMAND_TEMPLATE;
+    command.p1 = ALL_LANGUAGES;
+    exchange_checked(ledger_api, &command)
+}
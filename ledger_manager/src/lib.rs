@@ -3,16 +3,85 @@
 //! This implements utility functions to manage the applications installed on your Ledger device.
 //! This is performed by both talking to the Ledger device connected by USB but also by making HTTP
 //! request to the Ledger API used by Ledger Live.
-
+//!
+//! It never panics on the data received from the device, the Ledger API or Ledger's HSM. Malformed
+//! data is reported as an [`Error`].
+//!
+//! It is fully blocking and doesn't need an async runtime: the Ledger API is queried with `minreq`
+//! and the HSM reached with `tungstenite`, both over `rustls`, so embedding it doesn't pull tokio
+//! nor OpenSSL in.
+
+#![deny(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+mod bridge;
+mod cache;
+mod download;
+mod firmware;
+mod hid;
+mod http;
+mod language;
+mod lockscreen;
+mod manager;
+mod mirror;
+#[cfg(feature = "test-utils")]
+mod mock;
+pub mod prelude;
+mod proxy;
+mod registry;
+mod retry;
+mod shared;
+mod speculos;
+mod tls;
+
+pub use bridge::{serve_bridge, TransportRemote, BRIDGE_DEFAULT_PORT};
+pub use cache::{package_dir, set_cache, set_package_dir};
+pub use download::{download_package, PackageManifest, PackagedApp, PACKAGE_MANIFEST};
+pub use firmware::{
+    current_final_firmware, final_firmware, firmware_update, flash_mcu, install_final_firmware,
+    install_osu, mcu_update_needed, pending_firmware_update, FinalFirmware, FirmwareStage,
+    FirmwareUpdate, McuFlash, McuVersion, OsuFirmware,
+};
+pub use hid::{enumerate_devices, LedgerDeviceHandle, TransportConfig, TransportHid};
+use http::http_request;
+pub use language::{
+    install_language, language_package, language_packages, uninstall_languages, LanguagePackage,
+    DEFAULT_LANGUAGE,
+};
 pub use ledger_apdu;
 pub use ledger_transport_hidapi;
+pub use lockscreen::{clear_lock_screen, set_lock_screen, LOCK_SCREEN_GRAY_LEVELS};
+pub use manager::LedgerManager;
+use mirror::api_v2_url;
+pub use mirror::{
+    api_url, probe_latency, provider, select_socket_url, set_api_url, set_provider,
+    set_socket_url, socket_url, PROBE_TIMEOUT,
+};
+#[cfg(feature = "test-utils")]
+pub use mock::MockTransport;
+use proxy::connect_websocket;
+pub use proxy::{proxy_url, set_proxy};
+pub use registry::{registry, set_registry, AppEntry, AppRegistry};
+pub use retry::{retry_policy, set_retry_policy, RetryPolicy};
+pub use shared::SharedTransport;
+pub use speculos::{TransportSpeculos, SPECULOS_DEFAULT_PORT};
+pub use tls::{set_ca_bundle, set_certificate_pins};
 
 use form_urlencoded::Serializer as UrlSerializer;
-use ledger_apdu::APDUCommand;
-use ledger_transport_hidapi::TransportNativeHID;
+use ledger_apdu::{APDUAnswer, APDUCommand};
+use ledger_transport_hidapi::{LedgerHIDError, TransportNativeHID};
 use serde_derive::Deserialize;
-
-use std::{error, str};
+#[cfg(feature = "serde")]
+use serde_derive::Serialize;
+
+use std::{
+    array::TryFromSliceError,
+    fmt,
+    ops::Deref,
+    net::TcpStream,
+    str, thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{stream::MaybeTlsStream, WebSocket};
 
 // https://github.com/LedgerHQ/ledger-live/blob/dd1d17fd3ce7ed42558204b2f93707fb9b1599de/libs/device-core/src/commands/use-cases/getVersion.ts#L6
 const GET_VERSION_COMMAND: APDUCommand<&[u8]> = APDUCommand {
@@ -50,6 +119,97 @@ const OPEN_APP_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
     data: &[],
 };
 
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/getAppAndVersion.ts
+const GET_APP_AND_VERSION_COMMAND: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xb0,
+    ins: 0x01,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/quitApp.ts
+const QUIT_APP_COMMAND: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xb0,
+    ins: 0xa7,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/getBatteryStatus.ts
+// P2 selects the battery information to get, see the BATTERY_* constants.
+const GET_BATTERY_STATUS_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x10,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+const BATTERY_PERCENTAGE: u8 = 0x00;
+const BATTERY_VOLTAGE: u8 = 0x01;
+const BATTERY_FLAGS: u8 = 0x04;
+
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/getDeviceName.ts
+const GET_DEVICE_NAME_COMMAND: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0xd2,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/editDeviceName.ts
+// The data is the new name and is set when sending the command.
+const SET_DEVICE_NAME_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0xd4,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+/// The maximum length of the name of a device, in bytes.
+pub const MAX_DEVICE_NAME_LENGTH: usize = 20;
+
+// https://github.com/LedgerHQ/app-ethereum/blob/1.10.4/doc/ethapp.adoc#get-eth-public-address
+// The data is the derivation path and is set when sending the command. Don't ask for confirmation
+// on the device nor for the chain code.
+const ETHEREUM_GET_ADDRESS_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x02,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// https://github.com/LedgerHQ/app-bitcoin-new/blob/2.2.2/doc/bitcoin.md#get_master_fingerprint
+const GET_MASTER_FINGERPRINT_COMMAND: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe1,
+    ins: 0x05,
+    p1: 0x00,
+    p2: 0x01,
+    data: &[],
+};
+
+// https://github.com/solana-labs/solana/blob/v1.18.11/sdk/src/derivation_path.rs and
+// https://github.com/LedgerHQ/app-solana/blob/1.4.1/doc/api.md#get_pubkey
+// The data is the derivation path and is set when sending the command.
+const SOLANA_GET_PUBKEY_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x05,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+/// The derivation path of the default Solana account, m/44'/501'.
+pub const SOLANA_DEFAULT_DERIVATION_PATH: [u32; 2] = [44 | 0x8000_0000, 501 | 0x8000_0000];
+
+/// The derivation path of the default Ethereum account, m/44'/60'/0'/0/0.
+pub const ETHEREUM_DEFAULT_DERIVATION_PATH: [u32; 5] =
+    [44 | 0x8000_0000, 60 | 0x8000_0000, 0x8000_0000, 0, 0];
+
 /// The Ledger Live API requires request to set their claimed version of Ledger Live. This was
 /// chosen arbitrarily as a working value.
 pub const LIVE_COMMON_VERSION: &str = "34.0.0";
@@ -57,61 +217,652 @@ pub const LIVE_COMMON_VERSION: &str = "34.0.0";
 /// The Ledger Live API has multiple channels to download binaries. This sets which one to use. 1
 /// is default. 4 is "shitcoins". The rest is unclear. Defined here:
 /// https://github.com/LedgerHQ/ledger-live/blob/4d1d7bb3462fd0c986ed587f0cf426afc96850c8/libs/device-core/src/managerApi/use-cases/getProviderIdUseCase.ts#L3-L9
-// TODO: make it possible to set it?
+/// Another one can be set with [`set_provider`].
 pub const PROVIDER: u32 = 1;
 
 pub const BASE_API_V1_URL: &str = "https://manager.api.live.ledger.com/api";
 pub const BASE_API_V2_URL: &str = "https://manager.api.live.ledger.com/api/v2";
 pub const BASE_SOCKET_URL: &str = "wss://scriptrunner.api.live.ledger.com/update";
 
-/// The return code when sending an APDU command to a Ledger device. Taken from
+/// The status word a Ledger device answers a command with: success, or why it failed the command
+/// (see [`Error::Status`]). Taken from
 /// https://github.com/LedgerHQ/ledger-live/blob/4d1d7bb3462fd0c986ed587f0cf426afc96850c8/libs/ledgerjs/packages/errors/src/index.ts#L233
-#[derive(Debug, Clone, Copy)]
-pub enum StatusCode {
-    //ACCESS_CONDITION_NOT_FULFILLED = 0x9804,
-    //ALGORITHM_NOT_SUPPORTED = 0x9484,
-    //CLA_NOT_SUPPORTED = 0x6e00,
-    //CODE_BLOCKED = 0x9840,
-    //CODE_NOT_INITIALIZED = 0x9802,
-    //COMMAND_INCOMPATIBLE_FILE_STRUCTURE = 0x6981,
-    //CONDITIONS_OF_USE_NOT_SATISFIED = 0x6985,
-    //CONTRADICTION_INVALIDATION = 0x9810,
-    //CONTRADICTION_SECRET_CODE_STATUS = 0x9808,
-    //CUSTOM_IMAGE_BOOTLOADER = 0x662f,
-    //CUSTOM_IMAGE_EMPTY = 0x662e,
-    //FILE_ALREADY_EXISTS = 0x6a89,
-    //FILE_NOT_FOUND = 0x9404,
-    //GP_AUTH_FAILED = 0x6300,
-    //HALTED = 0x6faa,
-    //INCONSISTENT_FILE = 0x9408,
-    //INCORRECT_DATA = 0x6a80,
-    //INCORRECT_LENGTH = 0x6700,
-    //INCORRECT_P1_P2 = 0x6b00,
-    //INS_NOT_SUPPORTED = 0x6d00,
-    //DEVICE_NOT_ONBOARDED = 0x6d07,
-    //DEVICE_NOT_ONBOARDED_2 = 0x6611,
-    //INVALID_KCV = 0x9485,
-    //INVALID_OFFSET = 0x9402,
-    //LICENSING = 0x6f42,
-    LockedDevice = 0x5515,
-    //MAX_VALUE_REACHED = 0x9850,
-    //MEMORY_PROBLEM = 0x9240,
-    //MISSING_CRITICAL_PARAMETER = 0x6800,
-    //NO_EF_SELECTED = 0x9400,
-    //NOT_ENOUGH_MEMORY_SPACE = 0x6a84,
-    OK = 0x9000,
-    //PIN_REMAINING_ATTEMPTS = 0x63c0,
-    //REFERENCED_DATA_NOT_FOUND = 0x6a88,
-    //SECURITY_STATUS_NOT_SATISFIED = 0x6982,
-    //TECHNICAL_PROBLEM = 0x6f00,
-    //UNKNOWN_APDU = 0x6d02,
-    //USER_REFUSED_ON_DEVICE = 0x5501,
-    //NOT_ENOUGH_SPACE = 0x5102,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StatusWord {
+    /// The command succeeded (0x9000).
+    Ok,
+    /// The user refused on the device (0x5501).
+    UserRefused,
+    /// The device is locked (0x5515).
+    DeviceLocked,
+    /// There isn't enough space left on the device (0x5102).
+    NotEnoughSpace,
+    /// The conditions to run the command aren't met, for instance the user refused (0x6985).
+    ConditionsOfUseNotSatisfied,
+    /// The command requires a state the device isn't in, for instance a secure channel (0x6982).
+    SecurityStatusNotSatisfied,
+    /// The command has an incorrect length (0x6700).
+    IncorrectLength,
+    /// The command has incorrect data (0x6a80).
+    IncorrectData,
+    /// There isn't enough memory left on the device (0x6a84).
+    NotEnoughMemory,
+    /// What the command refers to isn't on the device (0x6a88).
+    ReferencedDataNotFound,
+    /// What the command creates is already on the device (0x6a89).
+    AlreadyExists,
+    /// The command has incorrect parameters (0x6b00).
+    IncorrectParameters,
+    /// The app to open isn't installed, as told by the dashboard (0x6807).
+    AppNotFound,
+    /// The device isn't set up yet (0x6d07 or 0x6611).
+    NotOnboarded,
+    /// The instruction isn't supported, for instance by the app open (0x6d00).
+    InsNotSupported,
+    /// The class isn't supported, for instance by the app open (0x6e00).
+    ClaNotSupported,
+    /// The device failed internally (0x6f00).
+    TechnicalProblem,
+    /// The device halted (0x6faa).
+    Halted,
+    /// Another status word.
+    Other(u16),
+}
+
+impl StatusWord {
+    /// The status word, as sent by the device.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::Ok => 0x9000,
+            Self::UserRefused => 0x5501,
+            Self::DeviceLocked => 0x5515,
+            Self::NotEnoughSpace => 0x5102,
+            Self::ConditionsOfUseNotSatisfied => 0x6985,
+            Self::SecurityStatusNotSatisfied => 0x6982,
+            Self::IncorrectLength => 0x6700,
+            Self::IncorrectData => 0x6a80,
+            Self::NotEnoughMemory => 0x6a84,
+            Self::ReferencedDataNotFound => 0x6a88,
+            Self::AlreadyExists => 0x6a89,
+            Self::IncorrectParameters => 0x6b00,
+            Self::AppNotFound => 0x6807,
+            Self::NotOnboarded => 0x6d07,
+            Self::InsNotSupported => 0x6d00,
+            Self::ClaNotSupported => 0x6e00,
+            Self::TechnicalProblem => 0x6f00,
+            Self::Halted => 0x6faa,
+            Self::Other(code) => *code,
+        }
+    }
+
+    /// Whether the command succeeded.
+    pub fn is_ok(&self) -> bool {
+        *self == Self::Ok
+    }
+
+    // What the status word means, if known.
+    fn description(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::Ok => "success",
+            Self::UserRefused => "refused by the user",
+            Self::DeviceLocked => "device locked",
+            Self::NotEnoughSpace => "not enough space",
+            Self::ConditionsOfUseNotSatisfied => "conditions of use not satisfied",
+            Self::SecurityStatusNotSatisfied => "security status not satisfied",
+            Self::IncorrectLength => "incorrect length",
+            Self::IncorrectData => "incorrect data",
+            Self::NotEnoughMemory => "not enough memory",
+            Self::ReferencedDataNotFound => "referenced data not found",
+            Self::AlreadyExists => "already exists",
+            Self::IncorrectParameters => "incorrect parameters",
+            Self::AppNotFound => "app not found",
+            Self::NotOnboarded => "device not set up",
+            Self::InsNotSupported => "instruction not supported",
+            Self::ClaNotSupported => "class not supported",
+            Self::TechnicalProblem => "technical problem",
+            Self::Halted => "device halted",
+            Self::Other(_) => return None,
+        })
+    }
+}
+
+impl From<u16> for StatusWord {
+    fn from(code: u16) -> Self {
+        match code {
+            0x9000 => Self::Ok,
+            0x5501 => Self::UserRefused,
+            0x5515 => Self::DeviceLocked,
+            0x5102 => Self::NotEnoughSpace,
+            0x6985 => Self::ConditionsOfUseNotSatisfied,
+            0x6982 => Self::SecurityStatusNotSatisfied,
+            0x6700 => Self::IncorrectLength,
+            0x6a80 => Self::IncorrectData,
+            0x6a84 => Self::NotEnoughMemory,
+            0x6a88 => Self::ReferencedDataNotFound,
+            0x6a89 => Self::AlreadyExists,
+            0x6b00 => Self::IncorrectParameters,
+            0x6807 => Self::AppNotFound,
+            0x6d07 | 0x6611 => Self::NotOnboarded,
+            0x6d00 => Self::InsNotSupported,
+            0x6e00 => Self::ClaNotSupported,
+            0x6f00 => Self::TechnicalProblem,
+            0x6faa => Self::Halted,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for StatusWord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.description() {
+            Some(description) => write!(f, "{:#06x} ({})", self.code(), description),
+            None => write!(f, "{:#06x}", self.code()),
+        }
+    }
+}
+
+/// What an operation was doing when the device was disconnected. See
+/// [`Error::DeviceDisconnected`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum OperationStage {
+    /// Opening the secure channel between the device and Ledger's HSM, before changing anything
+    /// on it.
+    OpeningSecureChannel,
+    /// Transferring what Ledger's HSM sends in bulk to the device, for instance the binary of an
+    /// app.
+    Transferring,
+    /// Completing the operation with Ledger's HSM, once the data was transferred.
+    Completing,
+}
+
+impl fmt::Display for OperationStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::OpeningSecureChannel => "opening the secure channel with Ledger's HSM",
+            Self::Transferring => "transferring data to it",
+            Self::Completing => "completing the operation",
+        })
+    }
+}
+
+/// An error when talking to the device, to the Ledger API or to Ledger's HSM.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The communication with the device failed.
+    #[error("Error communicating with the device: {0}")]
+    Transport(#[from] LedgerHIDError),
+    /// The device is locked. It must be unlocked with its PIN.
+    #[error("Device is locked.")]
+    LockedDevice,
+    /// The device was disconnected, for instance unplugged, during this stage of the operation.
+    /// The secure channel with Ledger's HSM, if any, is closed, and an app whose transfer was
+    /// interrupted isn't installed: the operation can be run again once the device is reconnected.
+    #[error("The device was disconnected while {0}, reconnect it and try again")]
+    DeviceDisconnected(OperationStage),
+    /// The user refused on the device, for instance to allow Ledger manager.
+    #[error("Refused on the device.")]
+    UserRefused,
+    /// The device answered a command with an error status word.
+    #[error("Device returned error status {0}.")]
+    Status(StatusWord),
+    /// The device didn't answer in time. See [`TransportConfig::timeout`].
+    #[error("Timed out waiting for the device to answer.")]
+    Timeout,
+    /// The device answered with data which could not be parsed.
+    #[error("Invalid response from the device: {0}.")]
+    InvalidResponse(&'static str),
+    /// An APDU command which could not be deserialized.
+    #[error("Invalid APDU command.")]
+    InvalidCommand,
+    /// The connection to an emulator or to a bridge failed.
+    #[error("Error communicating with the emulator or bridge: {0}")]
+    Io(#[from] std::io::Error),
+    /// The bridge serving the device failed to exchange the APDU with it. See [`TransportRemote`].
+    #[error("Error on the bridge to the device: {0}")]
+    Remote(String),
+    /// The proxy to Ledger's API and HSM is invalid or failed to connect to them. See
+    /// [`set_proxy`].
+    #[error("Error connecting through the proxy: {0}")]
+    Proxy(String),
+    /// The certificate authorities or pins set to verify the certificate of Ledger's HSM are
+    /// invalid. See [`set_ca_bundle`] and [`set_certificate_pins`].
+    #[error("Invalid TLS settings: {0}")]
+    Tls(String),
+    /// A request to the Ledger API failed.
+    #[error("Error querying the Ledger API: {0}")]
+    Http(#[from] minreq::Error),
+    /// The Ledger API or HSM sent a message which could not be parsed.
+    #[error("Invalid JSON message: {0}")]
+    Json(#[from] serde_json::Error),
+    /// The websocket connection to Ledger's HSM failed.
+    #[error("Error communicating with Ledger's HSM: {0}")]
+    WebSocket(Box<tungstenite::Error>),
+    /// Ledger's API or HSM couldn't be reached at all: the machine is offline, or its DNS or
+    /// firewall blocks them.
+    #[error("Can't reach Ledger's servers, check your internet connection: {0}")]
+    Offline(String),
+    /// Ledger's API or HSM is down or overloaded: it refused or dropped the connection, or
+    /// answered with a server error.
+    #[error("Ledger's servers are unavailable, try again later: {0}")]
+    ServerUnavailable(String),
+    /// Ledger's API or HSM rejected the request (a client error such as a missing resource or a
+    /// denied access), which is likely a bug.
+    #[error("Ledger's servers rejected the request, please report this bug: {0}")]
+    RequestRejected(String),
+    /// Ledger's HSM reported an error or sent an unexpected message.
+    #[error("Ledger's HSM: {0}")]
+    Hsm(String),
+    /// The answer of the Ledger API to a request isn't in the package directory. See
+    /// [`set_package_dir`].
+    #[error("Not in the package directory: the answer to {0}")]
+    MissingPackage(String),
+    /// An answer in the package directory doesn't match the hash recorded in its manifest when it
+    /// was downloaded, or can't be read. See [`download_package`].
+    #[error("Invalid package directory: the answer to {0}")]
+    InvalidPackage(String),
+    /// The catalog data is inconsistent, for instance an app depends on a missing library.
+    #[error("Invalid catalog: {0}")]
+    Catalog(String),
+    /// The operation isn't possible in the mode the device is in, for instance flashing the MCU
+    /// while not in bootloader mode.
+    #[error("Wrong device mode: {0}.")]
+    WrongMode(&'static str),
+    /// The device can't perform the operation, for instance a model without the feature.
+    #[error("Not supported: {0}.")]
+    Unsupported(&'static str),
+    /// An app is open on the device, which must be back on the dashboard. See [`quit_app`].
+    #[error("The {0} app is open on the device. Quit it to go back to the dashboard.")]
+    AppOpen(String),
+}
+
+impl Error {
+    /// Whether the device is locked, for instance because it locked itself during the operation.
+    /// The operation can be run again once it's unlocked (see [`wait_unlocked`]).
+    pub fn is_locked_device(&self) -> bool {
+        matches!(self, Self::LockedDevice)
+    }
+
+    /// The name of the app open on the device, if the operation failed because it needs the
+    /// dashboard. The operation can be run again once the app is quit (see [`quit_app`]).
+    pub fn app_open(&self) -> Option<&str> {
+        match self {
+            Self::AppOpen(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The status word the device answered with, if it failed the command.
+    pub fn status_word(&self) -> Option<StatusWord> {
+        match self {
+            Self::Status(status) => Some(*status),
+            Self::LockedDevice => Some(StatusWord::DeviceLocked),
+            Self::UserRefused => Some(StatusWord::UserRefused),
+            _ => None,
+        }
+    }
+
+    /// Whether this error may be caused by the connection with the device being lost for a moment,
+    /// for instance by a USB hub resetting, the computer waking up from sleep or the channel being
+    /// desynchronized. Reconnecting to the device may fix it.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Self::Transport(
+                LedgerHIDError::Comm(_)
+                    | LedgerHIDError::Io(_)
+                    | LedgerHIDError::Hid(_)
+                    | LedgerHIDError::DeviceNotFound
+            ) | Self::DeviceDisconnected(_)
+        )
+    }
+
+    // The error for a failure to exchange with the device during this stage of an operation: the
+    // device was disconnected if it's gone. Other failures to reach it are left as they are.
+    pub(crate) fn disconnected(self, stage: OperationStage) -> Self {
+        match self {
+            Self::Transport(LedgerHIDError::DeviceNotFound) => Self::DeviceDisconnected(stage),
+            e => e,
+        }
+    }
+
+    /// Whether a request to Ledger's API or the connection to its HSM failed for a reason which
+    /// may be temporary, such as a network blip or an overloaded server, so it's worth retrying. A
+    /// request which was rejected, or a certificate which failed to verify, is a permanent failure.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Offline(_) | Self::ServerUnavailable(_) => true,
+            Self::Http(
+                minreq::Error::IoError(_)
+                | minreq::Error::AddressNotFound
+                | minreq::Error::ProxyConnect,
+            ) => true,
+            Self::WebSocket(e) => match e.as_ref() {
+                tungstenite::Error::Io(_) => true,
+                tungstenite::Error::Http(response) => {
+                    retry::is_retryable_status(response.status().as_u16().into())
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // Tell apart, in a failure to reach Ledger's API or HSM, a machine which is offline from a
+    // server which is unavailable or which rejected the request.
+    pub(crate) fn classify(self) -> Self {
+        match &self {
+            Self::Http(e @ minreq::Error::AddressNotFound) => Self::Offline(e.to_string()),
+            Self::Http(e @ minreq::Error::IoError(io)) => Self::from_io(io.kind(), e.to_string()),
+            Self::WebSocket(e) => match e.as_ref() {
+                tungstenite::Error::Io(io) => Self::from_io(io.kind(), e.to_string()),
+                tungstenite::Error::Url(tungstenite::error::UrlError::UnableToConnect(_)) => {
+                    Self::Offline(e.to_string())
+                }
+                tungstenite::Error::Http(response) => Self::from_status(
+                    "Ledger's HSM",
+                    response.status().as_u16().into(),
+                    response.status().canonical_reason().unwrap_or_default(),
+                ),
+                _ => self,
+            },
+            _ => self,
+        }
+    }
+
+    // A failure to connect, or a connection dropped by the server.
+    fn from_io(kind: std::io::ErrorKind, message: String) -> Self {
+        use std::io::ErrorKind::*;
+        match kind {
+            ConnectionRefused | ConnectionReset | ConnectionAborted | BrokenPipe | UnexpectedEof => {
+                Self::ServerUnavailable(message)
+            }
+            _ => Self::Offline(message),
+        }
+    }
+
+    /// The error for a server which answered with this HTTP status, other than a success.
+    pub(crate) fn from_status(server: &str, status: i32, reason: &str) -> Self {
+        let message = format!("{} answered {} {}", server, status, reason);
+        if retry::is_retryable_status(status) || status >= 500 {
+            Self::ServerUnavailable(message)
+        } else {
+            Self::RequestRejected(message)
+        }
+    }
+
+    // How long Ledger's HSM asked to wait before connecting again, if it refused the connection
+    // with a `Retry-After` header.
+    pub(crate) fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::WebSocket(e) => match e.as_ref() {
+                tungstenite::Error::Http(response) => response
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(retry::parse_retry_after),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl From<StatusWord> for Error {
+    /// The error for a command the device failed with this status word.
+    fn from(status: StatusWord) -> Self {
+        match status {
+            StatusWord::DeviceLocked => Self::LockedDevice,
+            StatusWord::UserRefused | StatusWord::ConditionsOfUseNotSatisfied => Self::UserRefused,
+            status => Self::Status(status),
+        }
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Self::WebSocket(Box::new(e))
+    }
+}
+
+impl From<str::Utf8Error> for Error {
+    fn from(_: str::Utf8Error) -> Self {
+        Self::InvalidResponse("invalid UTF-8 string")
+    }
+}
+
+impl From<TryFromSliceError> for Error {
+    fn from(_: TryFromSliceError) -> Self {
+        Self::InvalidResponse("unexpected length")
+    }
+}
+
+impl From<hex::FromHexError> for Error {
+    fn from(_: hex::FromHexError) -> Self {
+        Self::InvalidCommand
+    }
+}
+
+/// The generation of the user interface library used by the firmware and apps of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum UiGeneration {
+    /// The original library of the Nano devices, for their small monochrome screens.
+    Bagl,
+    /// The newer library of the touchscreen devices.
+    Nbgl,
+}
+
+// The identifiers of a model, as reported by the device and on USB.
+struct ModelIds {
+    model: DeviceModel,
+    // The bits of the target id of the Secure Element identifying the model. The Ledger API and HSM
+    // know firmwares and apps by target id.
+    target_id_prefix: u32,
+    // The high byte of the USB product id when running the firmware. The low byte tells the
+    // interfaces it exposes.
+    product_id_prefix: u16,
+    // The USB product id in bootloader mode.
+    bootloader_product_id: u16,
+}
+
+// The identifiers of every model. Recognizing a new model, including for firmware updates, only
+// takes an entry here.
+// https://github.com/LedgerHQ/ledger-live/blob/dcbda65e65ead4014e767778da6022b78d8eddad/libs/ledgerjs/packages/devices/src/index.ts#L3-L156
+const MODEL_IDS: [ModelIds; 5] = [
+    ModelIds {
+        model: DeviceModel::NanoS,
+        target_id_prefix: 0x3110_0000,
+        product_id_prefix: 0x10,
+        bootloader_product_id: 0x0001,
+    },
+    ModelIds {
+        model: DeviceModel::NanoX,
+        target_id_prefix: 0x3300_0000,
+        product_id_prefix: 0x40,
+        bootloader_product_id: 0x0004,
+    },
+    ModelIds {
+        model: DeviceModel::NanoSPlus,
+        target_id_prefix: 0x3310_0000,
+        product_id_prefix: 0x50,
+        bootloader_product_id: 0x0005,
+    },
+    ModelIds {
+        model: DeviceModel::Stax,
+        target_id_prefix: 0x3320_0000,
+        product_id_prefix: 0x60,
+        bootloader_product_id: 0x0006,
+    },
+    ModelIds {
+        model: DeviceModel::Flex,
+        target_id_prefix: 0x3330_0000,
+        product_id_prefix: 0x70,
+        bootloader_product_id: 0x0007,
+    },
+];
+
+/// A model of Ledger device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DeviceModel {
+    NanoS,
+    NanoX,
+    NanoSPlus,
+    Stax,
+    Flex,
+}
+
+impl DeviceModel {
+    /// All the known models.
+    pub const ALL: [DeviceModel; 5] = [
+        Self::NanoS,
+        Self::NanoX,
+        Self::NanoSPlus,
+        Self::Stax,
+        Self::Flex,
+    ];
+
+    /// Get the model of a device from its target id, as returned by the device. `None` for unknown
+    /// models.
+    pub fn from_target_id(target_id: u32) -> Option<Self> {
+        MODEL_IDS
+            .iter()
+            .find(|ids| ids.target_id_prefix == target_id & 0xffff_0000)
+            .map(|ids| ids.model)
+    }
+
+    /// Get the model of a device from its USB product id, whether it runs its firmware or is in
+    /// bootloader mode. `None` for unknown models.
+    pub fn from_product_id(product_id: u16) -> Option<Self> {
+        MODEL_IDS
+            .iter()
+            .find(|ids| ids.product_id_prefix == product_id >> 8)
+            .map(|ids| ids.model)
+            .or_else(|| Self::from_bootloader_product_id(product_id))
+    }
+
+    /// The commercial name of this model.
+    pub fn name(&self) -> &str {
+        match self {
+            Self::NanoS => "Ledger Nano S",
+            Self::NanoX => "Ledger Nano X",
+            Self::NanoSPlus => "Ledger Nano S Plus",
+            Self::Stax => "Ledger Stax",
+            Self::Flex => "Ledger Flex",
+        }
+    }
+
+    /// Whether this model has a touchscreen, on which actions are confirmed by tapping rather than
+    /// by pressing both buttons.
+    pub fn has_touchscreen(&self) -> bool {
+        matches!(self, Self::Stax | Self::Flex)
+    }
+
+    /// Whether this model can connect over Bluetooth.
+    pub fn has_bluetooth(&self) -> bool {
+        matches!(self, Self::NanoX | Self::Stax | Self::Flex)
+    }
+
+    /// The size in pixels (width, height) of the screen of this model, for the touchscreen models.
+    pub fn screen_size(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::Stax => Some((400, 672)),
+            Self::Flex => Some((480, 600)),
+            Self::NanoS | Self::NanoX | Self::NanoSPlus => None,
+        }
+    }
+
+    /// Whether the picture shown while this model is locked can be customized. See
+    /// [`set_lock_screen`].
+    pub fn has_custom_lock_screen(&self) -> bool {
+        matches!(self, Self::Stax | Self::Flex)
+    }
+
+    /// Whether this model has a battery, and can therefore be used unplugged.
+    pub fn has_battery(&self) -> bool {
+        matches!(self, Self::NanoX | Self::Stax | Self::Flex)
+    }
+
+    /// The size of the storage of this model, in bytes. Part of it is used by the firmware.
+    pub fn flash_size(&self) -> u64 {
+        match self {
+            Self::NanoS => 320 * 1024,
+            Self::NanoX => 2 * 1024 * 1024,
+            Self::NanoSPlus | Self::Stax | Self::Flex => 1533 * 1024,
+        }
+    }
+
+    /// Roughly how many apps fit on this model, as advertised by Ledger. The actual number depends
+    /// on the size of the apps.
+    pub fn max_app_count_estimate(&self) -> u32 {
+        match self {
+            Self::NanoS => 3,
+            Self::NanoX | Self::NanoSPlus | Self::Stax | Self::Flex => 100,
+        }
+    }
+
+    /// The size of a storage block on this model, in bytes. The storage used by an installed app is
+    /// reported by the device as a number of blocks.
+    pub fn block_size(&self) -> u64 {
+        match self {
+            Self::NanoS | Self::NanoX => 4 * 1024,
+            Self::NanoSPlus | Self::Stax | Self::Flex => 32,
+        }
+    }
+
+    /// The generation of the user interface of this model.
+    pub fn ui_generation(&self) -> UiGeneration {
+        match self {
+            Self::NanoS | Self::NanoX | Self::NanoSPlus => UiGeneration::Bagl,
+            Self::Stax | Self::Flex => UiGeneration::Nbgl,
+        }
+    }
+
+    /// Get the model of a device in bootloader mode from its USB product id, for instance when the
+    /// device doesn't answer. `None` if the device isn't in bootloader mode or of an unknown
+    /// model. Nano S running a firmware older than 1.6 also use this product id.
+    pub fn from_bootloader_product_id(product_id: u16) -> Option<Self> {
+        MODEL_IDS
+            .iter()
+            .find(|ids| ids.bootloader_product_id == product_id)
+            .map(|ids| ids.model)
+    }
+}
+
+impl fmt::Display for DeviceModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// The masks of the flags of the first byte reported by the device in its version.
+// https://github.com/LedgerHQ/ledger-live/blob/dd1d17fd3ce7ed42558204b2f93707fb9b1599de/libs/device-core/src/commands/use-cases/parseGetVersionResponse.ts
+const RECOVERY_MODE_FLAG: u8 = 0x01;
+const ONBOARDED_FLAG: u8 = 0x04;
+const MANAGER_ALLOWED_FLAG: u8 = 0x08;
+const PIN_VALIDATED_FLAG: u8 = 0x80;
+
+/// The state of the settings of a device, as reported by the device along with its version. The
+/// other settings, such as the auto-lock delay, can't be read by the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DeviceSettings {
+    /// A seed was set up (or restored) on the device.
+    pub onboarded: bool,
+    /// The PIN was entered since the device was plugged in.
+    pub pin_validated: bool,
+    /// The Ledger manager is allowed on the device for this session.
+    pub manager_allowed: bool,
+    /// The device was started in recovery mode.
+    pub recovery_mode: bool,
 }
 
 /// Information queried from a Ledger device.
 // NOTE: MCU target id is always == target_id in Ledger Live
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DeviceInfo {
     pub target_id: u32,
     pub version: String,
@@ -120,26 +871,32 @@ pub struct DeviceInfo {
     pub se_version: Option<String>,
     pub se_target_id: u32,
     pub mcu_version: Option<String>,
+    /// The version of the MCU bootloader. Outside of bootloader mode, only reported by the recent
+    /// firmwares of the models other than the Nano S.
+    pub mcu_bootloader_version: Option<String>,
+    /// The revision of the hardware, if reported by the device.
+    pub hardware_version: Option<u8>,
 }
 
 impl DeviceInfo {
     /// Query information about this device.
-    ///
-    /// Adapted from https://github.com/LedgerHQ/ledger-live/blob/dd1d17fd3ce7ed42558204b2f93707fb9b1599de/libs/device-core/src/commands/use-cases/parseGetVersionResponse.ts
-    pub fn new(ledger_api: &TransportNativeHID) -> Result<Self, Box<dyn error::Error>> {
+    pub fn new(ledger_api: &dyn LedgerTransport) -> Result<Self, Error> {
         let ver_answer = ledger_api.exchange(&GET_VERSION_COMMAND)?;
-        let ret = ver_answer.retcode();
-        if ret == StatusCode::LockedDevice as u16 {
-            return Err("Device is locked.".into());
-        } else if ret != StatusCode::OK as u16 {
-            return Err(format!("Device isn't ready. Return code: {}.", ret).into());
+        let status = StatusWord::from(ver_answer.retcode());
+        if !status.is_ok() {
+            return Err(dashboard_error(ledger_api, status));
         }
+        Self::from_version_response(ver_answer.data())
+    }
 
-        let data = ver_answer.data();
+    /// Parse the data of the response of the device to the get version command.
+    ///
+    /// Adapted from https://github.com/LedgerHQ/ledger-live/blob/dd1d17fd3ce7ed42558204b2f93707fb9b1599de/libs/device-core/src/commands/use-cases/parseGetVersionResponse.ts
+    pub fn from_version_response(data: &[u8]) -> Result<Self, Error> {
         let mut i = 0;
 
         if data.len() < 5 {
-            return Err("Not enough data".into());
+            return Err(Error::InvalidResponse("not enough data"));
         }
         let target_id = u32::from_be_bytes(data[i..i + 4].try_into()?);
         i += 4;
@@ -147,7 +904,7 @@ impl DeviceInfo {
         i += 1;
 
         if data.len() < i + raw_ver_len + 1 {
-            return Err("Not enough data".into());
+            return Err(Error::InvalidResponse("not enough data"));
         }
         let raw_ver = &data[i..i + raw_ver_len];
         i += raw_ver_len;
@@ -156,7 +913,7 @@ impl DeviceInfo {
         i += 1;
 
         if data.len() < i + flags_len {
-            return Err("Not enough data".into());
+            return Err(Error::InvalidResponse("not enough data"));
         }
         let flags = &data[i..i + flags_len];
         i += flags_len;
@@ -164,32 +921,32 @@ impl DeviceInfo {
         let is_bootloader = (target_id & 4026531840) != 805306368;
         Ok(if is_bootloader {
             if data.len() < i + 1 {
-                return Err("Not enough data".into());
+                return Err(Error::InvalidResponse("not enough data"));
             }
             let part1_len = data[i] as usize;
             i += 1;
 
             if data.len() < i + part1_len {
-                return Err("Not enough data".into());
+                return Err(Error::InvalidResponse("not enough data"));
             }
             let part1 = &data[i..i + part1_len];
             i += part1_len;
 
             if part1_len >= 5 {
-                let se_version = str::from_utf8(part1).unwrap();
+                let se_version = str::from_utf8(part1)?;
 
                 if data.len() < i + 1 {
-                    return Err("Not enough data".into());
+                    return Err(Error::InvalidResponse("not enough data"));
                 }
                 let part2_len = data[i] as usize;
                 i += 1;
 
                 if data.len() < i + part2_len {
-                    return Err("Not enough data".into());
+                    return Err(Error::InvalidResponse("not enough data"));
                 }
                 let part2 = &data[i..i + part2_len];
                 //i += part2_len;
-                let se_target_id = u32::from_be_bytes(part2.try_into().unwrap());
+                let se_target_id = u32::from_be_bytes(part2.try_into()?);
 
                 Self {
                     target_id,
@@ -199,9 +956,11 @@ impl DeviceInfo {
                     se_version: Some(se_version.to_string()),
                     se_target_id,
                     mcu_version: None,
+                    mcu_bootloader_version: Some(version.to_string()),
+                    hardware_version: None,
                 }
             } else {
-                let se_target_id = u32::from_be_bytes(part1.try_into().unwrap());
+                let se_target_id = u32::from_be_bytes(part1.try_into()?);
 
                 Self {
                     target_id,
@@ -211,26 +970,43 @@ impl DeviceInfo {
                     se_version: None,
                     se_target_id,
                     mcu_version: None,
+                    mcu_bootloader_version: Some(version.to_string()),
+                    hardware_version: None,
                 }
             }
         } else {
             if data.len() < i + 1 {
-                return Err("Not enough data".into());
+                return Err(Error::InvalidResponse("not enough data"));
             }
             let mcu_len = data[i] as usize;
             i += 1;
 
             if data.len() < i + mcu_len {
-                return Err("Not enough data".into());
+                return Err(Error::InvalidResponse("not enough data"));
             }
             let mcu = &data[i..i + mcu_len];
-            //i += mcu_len;
-            let mcu = if mcu[mcu.len() - 1] == 0 {
-                &mcu[..mcu.len() - 1]
-            } else {
-                mcu
+            i += mcu_len;
+            let mcu = mcu.strip_suffix(&[0]).unwrap_or(mcu);
+            let mcu_version = str::from_utf8(mcu)?;
+
+            // Recent firmwares append the version of the MCU bootloader (except on the Nano S),
+            // then the hardware version, each prefixed with its length. Older ones don't.
+            let mut next_field = || {
+                let len = *data.get(i)? as usize;
+                let field = data.get(i + 1..i + 1 + len)?;
+                i += 1 + len;
+                Some(field)
             };
-            let mcu_version = str::from_utf8(mcu).unwrap();
+            let mcu_bootloader_version =
+                if DeviceModel::from_target_id(target_id) == Some(DeviceModel::NanoS) {
+                    None
+                } else {
+                    next_field()
+                        .map(|f| f.strip_suffix(&[0]).unwrap_or(f))
+                        .and_then(|f| str::from_utf8(f).ok())
+                        .map(str::to_string)
+                };
+            let hardware_version = next_field().and_then(|f| f.last().copied());
 
             //let osu_str = b"-osu";
             //if raw_ver.windows(osu_str.len()).any(|w| w == osu_str) {}
@@ -244,16 +1020,99 @@ impl DeviceInfo {
                 se_version: Some(version.to_string()),
                 se_target_id: target_id,
                 mcu_version: Some(mcu_version.to_string()),
+                mcu_bootloader_version,
+                hardware_version,
             }
         })
     }
+
+    /// Information about a device of this model (target id) running this firmware version, for
+    /// instance to query the catalog without a device connected.
+    pub fn for_model(target_id: u32, version: impl Into<String>) -> Self {
+        let version = version.into();
+        Self {
+            target_id,
+            version: version.clone(),
+            flags: Vec::new(),
+            is_bootloader: false,
+            se_version: Some(version),
+            se_target_id: target_id,
+            mcu_version: None,
+            mcu_bootloader_version: None,
+            hardware_version: None,
+        }
+    }
+
+    /// Whether this device runs a firmware updater (OSU) rather than a final firmware, as happens
+    /// when a firmware update was interrupted.
+    pub fn is_osu(&self) -> bool {
+        !self.is_bootloader && self.version.ends_with("-osu")
+    }
+
+    /// The model of this device. `None` for unknown models.
+    pub fn model(&self) -> Option<DeviceModel> {
+        DeviceModel::from_target_id(self.target_id)
+    }
+
+    /// Whether this device is a Ledger Nano S, whose storage is very limited.
+    pub fn is_nano_s(&self) -> bool {
+        self.model() == Some(DeviceModel::NanoS)
+    }
+
+    /// The size of a storage block on this device, in bytes. The storage used by an installed app
+    /// is reported by the device as a number of blocks.
+    pub fn block_size(&self) -> u64 {
+        self.model().map(|m| m.block_size()).unwrap_or(32)
+    }
+
+    /// The size of the storage of this device, in bytes. Part of it is used by the firmware.
+    /// `None` for unknown models.
+    pub fn memory_size(&self) -> Option<u64> {
+        self.model().map(|m| m.flash_size())
+    }
+
+    /// The settings of this device, from the flags it reported. `None` in bootloader mode or if the
+    /// device reported no flags (as older firmwares do).
+    pub fn settings(&self) -> Option<DeviceSettings> {
+        let flags = *self.flags.first().filter(|_| !self.is_bootloader)?;
+        Some(DeviceSettings {
+            onboarded: flags & ONBOARDED_FLAG != 0,
+            pin_validated: flags & PIN_VALIDATED_FLAG != 0,
+            manager_allowed: flags & MANAGER_ALLOWED_FLAG != 0,
+            recovery_mode: flags & RECOVERY_MODE_FLAG != 0,
+        })
+    }
+}
+
+/// How often [`wait_unlocked`] checks whether the device was unlocked.
+pub const UNLOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wait for the device to be unlocked with its PIN, for at most `timeout`. Returns the information
+/// about the device once unlocked (right away if it isn't locked), or [`Error::LockedDevice`] if
+/// it's still locked after the timeout.
+pub fn wait_unlocked(
+    ledger_api: &dyn LedgerTransport,
+    timeout: Duration,
+) -> Result<DeviceInfo, Error> {
+    let start = Instant::now();
+    loop {
+        match DeviceInfo::new(ledger_api) {
+            Err(Error::LockedDevice) if start.elapsed() < timeout => {
+                thread::sleep(UNLOCK_POLL_INTERVAL)
+            }
+            res => return res,
+        }
+    }
 }
 
 /// Information about an application as queried directly from the device.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InstalledApp {
     pub name: String,
+    #[cfg_attr(feature = "serde", serde(with = "hex"))]
     pub hash: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "hex"))]
     pub hash_code_data: Vec<u8>,
     pub blocks: u16,
     pub flags: u16,
@@ -273,15 +1132,96 @@ struct HsmMessage {
     pub data: Option<HsmMessageData>,
 }
 
-fn deser_apdu_command(hex_str: &str) -> Result<APDUCommand<Vec<u8>>, Box<dyn error::Error>> {
-    let bytes = hex::decode(hex_str)?;
+pub(crate) fn deser_apdu_command(hex_str: &str) -> Result<APDUCommand<Vec<u8>>, Error> {
+    apdu_command_from_bytes(&hex::decode(hex_str)?)
+}
+
+/// A channel to a Ledger device, over which APDUs are exchanged. Every function of this library
+/// talking to the device takes one, so they work the same over USB ([`TransportNativeHID`]), with
+/// the Speculos emulator ([`TransportSpeculos`]) or over any other implementation.
+pub trait LedgerTransport {
+    /// Send this command to the device and get its answer.
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error>;
+
+    /// Describe the device on the other end of the channel (for instance how it's connected), for
+    /// the user. The device itself is identified by querying it, see [`DeviceInfo::new`].
+    fn describe(&self) -> String;
+}
+
+impl dyn LedgerTransport + '_ {
+    /// Send this command, whatever holds its data, to the device and get its answer.
+    pub fn exchange<I: Deref<Target = [u8]>>(
+        &self,
+        command: &APDUCommand<I>,
+    ) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        self.exchange_apdu(&APDUCommand {
+            cla: command.cla,
+            ins: command.ins,
+            p1: command.p1,
+            p2: command.p2,
+            data: &command.data,
+        })
+    }
+}
+
+// So a transport chosen at runtime can be passed around like any other.
+impl<T: LedgerTransport + ?Sized> LedgerTransport for Box<T> {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        (**self).exchange_apdu(command)
+    }
+
+    fn describe(&self) -> String {
+        (**self).describe()
+    }
+}
+
+impl LedgerTransport for TransportNativeHID {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        Ok(self.exchange(command)?)
+    }
+
+    fn describe(&self) -> String {
+        "USB".to_string()
+    }
+}
+
+// The error for a dashboard command the device failed with this status word. An app open on the
+// device doesn't know about the dashboard commands, and answers that they aren't supported.
+pub(crate) fn dashboard_error(ledger_api: &dyn LedgerTransport, status: StatusWord) -> Error {
+    if matches!(
+        status,
+        StatusWord::ClaNotSupported | StatusWord::InsNotSupported
+    ) {
+        if let Ok(app) = current_app(ledger_api) {
+            if !app.is_dashboard() {
+                return Error::AppOpen(app.name);
+            }
+        }
+    }
+    status.into()
+}
+
+// Pass this command to the device and check it succeeded.
+pub(crate) fn exchange_checked(
+    ledger_api: &dyn LedgerTransport,
+    command: &APDUCommand<impl Deref<Target = [u8]>>,
+) -> Result<(), Error> {
+    let resp = ledger_api.exchange(command)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(dashboard_error(ledger_api, status));
+    }
+    Ok(())
+}
+
+pub(crate) fn apdu_command_from_bytes(bytes: &[u8]) -> Result<APDUCommand<Vec<u8>>, Error> {
     if bytes.len() < 5 {
-        return Err("Invalid command".into());
+        return Err(Error::InvalidCommand);
     }
 
     let (cla, ins, p1, p2, data_len) = (bytes[0], bytes[1], bytes[2], bytes[3], bytes[4] as usize);
     if bytes.len() != 5 + data_len {
-        return Err("Invalid command".into());
+        return Err(Error::InvalidCommand);
     }
 
     Ok(APDUCommand {
@@ -293,15 +1233,56 @@ fn deser_apdu_command(hex_str: &str) -> Result<APDUCommand<Vec<u8>>, Box<dyn err
     })
 }
 
+/// Send an arbitrary APDU to the device. The command must be serialized as `CLA INS P1 P2 Lc
+/// DATA`. Returns the response data and the status word.
+pub fn send_raw_apdu(
+    ledger_api: &dyn LedgerTransport,
+    apdu: &[u8],
+) -> Result<(Vec<u8>, u16), Error> {
+    let command = apdu_command_from_bytes(apdu)?;
+    let resp = ledger_api.exchange(&command)?;
+    Ok((resp.data().to_vec(), resp.retcode()))
+}
+
 /// Some actions, such as installing apps or upgrading the firmware, are done in Ledger Live by
 /// opening a socket so a remote server communicates directly with the Ledger. It appears to be
 /// talking to an HSM up there which would manage sensitive actions.
 /// Parameters are passed directly in the url. Don't forget to escape the necessary characters!
-pub fn query_via_websocket(
-    ledger_api: &TransportNativeHID,
+pub fn query_via_websocket(ledger_api: &dyn LedgerTransport, url: &str) -> Result<(), Error> {
+    query_via_websocket_with_progress(ledger_api, url, |_| {})
+}
+
+/// Like [`query_via_websocket`], calling `progress` with the fraction (between 0 and 1) of the
+/// commands sent in bulk by the HSM which were passed to the device. The bulk is where the data is
+/// written, for instance the binary of an app or a firmware.
+pub fn query_via_websocket_with_progress(
+    ledger_api: &dyn LedgerTransport,
     url: &str,
-) -> Result<(), Box<dyn error::Error>> {
-    let (mut socket, _) = tungstenite::connect(url)?;
+    mut progress: impl FnMut(f32),
+) -> Result<(), Error> {
+    let mut socket = connect_websocket(url)?;
+    let res = hsm_session(ledger_api, &mut socket, &mut progress);
+    if res.is_err() {
+        // Close the secure channel rather than leaving the HSM waiting for the device, so the
+        // operation can be run again right away.
+        let _ = socket.close(None);
+        let _ = socket.flush();
+    }
+    res
+}
+
+// Pass the commands of Ledger's HSM to the device and send it back the answers, until the HSM tells
+// the operation is over.
+fn hsm_session(
+    ledger_api: &dyn LedgerTransport,
+    socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+    progress: &mut impl FnMut(f32),
+) -> Result<(), Error> {
+    // The last error the device answered the HSM's commands with, which tells better than the HSM
+    // why the operation failed.
+    let mut device_error = None;
+    // Where the operation is at, to tell when the device is disconnected.
+    let mut stage = OperationStage::OpeningSecureChannel;
 
     // https://github.com/LedgerHQ/ledger-live/blob/99879eb5bada1ecaea7a02d8886e16b44657af6d/libs/ledger-live-common/src/socket/index.ts#L95
     loop {
@@ -318,21 +1299,25 @@ pub fn query_via_websocket(
                 if msg.query == "exchange" {
                     let command_hex = match msg.data {
                         Some(HsmMessageData::Command(h)) => h,
-                        _ => return Err("A single command is expected in 'exchange' mode.".into()),
+                        _ => {
+                            return Err(Error::Hsm(
+                                "A single command is expected in 'exchange' mode.".to_string(),
+                            ))
+                        }
                     };
                     let command = deser_apdu_command(&command_hex)?;
 
                     // NOTE: the HSM expects only the data, not the last two bytes of the raw
                     // response (the status) in the "data" field below.
-                    let resp = ledger_api.exchange(&command)?;
-                    let response = if resp.retcode() == StatusCode::OK as u16 {
+                    let resp = ledger_api
+                        .exchange(&command)
+                        .map_err(|e| e.disconnected(stage))?;
+                    let status = StatusWord::from(resp.retcode());
+                    let response = if status.is_ok() {
                         "success"
                     } else {
-                        eprintln!(
-                            "Error when installing app. Error code: {:#02x}. Resp: {:?}.",
-                            resp.retcode(),
-                            resp
-                        );
+                        log::warn!("The device answered the HSM with error status {}.", status);
+                        device_error = Some(status);
                         "error"
                     };
                     let resp_data = hex::encode(resp.data());
@@ -351,15 +1336,28 @@ pub fn query_via_websocket(
 
                     let commands = match msg.data {
                         Some(HsmMessageData::CommandList(l)) => l,
-                        _ => return Err("Expecting a list of commands in bulk mode.".into()),
+                        _ => {
+                            return Err(Error::Hsm(
+                                "Expecting a list of commands in bulk mode.".to_string(),
+                            ))
+                        }
                     };
-                    for cmd_hex in commands {
-                        if cmd_hex.is_empty() {
-                            continue;
+                    let total = commands.len();
+                    stage = OperationStage::Transferring;
+                    for (i, cmd_hex) in commands.into_iter().enumerate() {
+                        if !cmd_hex.is_empty() {
+                            let command = deser_apdu_command(&cmd_hex)?;
+                            let resp = ledger_api
+                                .exchange(&command)
+                                .map_err(|e| e.disconnected(stage))?;
+                            let status = StatusWord::from(resp.retcode());
+                            if !status.is_ok() {
+                                return Err(dashboard_error(ledger_api, status));
+                            }
                         }
-                        let command = deser_apdu_command(&cmd_hex)?;
-                        let _ = ledger_api.exchange(&command)?;
+                        progress((i + 1) as f32 / total as f32);
                     }
+                    stage = OperationStage::Completing;
 
                     let ws_resp = serde_json::json!({
                         "nonce": msg.nonce,
@@ -370,25 +1368,27 @@ pub fn query_via_websocket(
                 } else if msg.query == "success" {
                     return Ok(());
                 } else if msg.query == "error" {
-                    return Err(
-                        format!("Got an 'error' query on the ws. Full message: {}.", text).into(),
-                    );
+                    if let Some(status) = device_error {
+                        return Err(dashboard_error(ledger_api, status));
+                    }
+                    return Err(Error::Hsm(format!(
+                        "Got an 'error' query on the ws. Full message: {}.",
+                        text
+                    )));
                 } else if msg.query == "warning" {
                     eprintln!("Got a 'warning' query on the ws. Full message: {}.", text);
                 } else {
-                    return Err(format!(
+                    return Err(Error::Hsm(format!(
                         "Got an unsupported query on the ws. Full message: {}.",
                         text
-                    )
-                    .into());
+                    )));
                 }
             }
             _ => {
-                return Err(format!(
+                return Err(Error::Hsm(format!(
                     "Got an unsupported message type on the ws. Message: {:?}.",
                     msg
-                )
-                .into())
+                )))
             }
         }
     }
@@ -396,21 +1396,31 @@ pub fn query_via_websocket(
 
 /// Get a list of applications installed on this device.
 pub fn list_installed_apps_raw(
-    ledger_api: &TransportNativeHID,
-) -> Result<Vec<InstalledApp>, Box<dyn error::Error>> {
+    ledger_api: &dyn LedgerTransport,
+) -> Result<Vec<InstalledApp>, Error> {
     let mut answer = ledger_api.exchange(&LIST_APPS_COMMAND)?;
+    // A locked device, or the user refusing to allow Ledger manager, answers with no data, as if
+    // no app was installed.
+    let status = StatusWord::from(answer.retcode());
+    if !status.is_ok() {
+        return Err(dashboard_error(ledger_api, status));
+    }
     let mut data = answer.data();
 
     // See https://github.com/LedgerHQ/ledger-live/blob/99879eb5bada1ecaea7a02d8886e16b44657af6d/libs/ledger-live-common/src/hw/listApps.ts#L9
     let mut installed_apps = Vec::new();
     while !data.is_empty() {
         let mut i = 0;
-        assert_eq!(data[i], 0x01);
+        if data[i] != 0x01 {
+            return Err(Error::InvalidResponse(
+                "unexpected listApps response format",
+            ));
+        }
         i += 1;
 
         while i < data.len() {
             if data.len() < i + 1 + 2 + 2 + 32 + 32 + 1 {
-                return Err("Not enough data".into());
+                return Err(Error::InvalidResponse("not enough data"));
             }
 
             let len = data[i] as usize;
@@ -427,10 +1437,10 @@ pub fn list_installed_apps_raw(
             i += 1;
 
             if data.len() < i + name_len {
-                return Err("Not enough data".into());
+                return Err(Error::InvalidResponse("not enough data"));
             }
             if len != name_len + 70 {
-                return Err("Invalid listApps length data.".into());
+                return Err(Error::InvalidResponse("invalid listApps length data"));
             }
             let name = str::from_utf8(&data[i..i + name_len])?.to_string();
             i += name_len;
@@ -451,45 +1461,146 @@ pub fn list_installed_apps_raw(
     Ok(installed_apps)
 }
 
-/// Get the metadata of the applications installed on the device. This calls the Ledger API, to
-/// only query the data available from the device see `list_installed_apps_raw`.
+/// An application installed on the device, along with its metadata from the Ledger API if the API
+/// knows about it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InstalledAppDetails {
+    pub name: String,
+    /// The version of the app, if known by the Ledger API.
+    pub version: Option<String>,
+    #[cfg_attr(feature = "serde", serde(with = "hex"))]
+    pub hash: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(with = "hex"))]
+    pub hash_code_data: Vec<u8>,
+    pub flags: u16,
+    /// The number of storage blocks used by the app on the device.
+    pub blocks: u16,
+    /// The size of the app binary, if known by the Ledger API.
+    pub bytes: Option<u64>,
+    /// The metadata of the app, if known by the Ledger API.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub info: Option<AppInfo>,
+}
+
+impl InstalledAppDetails {
+    fn new(app: InstalledApp, info: Option<AppInfo>) -> Self {
+        Self {
+            name: app.name,
+            version: info.as_ref().map(|i| i.version.clone()),
+            hash: app.hash,
+            hash_code_data: app.hash_code_data,
+            flags: app.flags,
+            blocks: app.blocks,
+            bytes: info.as_ref().and_then(|i| i.bytes),
+            info,
+        }
+    }
+}
+
+impl fmt::Display for InstalledAppDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some(ref version) => write!(f, "{} {}", self.name, version)?,
+            None => write!(f, "{} (unknown version)", self.name)?,
+        }
+        match self.bytes {
+            Some(bytes) => write!(f, ", {} bytes", bytes),
+            None => write!(f, ", {} blocks", self.blocks),
+        }
+    }
+}
+
+/// How the storage of a device is used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MemoryInfo {
+    /// The size of the storage of the device, in bytes.
+    pub total: u64,
+    /// The storage used by the installed apps, in bytes.
+    pub used: u64,
+    /// The storage left for installing apps, in bytes. This is an upper bound: the device doesn't
+    /// report the storage used by its firmware.
+    pub free: u64,
+    /// The number of apps installed, libraries included. Each of them uses a slot.
+    pub app_slots: usize,
+}
+
+impl MemoryInfo {
+    // Compute the storage used on this device by these installed apps. `None` if the storage size
+    // of this model is unknown.
+    fn new(device_info: &DeviceInfo, installed: &[InstalledApp]) -> Option<Self> {
+        let total = device_info.memory_size()?;
+        let used = installed
+            .iter()
+            .map(|a| u64::from(a.blocks) * device_info.block_size())
+            .sum();
+        Some(Self {
+            total,
+            used,
+            free: total.saturating_sub(used),
+            app_slots: installed.len(),
+        })
+    }
+}
+
+/// Query how the storage of this device is used. The device doesn't report it directly: like
+/// Ledger Live we sum the blocks used by each installed app, as listed by the device, and compare
+/// it to the storage size of the model. `None` if the storage size of this model is unknown.
+pub fn device_memory(ledger_api: &dyn LedgerTransport) -> Result<Option<MemoryInfo>, Error> {
+    let device_info = DeviceInfo::new(ledger_api)?;
+    let installed = list_installed_apps_raw(ledger_api)?;
+    Ok(MemoryInfo::new(&device_info, &installed))
+}
+
+/// Get the applications installed on the device along with their metadata. This calls the Ledger
+/// API, to only query the data available from the device see `list_installed_apps_raw`.
 pub fn list_installed_apps(
-    ledger_api: &TransportNativeHID,
-) -> Result<Vec<Option<AppInfo>>, Box<dyn error::Error>> {
-    let hashes = list_installed_apps_raw(ledger_api)?
-        .into_iter()
-        .map(|a| a.hash)
-        .collect::<Vec<_>>();
-    if hashes.is_empty() {
+    ledger_api: &dyn LedgerTransport,
+) -> Result<Vec<InstalledAppDetails>, Error> {
+    let installed = list_installed_apps_raw(ledger_api)?;
+    if installed.is_empty() {
         return Ok(Vec::new());
     }
-    apps_by_hashes(hashes)
+    let hashes = installed.iter().map(|a| a.hash.clone()).collect();
+    let infos = apps_by_hashes(hashes)?;
+    // The API returns the elements in the same order as the hashes, with null for unknown apps.
+    Ok(installed
+        .into_iter()
+        .zip(infos.into_iter().chain(std::iter::repeat(None)))
+        .map(|(app, info)| InstalledAppDetails::new(app, info))
+        .collect())
 }
 
 /// Get the installed app, if any.
 pub fn app_installed(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     app: LedgerApp,
-) -> Result<Option<InstalledApp>, Box<dyn error::Error>> {
-    let lowercase_app_name = app.app_name();
+) -> Result<Option<InstalledApp>, Error> {
+    installed_app_by_name(ledger_api, app.app_name())
+}
+
+// Get the installed app with this name (case-insensitive), if any.
+fn installed_app_by_name(
+    ledger_api: &dyn LedgerTransport,
+    name: &str,
+) -> Result<Option<InstalledApp>, Error> {
+    let name = name.to_lowercase();
     Ok(list_installed_apps_raw(ledger_api)?
         .into_iter()
-        .find(|a| a.name.to_lowercase() == lowercase_app_name))
+        .find(|a| a.name.to_lowercase() == name))
 }
 
 /// Whether the app is installed on this device.
-pub fn is_app_installed(
-    ledger_api: &TransportNativeHID,
-    app: LedgerApp,
-) -> Result<bool, Box<dyn error::Error>> {
+pub fn is_app_installed(ledger_api: &dyn LedgerTransport, app: LedgerApp) -> Result<bool, Error> {
     Ok(app_installed(ledger_api, app)?.is_some())
 }
 
 /// Get the installed Bitcoin app, if any. Set `is_testnet` to look for the testnet Bitcoin app.
 pub fn bitcoin_app_installed(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     is_testnet: bool,
-) -> Result<Option<InstalledApp>, Box<dyn error::Error>> {
+) -> Result<Option<InstalledApp>, Error> {
     let app = if is_testnet {
         LedgerApp::BitcoinTest
     } else {
@@ -500,70 +1611,200 @@ pub fn bitcoin_app_installed(
 
 /// Whether the Bitcoin app is installed on this device.
 pub fn is_bitcoin_app_installed(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     is_testnet: bool,
-) -> Result<bool, Box<dyn error::Error>> {
+) -> Result<bool, Error> {
     Ok(bitcoin_app_installed(ledger_api, is_testnet)?.is_some())
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct DeviceVersion {
     pub id: i64,
 }
 
 #[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct FirmwareInfo {
     pub perso: String,
 }
 
 impl FirmwareInfo {
-    pub fn from_device(device_info: &DeviceInfo) -> Self {
-        let dev_ver_resp = minreq::Request::new(
+    pub fn from_device(device_info: &DeviceInfo) -> Result<Self, Error> {
+        let dev_ver_resp = http_request(
             minreq::Method::Post,
-            &format!("{}/get_device_version", BASE_API_V1_URL),
+            format!("{}/get_device_version", api_url()),
         )
         .with_param("livecommonversion", LIVE_COMMON_VERSION)
         .with_json(&serde_json::json!({
-        "provider": PROVIDER,
+        "provider": provider(),
         "target_id": device_info.target_id,
-        }))
-        .unwrap()
-        .send()
-        .unwrap();
-        let device_version = dev_ver_resp.json::<DeviceVersion>().unwrap();
+        }))?
+        .send_cached()?;
+        let device_version = dev_ver_resp.json::<DeviceVersion>()?;
 
-        let firm_resp = minreq::Request::new(
+        let firm_resp = http_request(
             minreq::Method::Post,
-            &format!("{}/get_firmware_version", BASE_API_V1_URL),
+            format!("{}/get_firmware_version", api_url()),
         )
         .with_param("livecommonversion", LIVE_COMMON_VERSION)
         .with_json(&serde_json::json!({
-        "provider": PROVIDER,
+        "provider": provider(),
         "device_version": device_version.id,
         "version_name": &device_info.version,
-        }))
-        .unwrap()
-        .send()
-        .unwrap();
-        firm_resp.json::<FirmwareInfo>().unwrap()
+        }))?
+        .send_cached()?;
+        Ok(firm_resp.json::<FirmwareInfo>()?)
     }
 }
 
+// The numeric components of a version such as "2.30", for comparisons. A pre-release suffix such
+// as "-rc1" is ignored, and so are trailing zeros, "2.1" being the same version as "2.1.0".
+pub(crate) fn version_key(version: &str) -> Vec<u64> {
+    let mut key: Vec<u64> = version
+        .split(['.', '-'])
+        .map_while(|part| part.parse().ok())
+        .collect();
+    while key.last() == Some(&0) {
+        key.pop();
+    }
+    key
+}
+
+// Whether installing this version over the installed one would be a downgrade.
+pub(crate) fn is_downgrade(target: &str, installed: &str) -> bool {
+    version_key(target) < version_key(installed)
+}
+
+/// Get the name of the latest firmware version available for this device, if there is a newer one
+/// than the version it runs.
+// Adapted from https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/manager/api.ts
+pub fn latest_firmware(device_info: &DeviceInfo) -> Result<Option<String>, Error> {
+    Ok(firmware::latest_osu(device_info)?.map(|osu| osu.name))
+}
+
+/// Whether a firmware update is available for this device. Always `false` for a device in
+/// bootloader mode, whose firmware version isn't known.
+pub fn is_firmware_update_available(device_info: &DeviceInfo) -> Result<bool, Error> {
+    if device_info.is_bootloader {
+        return Ok(false);
+    }
+    Ok(latest_firmware(device_info)?.is_some())
+}
+
+/// The versions of the firmware components of a device, along with the latest firmware update
+/// available for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FirmwareVersions {
+    /// Whether the device is in bootloader mode.
+    pub is_bootloader: bool,
+    /// The version of the Secure Element firmware, if reported.
+    pub se_version: Option<String>,
+    /// The version of the MCU firmware, if reported. Not reported in bootloader mode.
+    pub mcu_version: Option<String>,
+    /// The version of the MCU bootloader. Only reported in bootloader mode.
+    pub bootloader_version: Option<String>,
+    /// The name of the latest firmware update (OSU) available for the device, if any.
+    pub latest_osu: Option<String>,
+    /// The version of the firmware the latest update installs, if any.
+    pub latest_version: Option<String>,
+}
+
+/// Get the versions of the firmware components of this device and the latest firmware update
+/// available for it.
+// See https://github.com/LedgerHQ/ledger-live/blob/dd1d17fd3ce7ed42558204b2f93707fb9b1599de/libs/device-core/src/commands/use-cases/parseGetVersionResponse.ts
+pub fn get_firmware_info(ledger_api: &dyn LedgerTransport) -> Result<FirmwareVersions, Error> {
+    firmware_status(&DeviceInfo::new(ledger_api)?)
+}
+
+/// Get the versions of the firmware components of this device and the latest firmware update
+/// available for its model from the Ledger API. Nothing is installed, so this can be used to
+/// decide whether to update.
+pub fn firmware_status(device_info: &DeviceInfo) -> Result<FirmwareVersions, Error> {
+    // In bootloader mode, the version reported is the one of the MCU bootloader.
+    let (bootloader_version, latest_osu) = if device_info.is_bootloader {
+        (Some(device_info.version.clone()), None)
+    } else {
+        (None, latest_firmware(device_info)?)
+    };
+    let latest_version = latest_osu
+        .as_ref()
+        .map(|name| name.trim_end_matches("-osu").to_string());
+    Ok(FirmwareVersions {
+        is_bootloader: device_info.is_bootloader,
+        se_version: device_info.se_version.clone(),
+        mcu_version: device_info.mcu_version.clone(),
+        bootloader_version,
+        latest_osu,
+        latest_version,
+    })
+}
+
 /// Supported Ledger applications
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum LedgerApp {
     Bitcoin,
     BitcoinTest,
     Solana,
+    Ethereum,
+    /// Built on the legacy Bitcoin app. On some models it depends on the "Bitcoin Legacy" library
+    /// app, which the catalog lists as its dependency.
+    Litecoin,
+    /// Named "Cardano ADA" in the catalog and on the device.
+    Cardano,
+    Cosmos,
+    /// The generic Polkadot app, which replaced the Polkadot-only app in the catalog under the same
+    /// name.
+    Polkadot,
+    /// One of the largest apps, see [`InstallErr::NotEnoughSpace`].
+    Monero,
+    /// The XRP Ledger (Ripple) app.
+    Xrp,
+    /// Ledger's swap app. It needs the apps of the currencies being swapped, see
+    /// [`AppRegistry::with_plugins`].
+    Exchange,
 }
 
 impl LedgerApp {
+    /// All the supported applications.
+    pub const ALL: [LedgerApp; 11] = [
+        Self::Bitcoin,
+        Self::BitcoinTest,
+        Self::Solana,
+        Self::Ethereum,
+        Self::Litecoin,
+        Self::Cardano,
+        Self::Cosmos,
+        Self::Polkadot,
+        Self::Monero,
+        Self::Xrp,
+        Self::Exchange,
+    ];
+
+    /// Get the supported app corresponding to this name, as it appears in the Ledger catalog or on
+    /// the device, or to one of its aliases in the [`registry`]. The comparison is
+    /// case-insensitive.
+    pub fn from_app_name(name: &str) -> Option<Self> {
+        let name = registry().resolve(name).to_lowercase();
+        Self::ALL.into_iter().find(|app| app.app_name() == name)
+    }
+
     /// Get the app name as it appears in the Ledger catalog (lowercase)
     pub fn app_name(&self) -> &str {
         match self {
             Self::Bitcoin => "bitcoin",
             Self::BitcoinTest => "bitcoin test",
             Self::Solana => "solana",
+            Self::Ethereum => "ethereum",
+            Self::Litecoin => "litecoin",
+            Self::Cardano => "cardano ada",
+            Self::Cosmos => "cosmos",
+            Self::Polkadot => "polkadot",
+            Self::Monero => "monero",
+            Self::Xrp => "xrp",
+            Self::Exchange => "exchange",
         }
     }
 
@@ -573,6 +1814,14 @@ impl LedgerApp {
             Self::Bitcoin => b"Bitcoin",
             Self::BitcoinTest => b"Bitcoin Test",
             Self::Solana => b"Solana",
+            Self::Ethereum => b"Ethereum",
+            Self::Litecoin => b"Litecoin",
+            Self::Cardano => b"Cardano ADA",
+            Self::Cosmos => b"Cosmos",
+            Self::Polkadot => b"Polkadot",
+            Self::Monero => b"Monero",
+            Self::Xrp => b"XRP",
+            Self::Exchange => b"Exchange",
         }
     }
 
@@ -584,6 +1833,7 @@ impl LedgerApp {
 
 /// Information about an application as queried from the Ledger API (not the Ledger device).
 #[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct AppInfo {
     #[serde(rename = "versionName")]
     pub version_name: String,
@@ -591,12 +1841,28 @@ pub struct AppInfo {
     pub version_id: u32,
     pub version: String,
     pub perso: String,
+    /// The name of the script to run to uninstall the app.
+    #[serde(default)]
+    pub delete: String,
     #[serde(rename = "deleteKey")]
     pub delete_key: String,
     pub firmware: String,
     #[serde(rename = "firmwareKey")]
     pub firmware_key: String,
     pub hash: String,
+    /// The size of the app binary, in bytes. Only returned by the v2 API.
+    #[serde(default)]
+    pub bytes: Option<u64>,
+    /// The name of the library app this app depends on, if any. Only returned by the v2 API.
+    #[serde(rename = "parentName", default)]
+    pub parent_name: Option<String>,
+    /// The description of this version of the app, if any. Only returned by the v2 API.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// A warning Ledger publishes about this version of the app, if any. Only returned by the v2
+    /// API.
+    #[serde(default)]
+    pub warning: Option<String>,
 }
 
 // Keep the old name as an alias for backwards compatibility
@@ -605,28 +1871,21 @@ pub type BitcoinAppInfo = AppInfo;
 // Returns a Vec of Options as some elements in the response's JSON array may be `null`.
 /// Get metadata about a list of apps identified by their hash. Elements returned seem to
 /// be in the same order as the hashes, with `None` for not found.
-pub fn apps_by_hashes(
-    hashes: Vec<Vec<u8>>,
-) -> Result<Vec<Option<AppInfo>>, Box<dyn error::Error>> {
+pub fn apps_by_hashes(hashes: Vec<Vec<u8>>) -> Result<Vec<Option<AppInfo>>, Error> {
     if hashes.is_empty() {
         let e: Vec<Option<AppInfo>> = Vec::new();
         return Ok(e);
     }
     let hashes_hex: Vec<_> = hashes.into_iter().map(|h| hex::encode(&h).into()).collect();
-    let resp_apps = minreq::Request::new(
-        minreq::Method::Post,
-        format!("{}/apps/hash", BASE_API_V2_URL),
-    )
-    .with_param("livecommonversion", LIVE_COMMON_VERSION)
-    .with_json(&serde_json::Value::Array(hashes_hex))?
-    .send()?;
+    let resp_apps = http_request(minreq::Method::Post, format!("{}/apps/hash", api_v2_url()))
+        .with_param("livecommonversion", LIVE_COMMON_VERSION)
+        .with_json(&serde_json::Value::Array(hashes_hex))?
+        .send_cached()?;
     Ok(resp_apps.json::<Vec<_>>()?.into_iter().collect())
 }
 
 // Backwards compatibility
-pub fn bitcoin_apps_by_hashes(
-    hashes: Vec<Vec<u8>>,
-) -> Result<Vec<Option<BitcoinAppInfo>>, Box<dyn error::Error>> {
+pub fn bitcoin_apps_by_hashes(hashes: Vec<Vec<u8>>) -> Result<Vec<Option<BitcoinAppInfo>>, Error> {
     apps_by_hashes(hashes)
 }
 
@@ -634,25 +1893,200 @@ pub fn bitcoin_apps_by_hashes(
 // This uses the v2 API. See for reference:
 // - https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/apps/listApps/v2.ts
 // - https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/device-core/src/managerApi/repositories/HttpManagerApiRepository.ts#L211
-pub fn latest_app(
-    device_info: &DeviceInfo,
+pub fn latest_app(device_info: &DeviceInfo, app: LedgerApp) -> Result<Option<AppInfo>, Error> {
+    latest_app_by_name(device_info, app.app_name())
+}
+
+/// The latest version of an app available for a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AppVersion {
+    pub version: String,
+    /// The hash of the app binary, as hex.
+    pub hash: String,
+}
+
+/// Get the latest version of this app available for the firmware of this device, along with the
+/// hash of its binary. This doesn't install anything, for instance to tell the user an update is
+/// available. `None` if the app isn't available for this device.
+pub fn get_latest_app_version(
     app: LedgerApp,
-) -> Result<Option<AppInfo>, Box<dyn error::Error>> {
-    let resp_apps = minreq::Request::new(
+    device_info: &DeviceInfo,
+) -> Result<Option<AppVersion>, Error> {
+    Ok(latest_app(device_info, app)?.map(|info| AppVersion {
+        version: info.version,
+        hash: info.hash,
+    }))
+}
+
+/// Query the catalog for all the apps available for this device.
+pub fn apps_by_target(device_info: &DeviceInfo) -> Result<Vec<AppInfo>, Error> {
+    apps_by_target_for_provider(device_info, provider())
+}
+
+// Query the catalog of this provider for all the apps available for this device.
+fn apps_by_target_for_provider(
+    device_info: &DeviceInfo,
+    provider: u32,
+) -> Result<Vec<AppInfo>, Error> {
+    let resp_apps = http_request(
         minreq::Method::Get,
-        format!("{}/apps/by-target", BASE_API_V2_URL),
+        format!("{}/apps/by-target", api_v2_url()),
     )
     .with_param("livecommonversion", LIVE_COMMON_VERSION)
-    .with_param("provider", PROVIDER.to_string())
+    .with_param("provider", provider.to_string())
     .with_param("target_id", device_info.target_id.to_string())
     .with_param("firmware_version_name", device_info.version.clone())
-    .send()?;
+    .send_cached()?;
+    Ok(resp_apps.json::<Vec<AppInfo>>()?)
+}
 
-    let app_name = app.app_name();
-    Ok(resp_apps
-        .json::<Vec<AppInfo>>()?
+// Get the latest app with this name (case-insensitive) from the catalog.
+fn latest_app_by_name(device_info: &DeviceInfo, name: &str) -> Result<Option<AppInfo>, Error> {
+    let name = name.to_lowercase();
+    Ok(apps_by_target(device_info)?
         .into_iter()
-        .find(|a| a.version_name.to_lowercase() == app_name))
+        .find(|a| a.version_name.to_lowercase() == name))
+}
+
+// Get the app to install according to these options from the catalog, if available.
+fn catalog_app(
+    device_info: &DeviceInfo,
+    options: &InstallOptions,
+) -> Result<Option<AppInfo>, Error> {
+    let name = options.name.to_lowercase();
+    Ok(apps_by_target_for_provider(device_info, options.provider)?
+        .into_iter()
+        .find(|a| a.version_name.to_lowercase() == name))
+}
+
+/// Get the version of the app which installing or updating it with these options would flash, for
+/// instance to show its description to the user beforehand. `None` if it isn't available for this
+/// device.
+pub fn target_app(
+    device_info: &DeviceInfo,
+    options: &InstallOptions,
+) -> Result<Option<AppInfo>, Error> {
+    catalog_app(device_info, options)
+}
+
+/// Get the chain of apps this app depends on from the catalog, starting with its direct dependency.
+/// Shared libraries (such as "Bitcoin Legacy" for some Bitcoin forks) must be present on the device
+/// for the apps depending on them to run. So must the app loading a plugin (such as the Ethereum app
+/// for the 1inch plugin), as declared in the [`registry`].
+pub fn app_dependencies(device_info: &DeviceInfo, app: &AppInfo) -> Result<Vec<AppInfo>, Error> {
+    dependency_chain(&apps_by_target(device_info)?, app)
+}
+
+// Get the chain of apps this app depends on from this catalog, starting with its direct
+// dependency.
+fn dependency_chain(catalog: &[AppInfo], app: &AppInfo) -> Result<Vec<AppInfo>, Error> {
+    let mut deps: Vec<AppInfo> = Vec::new();
+    let mut parent_name = parent_of(app);
+
+    while let Some(name) = parent_name {
+        let lowercase_name = name.to_lowercase();
+        // Guard against a cycle in the catalog data.
+        if deps
+            .iter()
+            .any(|d| d.version_name.to_lowercase() == lowercase_name)
+        {
+            return Err(Error::Catalog(format!(
+                "Dependency cycle detected on app '{}'.",
+                name
+            )));
+        }
+        let parent = catalog
+            .iter()
+            .find(|a| a.version_name.to_lowercase() == lowercase_name)
+            .ok_or_else(|| {
+                let mut chain = chain_names(app, &deps);
+                chain.push(name.clone());
+                Error::Catalog(format!(
+                    "Dependency '{}' not found in the catalog ({}).",
+                    name,
+                    chain.join(" -> ")
+                ))
+            })?;
+        parent_name = parent_of(parent);
+        deps.push(parent.clone());
+    }
+
+    Ok(deps)
+}
+
+// The name of the app this app depends on, if any: the catalog only knows about libraries, the
+// registry about the base app of plugins.
+fn parent_of(app: &AppInfo) -> Option<String> {
+    app.parent_name.clone().or_else(|| {
+        registry()
+            .base_app(&app.version_name)
+            .map(|name| name.to_string())
+    })
+}
+
+// The names of this app and of these apps it depends on, in order, to report a dependency chain.
+fn chain_names(app: &AppInfo, deps: &[AppInfo]) -> Vec<String> {
+    std::iter::once(app)
+        .chain(deps)
+        .map(|a| a.version_name.clone())
+        .collect()
+}
+
+/// An app available in the catalog for a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AppMetadata {
+    pub name: String,
+    pub version: String,
+    /// The size of the app binary, in bytes, if returned by the catalog.
+    pub bytes: Option<u64>,
+    /// The hash of the app binary, as hex.
+    pub hash: String,
+    /// The names of the library apps this app depends on, starting with its direct dependency.
+    pub dependencies: Vec<String>,
+}
+
+/// List all the apps available in the catalog for the model and firmware version of this device,
+/// for instance to let the user pick which ones to install. The catalog returns all of them at
+/// once, so this is a single query.
+pub fn available_apps(device_info: &DeviceInfo) -> Result<Vec<AppMetadata>, Error> {
+    let catalog = apps_by_target(device_info)?;
+    catalog
+        .iter()
+        .map(|app| {
+            Ok(AppMetadata {
+                name: app.version_name.clone(),
+                version: app.version.clone(),
+                bytes: app.bytes,
+                hash: app.hash.clone(),
+                dependencies: dependency_chain(&catalog, app)?
+                    .into_iter()
+                    .map(|dep| dep.version_name)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Get the names of the installed apps which are not available in the catalog for this device
+/// anymore, for instance because they were discontinued. Those may disappear after a firmware
+/// update and couldn't be reinstalled.
+pub fn discontinued_apps(
+    device_info: &DeviceInfo,
+    installed: &[AppInfo],
+) -> Result<Vec<String>, Error> {
+    let catalog = apps_by_target(device_info)?;
+    Ok(installed
+        .iter()
+        .filter(|app| {
+            let name = app.version_name.to_lowercase();
+            !catalog
+                .iter()
+                .any(|a| a.version_name.to_lowercase() == name)
+        })
+        .map(|app| app.version_name.clone())
+        .collect())
 }
 
 /// Get the Bitcoin apps information for this device from the "catalog" (as Ledger Live calls it).
@@ -663,7 +2097,7 @@ pub fn latest_app(
 // https://github.com/LedgerHQ/ledger-live/blob/99879eb5bada1ecaea7a02d8886e16b44657af6d/libs/ledger-live-common/src/manager/index.ts#L103-L104.
 pub fn get_latest_apps(
     device_info: &DeviceInfo,
-) -> Result<(Option<BitcoinAppInfo>, Option<BitcoinAppInfo>), Box<dyn error::Error>> {
+) -> Result<(Option<BitcoinAppInfo>, Option<BitcoinAppInfo>), Error> {
     let bitcoin = latest_app(device_info, LedgerApp::Bitcoin)?;
     let test = latest_app(device_info, LedgerApp::BitcoinTest)?;
     Ok((bitcoin, test))
@@ -674,7 +2108,7 @@ pub fn get_latest_apps(
 pub fn bitcoin_latest_app(
     device_info: &DeviceInfo,
     is_testnet: bool,
-) -> Result<Option<BitcoinAppInfo>, Box<dyn error::Error>> {
+) -> Result<Option<BitcoinAppInfo>, Error> {
     let app = if is_testnet {
         LedgerApp::BitcoinTest
     } else {
@@ -683,27 +2117,267 @@ pub fn bitcoin_latest_app(
     latest_app(device_info, app)
 }
 
+/// An error arising when opening an app.
+#[derive(Debug, thiserror::Error)]
+pub enum OpenAppErr {
+    /// The application is not installed.
+    #[error("The application is not installed.")]
+    NotInstalled,
+    /// The user refused to open the app on the device.
+    #[error("Opening the application was refused on the device.")]
+    UserRefused,
+    /// The device is locked. It must be unlocked with its PIN.
+    #[error("Device is locked.")]
+    LockedDevice,
+    /// Another app, with this name, is open. It must be closed to go back to the dashboard first.
+    #[error("The {0} application is already open. Close it first.")]
+    OtherAppOpen(String),
+    #[error(transparent)]
+    Any(#[from] Error),
+}
+
+impl OpenAppErr {
+    /// Whether opening the app failed because the device is locked. See
+    /// [`Error::is_locked_device`].
+    pub fn is_locked_device(&self) -> bool {
+        match self {
+            Self::LockedDevice => true,
+            Self::Any(e) => e.is_locked_device(),
+            _ => false,
+        }
+    }
+
+    /// The name of the app open on the device, if opening another one failed because of it. See
+    /// [`Error::app_open`].
+    pub fn app_open(&self) -> Option<&str> {
+        match self {
+            Self::OtherAppOpen(name) => Some(name),
+            Self::Any(e) => e.app_open(),
+            _ => None,
+        }
+    }
+}
+
 /// Open the given application on the device.
-pub fn open_app(
-    ledger_api: &TransportNativeHID,
-    app: LedgerApp,
-) -> Result<(), Box<dyn error::Error>> {
+pub fn open_app(ledger_api: &dyn LedgerTransport, app: LedgerApp) -> Result<(), OpenAppErr> {
+    open_app_by_name(ledger_api, &String::from_utf8_lossy(app.open_name()))
+}
+
+/// Open the app with this name on the device, such as "Polygon". The name must be spelled exactly
+/// as on the device, case included. This allows opening any app, not only the supported ones.
+pub fn open_app_by_name(ledger_api: &dyn LedgerTransport, name: &str) -> Result<(), OpenAppErr> {
     let mut command = OPEN_APP_COMMAND_TEMPLATE;
-    command.data = app.open_name();
+    command.data = name.as_bytes();
 
     let resp = ledger_api.exchange(&command)?;
-    if resp.retcode() != StatusCode::OK as u16 {
-        return Err(format!("Error opening app. Ledger response: {:#x?}.", resp).into());
+    // See https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/openApp.ts
+    match StatusWord::from(resp.retcode()) {
+        StatusWord::Ok => Ok(()),
+        StatusWord::AppNotFound => Err(OpenAppErr::NotInstalled),
+        StatusWord::UserRefused | StatusWord::ConditionsOfUseNotSatisfied => {
+            Err(OpenAppErr::UserRefused)
+        }
+        StatusWord::DeviceLocked => Err(OpenAppErr::LockedDevice),
+        // An app doesn't know about the dashboard commands.
+        status @ (StatusWord::ClaNotSupported | StatusWord::InsNotSupported) => {
+            Err(match dashboard_error(ledger_api, status) {
+                Error::AppOpen(name) => OpenAppErr::OtherAppOpen(name),
+                e => e.into(),
+            })
+        }
+        // Some firmware versions don't tell apart a missing app. Look for it to find out.
+        status => match installed_app_by_name(ledger_api, name) {
+            Ok(None) => Err(OpenAppErr::NotInstalled),
+            _ => Err(Error::from(status).into()),
+        },
+    }
+}
+
+/// The app currently running on a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunningApp {
+    pub name: String,
+    pub version: String,
+}
+
+impl RunningApp {
+    /// Whether this is the dashboard, that is no app is open.
+    pub fn is_dashboard(&self) -> bool {
+        // Older firmware versions report "OLOS\0".
+        self.name == "BOLOS" || self.name == "OLOS\0"
+    }
+
+    /// Whether this is this app.
+    pub fn is(&self, app: LedgerApp) -> bool {
+        self.name.as_bytes() == app.open_name()
+    }
+}
+
+/// Quit the app open on the device, to go back to the dashboard. The device then re-enumerates, so
+/// the caller must reconnect to it.
+pub fn quit_app(ledger_api: &dyn LedgerTransport) -> Result<(), Error> {
+    exchange_checked(ledger_api, &QUIT_APP_COMMAND)
+}
+
+// Query this information about the battery from the device. `None` for models without a battery.
+fn battery_query(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    info: u8,
+) -> Result<Option<Vec<u8>>, Error> {
+    if !device_info.model().is_some_and(|m| m.has_battery()) {
+        return Ok(None);
+    }
+    let mut command = GET_BATTERY_STATUS_COMMAND_TEMPLATE;
+    command.p2 = info;
+    let resp = ledger_api.exchange(&command)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
+    }
+    Ok(Some(resp.data().to_vec()))
+}
+
+/// Get the charge of the battery of the device, in percent. `None` for models without a battery,
+/// or if the device doesn't know.
+pub fn battery_level(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+) -> Result<Option<u8>, Error> {
+    // 0xff when unknown.
+    Ok(battery_query(ledger_api, device_info, BATTERY_PERCENTAGE)?
+        .and_then(|data| data.first().copied())
+        .filter(|level| *level <= 100))
+}
+
+/// How the battery of a device is being charged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ChargingMode {
+    /// It isn't.
+    None,
+    /// The device is powered over USB.
+    Usb,
+    /// Wireless charging.
+    Qi,
+}
+
+/// Get how the battery of the device is being charged. `None` for models without a battery.
+pub fn battery_charging(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+) -> Result<Option<ChargingMode>, Error> {
+    let data = match battery_query(ledger_api, device_info, BATTERY_FLAGS)? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let flags = u32::from_be_bytes(
+        data.get(..4)
+            .ok_or(Error::InvalidResponse("not enough data"))?
+            .try_into()?,
+    );
+    Ok(Some(if flags & 0x01 != 0 {
+        ChargingMode::Usb
+    } else if flags & 0x02 != 0 {
+        ChargingMode::Qi
+    } else {
+        ChargingMode::None
+    }))
+}
+
+/// The state of the battery of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BatteryStatus {
+    /// The charge, in percent. `None` if the device doesn't know.
+    pub percentage: Option<u8>,
+    /// The voltage, in millivolts.
+    pub voltage: u16,
+    pub charging: ChargingMode,
+}
+
+/// Get the state of the battery of the device. `None` for models without a battery.
+pub fn battery_status(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+) -> Result<Option<BatteryStatus>, Error> {
+    let voltage = match battery_query(ledger_api, device_info, BATTERY_VOLTAGE)? {
+        Some(data) => u16::from_be_bytes(
+            data.get(..2)
+                .ok_or(Error::InvalidResponse("not enough data"))?
+                .try_into()?,
+        ),
+        None => return Ok(None),
+    };
+    let percentage = battery_level(ledger_api, device_info)?;
+    let charging = battery_charging(ledger_api, device_info)?.unwrap_or(ChargingMode::None);
+    Ok(Some(BatteryStatus {
+        percentage,
+        voltage,
+        charging,
+    }))
+}
+
+/// Get the name of the device, as shown when connecting to it over Bluetooth. Only the models with
+/// Bluetooth have a name.
+pub fn get_device_name(ledger_api: &dyn LedgerTransport) -> Result<String, Error> {
+    let resp = ledger_api.exchange(&GET_DEVICE_NAME_COMMAND)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
     }
+    Ok(str::from_utf8(resp.data())?.to_string())
+}
 
+/// Rename the device. The user must confirm the new name on the device. Only the models with
+/// Bluetooth have a name. See [`MAX_DEVICE_NAME_LENGTH`].
+pub fn set_device_name(ledger_api: &dyn LedgerTransport, name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.len() > MAX_DEVICE_NAME_LENGTH {
+        return Err(Error::InvalidCommand);
+    }
+    let mut command = SET_DEVICE_NAME_COMMAND_TEMPLATE;
+    command.data = name.as_bytes();
+    let resp = ledger_api.exchange(&command)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
+    }
     Ok(())
 }
 
+/// Get the app currently running on the device, or the dashboard if no app is open.
+pub fn current_app(ledger_api: &dyn LedgerTransport) -> Result<RunningApp, Error> {
+    let resp = ledger_api.exchange(&GET_APP_AND_VERSION_COMMAND)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
+    }
+
+    // The format byte, then the name and the version each prefixed with their length.
+    let data = resp.data();
+    let mut fields = Vec::with_capacity(2);
+    let mut i = 1;
+    for _ in 0..2 {
+        let len = *data
+            .get(i)
+            .ok_or(Error::InvalidResponse("not enough data"))? as usize;
+        let field = data
+            .get(i + 1..i + 1 + len)
+            .ok_or(Error::InvalidResponse("not enough data"))?;
+        fields.push(str::from_utf8(field)?.to_string());
+        i += 1 + len;
+    }
+    let version = fields.pop().unwrap_or_default();
+    let name = fields.pop().unwrap_or_default();
+    Ok(RunningApp { name, version })
+}
+
 /// Open the Bitcoin application on the device.
 pub fn open_bitcoin_app(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     is_testnet: bool,
-) -> Result<(), Box<dyn error::Error>> {
+) -> Result<(), OpenAppErr> {
     let app = if is_testnet {
         LedgerApp::BitcoinTest
     } else {
@@ -712,12 +2386,150 @@ pub fn open_bitcoin_app(
     open_app(ledger_api, app)
 }
 
+/// Get the fingerprint of the master key of the seed on the device, as reported by the Bitcoin
+/// app. The (mainnet or testnet) Bitcoin app must be open.
+pub fn bitcoin_master_fingerprint(ledger_api: &dyn LedgerTransport) -> Result<[u8; 4], Error> {
+    let resp = ledger_api.exchange(&GET_MASTER_FINGERPRINT_COMMAND)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
+    }
+    Ok(resp.data().try_into()?)
+}
+
+/// Get the public key at this derivation path from the Solana app. The Solana app must be open.
+pub fn solana_pubkey(
+    ledger_api: &dyn LedgerTransport,
+    derivation_path: &[u32],
+) -> Result<[u8; 32], Error> {
+    let mut data = vec![derivation_path.len() as u8];
+    for index in derivation_path {
+        data.extend_from_slice(&index.to_be_bytes());
+    }
+    let mut command = SOLANA_GET_PUBKEY_COMMAND_TEMPLATE;
+    command.data = &data;
+
+    let resp = ledger_api.exchange(&command)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
+    }
+    Ok(resp.data().try_into()?)
+}
+
+/// Get the address of the account at this derivation path from the Ethereum app, as hex with the
+/// EIP-55 checksum casing. The Ethereum app must be open.
+pub fn ethereum_address(
+    ledger_api: &dyn LedgerTransport,
+    derivation_path: &[u32],
+) -> Result<String, Error> {
+    let mut data = vec![derivation_path.len() as u8];
+    for index in derivation_path {
+        data.extend_from_slice(&index.to_be_bytes());
+    }
+    let mut command = ETHEREUM_GET_ADDRESS_COMMAND_TEMPLATE;
+    command.data = &data;
+
+    let resp = ledger_api.exchange(&command)?;
+    let status = StatusWord::from(resp.retcode());
+    if !status.is_ok() {
+        return Err(status.into());
+    }
+    // The public key then the address, each prefixed with their length.
+    let data = resp.data();
+    let pubkey_len = *data
+        .first()
+        .ok_or(Error::InvalidResponse("not enough data"))? as usize;
+    let addr_len = *data
+        .get(1 + pubkey_len)
+        .ok_or(Error::InvalidResponse("not enough data"))? as usize;
+    let addr = data
+        .get(2 + pubkey_len..2 + pubkey_len + addr_len)
+        .ok_or(Error::InvalidResponse("not enough data"))?;
+    Ok(format!("0x{}", str::from_utf8(addr)?))
+}
+
+/// Information reported by an app once it's open, which lets the user check the device holds the
+/// expected seed (and passphrase).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PostOpenInfo {
+    /// The fingerprint of the master key, as reported by the Bitcoin app.
+    MasterFingerprint([u8; 4]),
+    /// The address of the default Solana account.
+    SolanaAddress(String),
+    /// The address of the default Ethereum account.
+    EthereumAddress(String),
+}
+
+impl fmt::Display for PostOpenInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MasterFingerprint(fg) => write!(f, "Master key fingerprint: {}", hex::encode(fg)),
+            Self::SolanaAddress(addr) => write!(f, "Default account (m/44'/501'): {}", addr),
+            Self::EthereumAddress(addr) => {
+                write!(f, "Default account (m/44'/60'/0'/0/0): {}", addr)
+            }
+        }
+    }
+}
+
+/// Run the app-specific post-open hook for this app, if it has one. The app must be open. Returns
+/// `None` for apps without a hook.
+pub fn post_open_info(
+    ledger_api: &dyn LedgerTransport,
+    app: LedgerApp,
+) -> Result<Option<PostOpenInfo>, Error> {
+    Ok(match app {
+        LedgerApp::Bitcoin | LedgerApp::BitcoinTest => Some(PostOpenInfo::MasterFingerprint(
+            bitcoin_master_fingerprint(ledger_api)?,
+        )),
+        LedgerApp::Solana => {
+            let pubkey = solana_pubkey(ledger_api, &SOLANA_DEFAULT_DERIVATION_PATH)?;
+            Some(PostOpenInfo::SolanaAddress(
+                bs58::encode(pubkey).into_string(),
+            ))
+        }
+        LedgerApp::Ethereum => Some(PostOpenInfo::EthereumAddress(ethereum_address(
+            ledger_api,
+            &ETHEREUM_DEFAULT_DERIVATION_PATH,
+        )?)),
+        // The Litecoin app speaks the legacy Bitcoin protocol, which doesn't expose the master key
+        // fingerprint.
+        LedgerApp::Litecoin => None,
+        // Cardano addresses are derived from an extended public key with a dedicated protocol, not
+        // worth implementing only to display an address.
+        LedgerApp::Cardano => None,
+        // Cosmos addresses are bech32 encodings of a hash of the public key, with a prefix which
+        // depends on the chain the user means to use.
+        LedgerApp::Cosmos => None,
+        // Polkadot addresses are SS58 encodings of the public key, with a prefix which depends on
+        // the network the user means to use.
+        LedgerApp::Polkadot => None,
+        // Monero addresses are made of a spend and a view key, which the app only gives through a
+        // protocol of its own, not worth implementing only to display an address.
+        LedgerApp::Monero => None,
+        // The XRP app would only give a public key, the address being a hash of it in a dedicated
+        // base58 alphabet, not worth implementing only to display it.
+        LedgerApp::Xrp => None,
+        // The Exchange app has no account of its own, it only calls into the currency apps.
+        LedgerApp::Exchange => None,
+    })
+}
+
 /// Check whether the Ledger device is genuine.
-pub fn genuine_check(ledger_api: &TransportNativeHID) -> Result<(), Box<dyn error::Error>> {
+pub fn genuine_check(ledger_api: &dyn LedgerTransport) -> Result<(), Error> {
     let device_info = DeviceInfo::new(ledger_api)?;
-    let firmware_info = FirmwareInfo::from_device(&device_info);
+    genuine_check_with(ledger_api, &device_info)
+}
 
-    let genuine_ws_url = UrlSerializer::new(format!("{}/genuine?", BASE_SOCKET_URL))
+pub(crate) fn genuine_check_with(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+) -> Result<(), Error> {
+    let firmware_info = FirmwareInfo::from_device(device_info)?;
+
+    let genuine_ws_url = UrlSerializer::new(format!("{}/genuine?", socket_url()))
         .append_pair("targetId", &device_info.target_id.to_string())
         .append_pair("perso", &firmware_info.perso)
         .finish();
@@ -725,22 +2537,221 @@ pub fn genuine_check(ledger_api: &TransportNativeHID) -> Result<(), Box<dyn erro
 }
 
 /// An error arising when installing an app.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum InstallErr {
     /// The application is already installed.
+    #[error("The application is already installed.")]
     AlreadyInstalled,
     /// Couldn't get info about the app.
+    #[error("Couldn't get info about the app.")]
     AppNotFound,
-    Any(Box<dyn error::Error>),
+    /// The app depends on a library app (or, for a plugin, on a base app) which isn't installed,
+    /// and the install strategy doesn't allow to install it.
+    #[error(
+        "The application depends on the '{}' app, which isn't installed ({}).",
+        .name,
+        .chain.join(" -> ")
+    )]
+    MissingDependency {
+        /// The name of the missing app.
+        name: String,
+        /// The names of the apps from the one being installed down to the missing one, for
+        /// instance `["Paraswap", "Ethereum"]`.
+        chain: Vec<String>,
+    },
+    /// The device doesn't have enough storage left for the app (and the libraries it depends on),
+    /// as estimated beforehand or as reported by the device while installing.
+    #[error("{}", not_enough_space(*.required, *.available))]
+    NotEnoughSpace {
+        /// The storage needed by the app and its libraries, in bytes.
+        required: u64,
+        /// The storage left on the device, in bytes.
+        available: u64,
+    },
+    /// The device wasn't set up yet: it has no seed. Ledger's HSM can't open a secure channel with
+    /// it.
+    #[error("The device isn't set up yet. Set up or restore a seed on it first.")]
+    NotOnboarded,
+    #[error(transparent)]
+    Any(#[from] Error),
+}
+
+impl InstallErr {
+    /// Whether the install failed because the device is locked. See [`Error::is_locked_device`].
+    pub fn is_locked_device(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_locked_device())
+    }
+
+    /// The name of the app open on the device, if the install failed because of it. See
+    /// [`Error::app_open`].
+    pub fn app_open(&self) -> Option<&str> {
+        match self {
+            Self::Any(e) => e.app_open(),
+            _ => None,
+        }
+    }
+}
+
+/// How to handle the dependencies of an app when installing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum InstallStrategy {
+    /// Install the library apps the app depends on first, if they are not already installed.
+    #[default]
+    WithDependencies,
+    /// Only install the app binary. The library apps it depends on must already be installed on
+    /// the device. This avoids surprises on devices with little storage, such as the Nano S.
+    AppOnly,
+}
+
+/// Options to install or update an app. Build it from a `LedgerApp` (or the name of any app in the
+/// catalog) and customize it, for instance
+/// `InstallOptions::new(LedgerApp::Bitcoin).force(true)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InstallOptions {
+    name: String,
+    provider: u32,
+    force: bool,
+    allow_downgrade: bool,
+    strategy: InstallStrategy,
+}
+
+impl InstallOptions {
+    /// Install the latest version of this app from the provider set (see [`provider`]).
+    pub fn new(app: LedgerApp) -> Self {
+        Self::by_name(String::from_utf8_lossy(app.open_name()))
+    }
+
+    /// Install the latest version of the app with this name in the catalog (case-insensitive),
+    /// from the provider set (see [`provider`]). This allows installing apps which aren't a `LedgerApp`.
+    pub fn by_name(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            provider: provider(),
+            force: false,
+            allow_downgrade: false,
+            strategy: InstallStrategy::default(),
+        }
+    }
+
+    /// Get the app from the catalog of this provider instead of the one set. See [`PROVIDER`].
+    pub fn provider(mut self, provider: u32) -> Self {
+        self.provider = provider;
+        self
+    }
+
+    /// Reinstall the app even if it is already installed (or already at the target version when
+    /// updating).
+    ///
+    /// The device won't install an app over an existing one, so when installing, the installed app
+    /// is uninstalled first. If installing it again then fails, for instance because the HSM can't
+    /// be reached, the app is left uninstalled and must be installed again.
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Update the app even if the version from the catalog is older than the installed one, for
+    /// instance when getting it from another provider.
+    pub fn allow_downgrade(mut self, allow_downgrade: bool) -> Self {
+        self.allow_downgrade = allow_downgrade;
+        self
+    }
+
+    /// How to handle the app's dependencies.
+    pub fn strategy(mut self, strategy: InstallStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// The app to install, if it's one of the supported apps.
+    pub fn app(&self) -> Option<LedgerApp> {
+        LedgerApp::from_app_name(&self.name)
+    }
+
+    /// The name of the app to install, as it appears in the catalog.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl From<LedgerApp> for InstallOptions {
+    fn from(app: LedgerApp) -> Self {
+        Self::new(app)
+    }
+}
+
+// The storage needed on this device to install these apps, and the storage left once the installed
+// app they replace (if any) is removed, in bytes. `None` if the storage size of this model is
+// unknown. Apps take whole blocks, which matters for large apps such as Monero on models with big
+// blocks.
+fn space_needed(
+    device_info: &DeviceInfo,
+    installed: &[InstalledApp],
+    apps: &[&AppInfo],
+    replaced: Option<&InstalledApp>,
+) -> Option<(u64, u64)> {
+    let memory = MemoryInfo::new(device_info, installed)?;
+    let block_size = device_info.block_size();
+    let mut needed = 0;
+    for app in apps {
+        match app.bytes {
+            Some(bytes) => needed += bytes.div_ceil(block_size) * block_size,
+            None => log::warn!(
+                "The catalog doesn't tell the size of the '{}' app, it may not fit on the device.",
+                app.version_name
+            ),
+        }
+    }
+    let free = memory.free
+        + replaced
+            .map(|a| u64::from(a.blocks) * block_size)
+            .unwrap_or(0);
+    Some((needed, free))
+}
+
+// The storage to free for this much to fit in what's left, in KB.
+fn missing_kb(required: u64, available: u64) -> u64 {
+    required.saturating_sub(available).div_ceil(1024)
+}
+
+// Tell the storage missing on the device. When the device reported it's full though the estimate
+// fits, how much to free can't be told.
+fn not_enough_space(required: u64, available: u64) -> String {
+    let missing = missing_kb(required, available);
+    if missing == 0 {
+        return format!(
+            "Not enough storage left on the device: {} KB needed and {} KB free were estimated, but the device reported it's full. Uninstall apps to make room.",
+            required.div_ceil(1024),
+            available / 1024
+        );
+    }
+    format!(
+        "Not enough storage left on the device: {} KB needed, {} KB free. Uninstall apps to free {} more KB.",
+        required.div_ceil(1024),
+        available / 1024,
+        missing
+    )
+}
+
+// Whether the device failed to install an app because its storage is full, though it seemed to fit:
+// the catalog may not tell the size of an app, and the device doesn't report the storage used by
+// its firmware.
+fn is_out_of_space(e: &Error) -> bool {
+    matches!(
+        e.status_word(),
+        Some(StatusWord::NotEnoughSpace | StatusWord::NotEnoughMemory)
+    )
 }
 
 fn install_app_internal(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     device_info: &DeviceInfo,
     app: &AppInfo,
-) -> Result<(), Box<dyn error::Error>> {
+) -> Result<(), Error> {
     // Make sure to properly escape the parameters in the request's parameter.
-    let install_ws_url = UrlSerializer::new(format!("{}/install?", BASE_SOCKET_URL))
+    let install_ws_url = UrlSerializer::new(format!("{}/install?", socket_url()))
         .append_pair("targetId", &device_info.target_id.to_string())
         .append_pair("perso", &app.perso)
         .append_pair("deleteKey", &app.delete_key)
@@ -751,24 +2762,127 @@ fn install_app_internal(
     query_via_websocket(ledger_api, &install_ws_url)
 }
 
-/// Install the given application on this device.
+/// Install the given application on this device. Pass `InstallOptions` instead of a `LedgerApp`
+/// to customize the installation.
 pub fn install_app(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
+    options: impl Into<InstallOptions>,
+) -> Result<(), InstallErr> {
+    let options = options.into();
+    let device_info = DeviceInfo::new(ledger_api).map_err(InstallErr::Any)?;
+    let installed = list_installed_apps_raw(ledger_api).map_err(InstallErr::Any)?;
+    install_app_with(ledger_api, &device_info, &installed, &options)
+}
+
+/// Install the app with this name in the catalog (case-insensitive), such as "Polygon". This
+/// allows installing any app, not only the supported ones.
+pub fn install_app_by_name(ledger_api: &dyn LedgerTransport, name: &str) -> Result<(), InstallErr> {
+    install_app(ledger_api, InstallOptions::by_name(name))
+}
+
+/// Install the given application on this device, handling its dependencies according to the
+/// given strategy.
+pub fn install_app_with_strategy(
+    ledger_api: &dyn LedgerTransport,
     app: LedgerApp,
+    strategy: InstallStrategy,
 ) -> Result<(), InstallErr> {
-    // First of all make sure it's not already installed.
-    if is_app_installed(ledger_api, app).map_err(InstallErr::Any)? {
+    install_app(ledger_api, InstallOptions::new(app).strategy(strategy))
+}
+
+// Refuse to install apps on a device which has no seed yet, rather than failing to open the secure
+// channel with the HSM.
+fn check_onboarded(device_info: &DeviceInfo) -> Result<(), InstallErr> {
+    if device_info.settings().is_some_and(|s| !s.onboarded) {
+        return Err(InstallErr::NotOnboarded);
+    }
+    Ok(())
+}
+
+pub(crate) fn install_app_with(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    installed: &[InstalledApp],
+    options: &InstallOptions,
+) -> Result<(), InstallErr> {
+    check_onboarded(device_info)?;
+
+    // First of all make sure it's not already installed, unless we were asked to reinstall it.
+    let is_installed = |name: &str| {
+        let name = name.to_lowercase();
+        installed.iter().any(|a| a.name.to_lowercase() == name)
+    };
+    let already_installed = installed
+        .iter()
+        .find(|a| a.name.to_lowercase() == options.name.to_lowercase());
+    if already_installed.is_some() && !options.force {
         return Err(InstallErr::AlreadyInstalled);
     }
 
-    // Get the app info, necessary for the websocket query below.
-    let device_info = DeviceInfo::new(ledger_api).map_err(InstallErr::Any)?;
-    let app_info = latest_app(&device_info, app)
-        .map_err(InstallErr::Any)?
+    // Get the app info, necessary for the websocket query below. Its dependencies come from the
+    // same catalog.
+    let catalog =
+        apps_by_target_for_provider(device_info, options.provider).map_err(InstallErr::Any)?;
+    let name = options.name.to_lowercase();
+    let app_info = catalog
+        .iter()
+        .find(|a| a.version_name.to_lowercase() == name)
+        .cloned()
         .ok_or(InstallErr::AppNotFound)?;
 
+    // Make sure the apps it depends on are present, installing them first if we may.
+    let deps = dependency_chain(&catalog, &app_info).map_err(InstallErr::Any)?;
+    if options.strategy == InstallStrategy::AppOnly {
+        if let Some(pos) = deps.iter().position(|dep| !is_installed(&dep.version_name)) {
+            return Err(InstallErr::MissingDependency {
+                name: deps[pos].version_name.clone(),
+                chain: chain_names(&app_info, &deps[..=pos]),
+            });
+        }
+    }
+    let missing_deps: Vec<_> = deps
+        .into_iter()
+        .filter(|dep| !is_installed(&dep.version_name))
+        .collect();
+    // Fail early if the device doesn't have enough storage left for all that. An app being
+    // reinstalled makes room for its new version.
+    let to_install: Vec<_> = std::iter::once(&app_info).chain(&missing_deps).collect();
+    let replaced = already_installed.filter(|_| options.force);
+    let space = space_needed(device_info, installed, &to_install, replaced);
+    if let Some((required, available)) = space.filter(|(required, available)| required > available)
+    {
+        return Err(InstallErr::NotEnoughSpace {
+            required,
+            available,
+        });
+    }
+    let install_err = |e: Error| match space {
+        Some((required, available)) if is_out_of_space(&e) => InstallErr::NotEnoughSpace {
+            required,
+            available,
+        },
+        _ => InstallErr::Any(e),
+    };
+
+    // The deepest dependency must be installed first.
+    for dep in missing_deps.iter().rev() {
+        install_app_internal(ledger_api, device_info, dep).map_err(install_err)?;
+    }
+
+    // The device won't install an app over an existing one. Remove it first when reinstalling.
+    if let Some(installed_app) = already_installed {
+        let installed_app_info = apps_by_hashes(vec![installed_app.hash.clone()])
+            .map_err(InstallErr::Any)?
+            .into_iter()
+            .next()
+            .flatten()
+            .ok_or(InstallErr::AppNotFound)?;
+        uninstall_app_internal(ledger_api, device_info, &installed_app_info)
+            .map_err(InstallErr::Any)?;
+    }
+
     // Now install the app by connecting through their websocket thing to their HSM.
-    install_app_internal(ledger_api, &device_info, &app_info).map_err(InstallErr::Any)?;
+    install_app_internal(ledger_api, device_info, &app_info).map_err(install_err)?;
 
     Ok(())
 }
@@ -776,7 +2890,7 @@ pub fn install_app(
 /// Install the Bitcoin application on this device. Set `is_testnet` to `true` to install the
 /// testnet app instead.
 pub fn install_bitcoin_app(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     is_testnet: bool,
 ) -> Result<(), InstallErr> {
     let app = if is_testnet {
@@ -788,50 +2902,135 @@ pub fn install_bitcoin_app(
 }
 
 /// An error arising when updating an app.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum UpdateErr {
     /// The application is not installed yet.
+    #[error("The application is not installed.")]
     NotInstalled,
     /// Couldn't get info about the app.
+    #[error("Couldn't get info about the app.")]
     AppNotFound,
     /// The installed app is already the latest.
+    #[error("The application is already the latest version.")]
     AlreadyLatest,
-    Any(Box<dyn error::Error>),
+    /// The device doesn't have enough storage left for the new version of the app, even once the
+    /// installed version is removed.
+    #[error("{}", not_enough_space(*.required, *.available))]
+    NotEnoughSpace {
+        /// The storage needed by the new version of the app, in bytes.
+        required: u64,
+        /// The storage left on the device once the installed version is removed, in bytes.
+        available: u64,
+    },
+    /// The version from the catalog is older than the installed one. See
+    /// [`InstallOptions::allow_downgrade`].
+    #[error("Version {target} is older than the installed version {installed}.")]
+    Downgrade { installed: String, target: String },
+    #[error(transparent)]
+    Any(#[from] Error),
+}
+
+impl UpdateErr {
+    /// Whether the update failed because the device is locked. See [`Error::is_locked_device`].
+    pub fn is_locked_device(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_locked_device())
+    }
+
+    /// The name of the app open on the device, if the update failed because of it. See
+    /// [`Error::app_open`].
+    pub fn app_open(&self) -> Option<&str> {
+        match self {
+            Self::Any(e) => e.app_open(),
+            _ => None,
+        }
+    }
 }
 
-/// Update the given application on this device.
+/// Update the given application on this device. Pass `InstallOptions` instead of a `LedgerApp`
+/// to customize the update.
 pub fn update_app(
-    ledger_api: &TransportNativeHID,
-    app: LedgerApp,
+    ledger_api: &dyn LedgerTransport,
+    options: impl Into<InstallOptions>,
+) -> Result<(), UpdateErr> {
+    let options = options.into();
+    let installed = list_installed_apps_raw(ledger_api).map_err(UpdateErr::Any)?;
+    let device_info = DeviceInfo::new(ledger_api).map_err(UpdateErr::Any)?;
+    update_app_with(ledger_api, &device_info, &installed, &options)
+}
+
+/// Update the app with this name (case-insensitive), such as "Polygon". This allows updating any
+/// app, not only the supported ones.
+pub fn update_app_by_name(ledger_api: &dyn LedgerTransport, name: &str) -> Result<(), UpdateErr> {
+    update_app(ledger_api, InstallOptions::by_name(name))
+}
+
+pub(crate) fn update_app_with(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    installed: &[InstalledApp],
+    options: &InstallOptions,
 ) -> Result<(), UpdateErr> {
     // First of all make sure the app is installed. Get its details.
-    let installed = app_installed(ledger_api, app)
-        .map_err(UpdateErr::Any)?
+    let installed_app = installed
+        .iter()
+        .find(|a| a.name.to_lowercase() == options.name.to_lowercase())
         .ok_or(UpdateErr::NotInstalled)?;
-    let installed_app_info = apps_by_hashes(vec![installed.hash])
+    let installed_app_info = apps_by_hashes(vec![installed_app.hash.clone()])
         .map_err(UpdateErr::Any)?
         .into_iter()
         .next()
         .ok_or(UpdateErr::AppNotFound)?;
 
     // Get the latest app info, necessary for the websocket query below.
-    let device_info = DeviceInfo::new(ledger_api).map_err(UpdateErr::Any)?;
-    let latest_app_info = latest_app(&device_info, app)
+    let latest_app_info = catalog_app(device_info, options)
         .map_err(UpdateErr::Any)?
         .ok_or(UpdateErr::AppNotFound)?;
 
     // It doesn't make a whole lot of sense to not check the version is indeed superior to the
     // version of the installed app. But this is the check Ledger Live does. And it also never uses
     // versionId as far as i can tell. So, do like Ledger.
-    if installed_app_info
-        .map(|a| a.version == latest_app_info.version)
-        .unwrap_or(false)
+    if !options.force
+        && installed_app_info
+            .as_ref()
+            .map(|a| a.version == latest_app_info.version)
+            .unwrap_or(false)
     {
         return Err(UpdateErr::AlreadyLatest);
     }
+    if let Some(installed_version) = installed_app_info.map(|a| a.version) {
+        if !options.allow_downgrade
+            && is_downgrade(&latest_app_info.version, &installed_version)
+        {
+            return Err(UpdateErr::Downgrade {
+                installed: installed_version,
+                target: latest_app_info.version,
+            });
+        }
+    }
+
+    // The new version replaces the installed one, make sure it fits.
+    let space = space_needed(
+        device_info,
+        installed,
+        &[&latest_app_info],
+        Some(installed_app),
+    );
+    if let Some((required, available)) = space.filter(|(required, available)| required > available)
+    {
+        return Err(UpdateErr::NotEnoughSpace {
+            required,
+            available,
+        });
+    }
 
     // Now install the app by connecting through their websocket thing to their HSM.
-    install_app_internal(ledger_api, &device_info, &latest_app_info).map_err(UpdateErr::Any)?;
+    install_app_internal(ledger_api, device_info, &latest_app_info).map_err(|e| match space {
+        Some((required, available)) if is_out_of_space(&e) => UpdateErr::NotEnoughSpace {
+            required,
+            available,
+        },
+        _ => UpdateErr::Any(e),
+    })?;
 
     Ok(())
 }
@@ -839,7 +3038,7 @@ pub fn update_app(
 /// Update the Bitcoin application on this device. Set `is_testnet` to `true` to install the
 /// testnet app instead.
 pub fn update_bitcoin_app(
-    ledger_api: &TransportNativeHID,
+    ledger_api: &dyn LedgerTransport,
     is_testnet: bool,
 ) -> Result<(), UpdateErr> {
     let app = if is_testnet {
@@ -849,3 +3048,521 @@ pub fn update_bitcoin_app(
     };
     update_app(ledger_api, app)
 }
+
+/// An error arising when uninstalling an app.
+#[derive(Debug, thiserror::Error)]
+pub enum UninstallErr {
+    /// The application is not installed.
+    #[error("The application is not installed.")]
+    NotInstalled,
+    /// Couldn't get info about the app.
+    #[error("Couldn't get info about the app.")]
+    AppNotFound,
+    /// The app is a library (or the base app of a plugin) another installed app depends on, which
+    /// isn't removed.
+    #[error(
+        "The '{app}' app is required by the '{dependent}' app, which must be uninstalled first."
+    )]
+    RequiredBy { app: String, dependent: String },
+    #[error(transparent)]
+    Any(#[from] Error),
+}
+
+impl UninstallErr {
+    /// Whether uninstalling failed because the device is locked. See
+    /// [`Error::is_locked_device`].
+    pub fn is_locked_device(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_locked_device())
+    }
+
+    /// The name of the app open on the device, if uninstalling failed because of it. See
+    /// [`Error::app_open`].
+    pub fn app_open(&self) -> Option<&str> {
+        match self {
+            Self::Any(e) => e.app_open(),
+            _ => None,
+        }
+    }
+}
+
+// Errors are serialized as a summary: the kind of error along with a message, if any.
+#[cfg(feature = "serde")]
+fn serialize_error<S: serde::Serializer>(
+    serializer: S,
+    kind: &str,
+    message: Option<String>,
+) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeStruct;
+    let mut summary = serializer.serialize_struct("Error", 2)?;
+    summary.serialize_field("kind", kind)?;
+    summary.serialize_field("message", &message)?;
+    summary.end()
+}
+
+// The errors a frontend may handle specifically have their own kind.
+#[cfg(feature = "serde")]
+fn serialize_any_error<S: serde::Serializer>(serializer: S, e: &Error) -> Result<S::Ok, S::Error> {
+    match e {
+        Error::UserRefused => serialize_error(serializer, "user_refused", None),
+        Error::LockedDevice => serialize_error(serializer, "locked_device", None),
+        Error::DeviceDisconnected(_) => {
+            serialize_error(serializer, "device_disconnected", Some(e.to_string()))
+        }
+        Error::AppOpen(_) => serialize_error(serializer, "app_open", Some(e.to_string())),
+        e => serialize_error(serializer, "other", Some(e.to_string())),
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for InstallErr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::AlreadyInstalled => serialize_error(serializer, "already_installed", None),
+            Self::AppNotFound => serialize_error(serializer, "app_not_found", None),
+            Self::MissingDependency { .. } => {
+                serialize_error(serializer, "missing_dependency", Some(self.to_string()))
+            }
+            Self::NotEnoughSpace { .. } => {
+                serialize_error(serializer, "not_enough_space", Some(self.to_string()))
+            }
+            Self::NotOnboarded => serialize_error(serializer, "not_onboarded", None),
+            Self::Any(e) => serialize_any_error(serializer, e),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for UpdateErr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::NotInstalled => serialize_error(serializer, "not_installed", None),
+            Self::AppNotFound => serialize_error(serializer, "app_not_found", None),
+            Self::AlreadyLatest => serialize_error(serializer, "already_latest", None),
+            Self::NotEnoughSpace { .. } => {
+                serialize_error(serializer, "not_enough_space", Some(self.to_string()))
+            }
+            Self::Downgrade { .. } => {
+                serialize_error(serializer, "downgrade", Some(self.to_string()))
+            }
+            Self::Any(e) => serialize_any_error(serializer, e),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for OpenAppErr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::NotInstalled => serialize_error(serializer, "not_installed", None),
+            Self::UserRefused => serialize_error(serializer, "user_refused", None),
+            Self::LockedDevice => serialize_error(serializer, "locked_device", None),
+            Self::OtherAppOpen(_) => {
+                serialize_error(serializer, "other_app_open", Some(self.to_string()))
+            }
+            Self::Any(e) => serialize_any_error(serializer, e),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for UninstallErr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::NotInstalled => serialize_error(serializer, "not_installed", None),
+            Self::AppNotFound => serialize_error(serializer, "app_not_found", None),
+            Self::RequiredBy { .. } => {
+                serialize_error(serializer, "required_by", Some(self.to_string()))
+            }
+            Self::Any(e) => serialize_any_error(serializer, e),
+        }
+    }
+}
+
+// Uninstalling is performed like an install, but using the "delete" script of the app.
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/uninstallApp.ts
+fn uninstall_app_internal(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    app: &AppInfo,
+) -> Result<(), Error> {
+    let uninstall_ws_url = UrlSerializer::new(format!("{}/install?", socket_url()))
+        .append_pair("targetId", &device_info.target_id.to_string())
+        .append_pair("perso", &app.perso)
+        .append_pair("deleteKey", &app.delete_key)
+        .append_pair("firmware", &app.delete)
+        .append_pair("firmwareKey", &app.delete_key)
+        .append_pair("hash", &app.hash)
+        .finish();
+    query_via_websocket(ledger_api, &uninstall_ws_url)
+}
+
+/// Uninstall the given application from this device.
+pub fn uninstall_app(ledger_api: &dyn LedgerTransport, app: LedgerApp) -> Result<(), UninstallErr> {
+    let installed = list_installed_apps_raw(ledger_api).map_err(UninstallErr::Any)?;
+    let device_info = DeviceInfo::new(ledger_api).map_err(UninstallErr::Any)?;
+    uninstall_app_with(ledger_api, &device_info, &installed, app.app_name())
+}
+
+/// Uninstall the app with this name (case-insensitive) from this device, such as "Polygon". This
+/// allows uninstalling any app, not only the supported ones.
+pub fn uninstall_app_by_name(
+    ledger_api: &dyn LedgerTransport,
+    name: &str,
+) -> Result<(), UninstallErr> {
+    let installed = list_installed_apps_raw(ledger_api)?;
+    let device_info = DeviceInfo::new(ledger_api)?;
+    uninstall_app_with(ledger_api, &device_info, &installed, name)
+}
+
+pub(crate) fn uninstall_app_with(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    installed: &[InstalledApp],
+    name: &str,
+) -> Result<(), UninstallErr> {
+    let name = name.to_lowercase();
+    let index = installed
+        .iter()
+        .position(|a| a.name.to_lowercase() == name)
+        .ok_or(UninstallErr::NotInstalled)?;
+    let mut infos = apps_by_hashes(installed.iter().map(|a| a.hash.clone()).collect())
+        .map_err(UninstallErr::Any)?;
+    let app_info = infos
+        .get_mut(index)
+        .and_then(Option::take)
+        .ok_or(UninstallErr::AppNotFound)?;
+    check_not_required(
+        infos.into_iter().flatten(),
+        &[app_info.version_name.to_lowercase()],
+    )?;
+
+    uninstall_app_internal(ledger_api, device_info, &app_info).map_err(UninstallErr::Any)
+}
+
+// Order the apps such as an app is always removed before the library (or the base app of a
+// plugin) it depends on.
+fn removal_order(mut apps: Vec<AppInfo>) -> Vec<AppInfo> {
+    let mut ordered = Vec::with_capacity(apps.len());
+
+    while !apps.is_empty() {
+        // Find the apps no other remaining app depends on.
+        let (leaves, rest): (Vec<_>, Vec<_>) = apps.iter().cloned().partition(|app| {
+            let name = app.version_name.to_lowercase();
+            !apps
+                .iter()
+                .any(|a| parent_of(a).is_some_and(|p| p.to_lowercase() == name))
+        });
+        // In case of a dependency cycle just remove the rest in any order.
+        if leaves.is_empty() {
+            ordered.extend(rest);
+            break;
+        }
+        ordered.extend(leaves);
+        apps = rest;
+    }
+
+    ordered
+}
+
+/// Uninstall this app from the device. Returns the number of bytes freed on the device, for
+/// instance to tell the user how much space was made for other apps.
+pub fn delete_app(ledger_api: &dyn LedgerTransport, app: LedgerApp) -> Result<u64, UninstallErr> {
+    let installed = list_installed_apps_raw(ledger_api)?;
+    let device_info = DeviceInfo::new(ledger_api)?;
+    delete_app_with(ledger_api, &device_info, &installed, app.app_name())
+}
+
+/// Uninstall the app with this name (case-insensitive) from the device, such as "Polygon".
+/// Returns the number of bytes freed on the device.
+pub fn delete_app_by_name(
+    ledger_api: &dyn LedgerTransport,
+    name: &str,
+) -> Result<u64, UninstallErr> {
+    let installed = list_installed_apps_raw(ledger_api)?;
+    let device_info = DeviceInfo::new(ledger_api)?;
+    delete_app_with(ledger_api, &device_info, &installed, name)
+}
+
+pub(crate) fn delete_app_with(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    installed: &[InstalledApp],
+    name: &str,
+) -> Result<u64, UninstallErr> {
+    let lowercase_name = name.to_lowercase();
+    let blocks = installed
+        .iter()
+        .find(|a| a.name.to_lowercase() == lowercase_name)
+        .ok_or(UninstallErr::NotInstalled)?
+        .blocks;
+    uninstall_app_with(ledger_api, device_info, installed, name)?;
+    Ok(u64::from(blocks) * device_info.block_size())
+}
+
+/// Uninstall all the apps from the device, libraries included. The apps unknown to the Ledger API
+/// can't be uninstalled and are left on the device. Returns the number of bytes freed on the
+/// device.
+pub fn delete_all_apps(ledger_api: &dyn LedgerTransport) -> Result<u64, Error> {
+    let installed = list_installed_apps_raw(ledger_api)?;
+    let device_info = DeviceInfo::new(ledger_api)?;
+    delete_all_apps_with(ledger_api, &device_info, &installed)
+}
+
+pub(crate) fn delete_all_apps_with(
+    ledger_api: &dyn LedgerTransport,
+    device_info: &DeviceInfo,
+    installed: &[InstalledApp],
+) -> Result<u64, Error> {
+    if installed.is_empty() {
+        return Ok(0);
+    }
+    let infos = apps_by_hashes(installed.iter().map(|a| a.hash.clone()).collect())?;
+    let mut freed = 0;
+    let mut apps = Vec::new();
+    for (installed, info) in installed.iter().zip(infos) {
+        if let Some(info) = info {
+            freed += u64::from(installed.blocks) * device_info.block_size();
+            apps.push(info);
+        }
+    }
+    for app in removal_order(apps) {
+        uninstall_app_internal(ledger_api, device_info, &app)?;
+    }
+    Ok(freed)
+}
+
+/// The applications to remove from a device to only keep a set of applications.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct UninstallPlan {
+    /// The apps to uninstall, in the order they must be uninstalled.
+    pub to_remove: Vec<AppInfo>,
+    /// The names of the installed apps that will be kept. This includes the libraries the
+    /// protected apps depend on.
+    pub kept: Vec<String>,
+    /// The names of the installed apps kept only because they are protected.
+    pub protected: Vec<String>,
+    /// The names of the installed apps unknown to the Ledger API, which can't be uninstalled.
+    pub unknown: Vec<String>,
+}
+
+/// Compute which installed apps to uninstall in order to remove all the apps but the ones whose
+/// name is in `keep` (case-insensitive). The apps whose name is in `protected` are never removed,
+/// whatever `keep` is. The libraries the kept apps depend on are kept too.
+pub fn plan_uninstall_all_except(
+    ledger_api: &dyn LedgerTransport,
+    keep: &[String],
+    protected_apps: &[String],
+) -> Result<UninstallPlan, Error> {
+    let mut known = Vec::new();
+    let mut unknown = Vec::new();
+    for app in list_installed_apps(ledger_api)? {
+        match app.info {
+            Some(info) => known.push(info),
+            None => unknown.push(app.name),
+        }
+    }
+    Ok(plan_keeping(known, unknown, keep, protected_apps))
+}
+
+// Plan the removal of these installed apps known to the Ledger API, but the ones in `keep` and
+// `protected_apps` and the libraries they depend on.
+fn plan_keeping(
+    known: Vec<AppInfo>,
+    unknown: Vec<String>,
+    keep: &[String],
+    protected_apps: &[String],
+) -> UninstallPlan {
+    // Protect the kept apps and, transitively, the libraries they depend on.
+    let mut protected: Vec<String> = keep
+        .iter()
+        .chain(protected_apps)
+        .map(|k| k.to_lowercase())
+        .collect();
+    let mut i = 0;
+    while i < protected.len() {
+        let parent = known
+            .iter()
+            .find(|a| a.version_name.to_lowercase() == protected[i])
+            .and_then(parent_of)
+            .map(|p| p.to_lowercase());
+        if let Some(parent) = parent {
+            if !protected.contains(&parent) {
+                protected.push(parent);
+            }
+        }
+        i += 1;
+    }
+
+    let (kept, to_remove): (Vec<_>, Vec<_>) = known
+        .into_iter()
+        .partition(|a| protected.contains(&a.version_name.to_lowercase()));
+    let kept: Vec<_> = kept.into_iter().map(|a| a.version_name).collect();
+    let only_protected = kept
+        .iter()
+        .filter(|name| {
+            let name = name.to_lowercase();
+            protected_apps.iter().any(|p| p.to_lowercase() == name)
+                && !keep.iter().any(|k| k.to_lowercase() == name)
+        })
+        .cloned()
+        .collect();
+    UninstallPlan {
+        to_remove: removal_order(to_remove),
+        kept,
+        protected: only_protected,
+        unknown,
+    }
+}
+
+// Refuse to remove the apps with these names (lowercase) if one of these installed apps, which
+// isn't removed along, depends on one of them.
+fn check_not_required(
+    installed: impl IntoIterator<Item = AppInfo>,
+    removed: &[String],
+) -> Result<(), UninstallErr> {
+    for info in installed {
+        if removed.contains(&info.version_name.to_lowercase()) {
+            continue;
+        }
+        if let Some(parent) = parent_of(&info) {
+            if removed.contains(&parent.to_lowercase()) {
+                return Err(UninstallErr::RequiredBy {
+                    app: parent,
+                    dependent: info.version_name,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Uninstall these apps from the device. They are removed in an order such as no library is
+/// removed before the apps depending on it. Nothing is removed if one of them is a library an
+/// installed app depends on, unless this app is removed too.
+pub fn uninstall_apps(
+    ledger_api: &dyn LedgerTransport,
+    apps: &[AppInfo],
+) -> Result<(), UninstallErr> {
+    let names: Vec<_> = apps.iter().map(|a| a.version_name.to_lowercase()).collect();
+    let installed = list_installed_apps(ledger_api)?;
+    check_not_required(installed.into_iter().filter_map(|a| a.info), &names)?;
+
+    let device_info = DeviceInfo::new(ledger_api)?;
+    for app in removal_order(apps.to_vec()) {
+        uninstall_app_internal(ledger_api, &device_info, &app)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // An app of the catalog, depending on the library with this name if any.
+    fn app(name: &str, parent: Option<&str>) -> AppInfo {
+        AppInfo {
+            version_name: name.to_string(),
+            version_id: 0,
+            version: "1.0.0".to_string(),
+            perso: String::new(),
+            delete: String::new(),
+            delete_key: String::new(),
+            firmware: String::new(),
+            firmware_key: String::new(),
+            hash: String::new(),
+            bytes: None,
+            parent_name: parent.map(|p| p.to_string()),
+            description: None,
+            warning: None,
+        }
+    }
+
+    fn names(apps: &[AppInfo]) -> Vec<&str> {
+        apps.iter().map(|a| a.version_name.as_str()).collect()
+    }
+
+    #[test]
+    fn tells_missing_space() {
+        assert_eq!(missing_kb(3000, 1000), 2);
+        assert_eq!(missing_kb(1000, 3000), 0);
+        assert!(not_enough_space(3 * 1024, 1024).ends_with("free 2 more KB."));
+        assert!(not_enough_space(1024, 3 * 1024).contains("the device reported it's full"));
+    }
+
+    #[test]
+    fn compares_versions() {
+        assert!(is_downgrade("1.9.0", "1.10.0"));
+        assert!(!is_downgrade("1.10.0", "1.9.0"));
+        // Different numbers of components.
+        assert!(!is_downgrade("2.1", "2.1.0"));
+        assert!(!is_downgrade("2.1.0", "2.1"));
+        assert!(is_downgrade("2.1", "2.1.1"));
+        assert!(!is_downgrade("2.2", "2.1.1"));
+        // Pre-release suffixes.
+        assert!(!is_downgrade("1.2.0-rc1", "1.2.0"));
+        assert!(!is_downgrade("1.3.0-rc1", "1.2.9"));
+        assert!(is_downgrade("1.2.0-rc1", "1.2.1"));
+    }
+
+    #[test]
+    fn removes_dependents_before_libraries() {
+        let apps = vec![
+            app("Base", None),
+            app("Library", Some("base")),
+            app("Standalone", None),
+            app("App", Some("Library")),
+        ];
+        assert_eq!(
+            names(&removal_order(apps)),
+            ["Standalone", "App", "Library", "Base"]
+        );
+    }
+
+    #[test]
+    fn reports_dependency_cycle() {
+        let catalog = vec![app("First", Some("Second")), app("Second", Some("First"))];
+        assert!(matches!(
+            dependency_chain(&catalog, &catalog[0]),
+            Err(Error::Catalog(e)) if e.contains("cycle")
+        ));
+    }
+
+    #[test]
+    fn removes_base_app_after_plugins() {
+        let known = vec![
+            app("Ethereum", None),
+            app("Paraswap", None),
+            app("1inch", None),
+        ];
+        let plan = plan_keeping(known.clone(), Vec::new(), &[], &[]);
+        assert_eq!(names(&plan.to_remove), ["Paraswap", "1inch", "Ethereum"]);
+        let plan = plan_keeping(known, Vec::new(), &["paraswap".to_string()], &[]);
+        assert_eq!(names(&plan.to_remove), ["1inch"]);
+        assert_eq!(plan.kept, ["Ethereum", "Paraswap"]);
+        assert!(matches!(
+            check_not_required(vec![app("Paraswap", None)], &["ethereum".to_string()]),
+            Err(UninstallErr::RequiredBy { app, dependent }) if app == "Ethereum" && dependent == "Paraswap"
+        ));
+    }
+
+    #[test]
+    fn keeps_library_of_protected_app() {
+        let known = vec![
+            app("Library", None),
+            app("Protected", Some("Library")),
+            app("Kept", None),
+            app("Other", None),
+        ];
+        let plan = plan_keeping(
+            known,
+            vec!["Unknown".to_string()],
+            &["kept".to_string()],
+            &["protected".to_string()],
+        );
+        assert_eq!(names(&plan.to_remove), ["Other"]);
+        assert_eq!(plan.kept, ["Library", "Protected", "Kept"]);
+        assert_eq!(plan.protected, ["Protected"]);
+        assert_eq!(plan.unknown, ["Unknown"]);
+    }
+}
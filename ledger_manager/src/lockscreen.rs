@@ -0,0 +1,174 @@
+//! Customizing the picture shown by the touchscreen devices while they are locked. The picture is
+//! staged on the device, then committed once it's complete, which the user must confirm on the
+//! device. The device only takes pictures in shades of gray, the size of its screen.
+// Adapted from https://github.com/LedgerHQ/ledger-live/tree/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw
+// (customLockScreenLoad.ts, customLockScreenRemove.ts and staxImageFormat.ts).
+
+use crate::{exchange_checked, DeviceModel, Error, LedgerTransport};
+
+use flate2::{write::GzEncoder, Compression};
+use ledger_apdu::APDUCommand;
+use std::io::Write;
+
+// Start staging a picture. The data is the size of the encoded picture, as a big-endian u32.
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/customLockScreenLoad.ts
+const CREATE_IMAGE_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x60,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// Write a chunk of the picture. The data is the offset of the chunk, as a big-endian u32, and the
+// chunk itself.
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/customLockScreenLoad.ts
+const LOAD_IMAGE_COMMAND_TEMPLATE: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x61,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// Replace the lock screen with the staged picture, once confirmed by the user.
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/customLockScreenLoad.ts
+const COMMIT_IMAGE_COMMAND: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x62,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// Restore the default lock screen.
+// https://github.com/LedgerHQ/ledger-live/blob/5a0a1aa5dc183116839851b79bceb6704f1de4b9/libs/ledger-live-common/src/hw/customLockScreenRemove.ts
+const DELETE_IMAGE_COMMAND: APDUCommand<&[u8]> = APDUCommand {
+    cla: 0xe0,
+    ins: 0x63,
+    p1: 0x00,
+    p2: 0x00,
+    data: &[],
+};
+
+// The pixels are written with 4 bits each (16 shades of gray), compressed with gzip.
+const BPP_FORMAT_4: u8 = 2;
+const COMPRESSION_GZIP: u8 = 1;
+// The pixel data is compressed by chunks of this many bytes.
+const COMPRESSION_CHUNK_SIZE: usize = 2048;
+// The largest chunk of the encoded picture sent at once, after its offset.
+const LOAD_CHUNK_SIZE: usize = 255 - 4;
+
+/// The number of shades of gray of a lock screen picture. See [`set_lock_screen`].
+pub const LOCK_SCREEN_GRAY_LEVELS: u8 = 16;
+
+// Encode this picture in the format of the device: a header with its size and the format of its
+// data, then the pixels written column by column from the top right corner, compressed.
+fn encode_picture(width: u32, height: u32, pixels: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut nibbles = Vec::with_capacity(pixels.len());
+    for x in (0..width).rev() {
+        for y in 0..height {
+            let level = pixels[(y * width + x) as usize];
+            nibbles.push(level.min(LOCK_SCREEN_GRAY_LEVELS - 1));
+        }
+    }
+    // Two pixels a byte, the first one in the high bits.
+    let packed: Vec<u8> = nibbles
+        .chunks(2)
+        .map(|p| p[0] << 4 | p.get(1).copied().unwrap_or(0))
+        .collect();
+
+    // Every compressed chunk is prefixed with its length, as a little-endian u16.
+    let mut data = Vec::new();
+    for chunk in packed.chunks(COMPRESSION_CHUNK_SIZE) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(chunk)
+            .map_err(|_| Error::InvalidCommand)?;
+        let compressed = encoder.finish().map_err(|_| Error::InvalidCommand)?;
+        let len = u16::try_from(compressed.len()).map_err(|_| Error::InvalidCommand)?;
+        data.extend_from_slice(&len.to_le_bytes());
+        data.extend_from_slice(&compressed);
+    }
+
+    let mut picture = Vec::with_capacity(8 + data.len());
+    picture.extend_from_slice(&(width as u16).to_le_bytes());
+    picture.extend_from_slice(&(height as u16).to_le_bytes());
+    picture.push(BPP_FORMAT_4 << 4 | COMPRESSION_GZIP);
+    picture.extend_from_slice(&(data.len() as u32).to_le_bytes()[..3]);
+    picture.extend_from_slice(&data);
+    Ok(picture)
+}
+
+/// Show this picture on the lock screen of the device. The picture must be the size of the screen
+/// (see [`DeviceModel::screen_size`]), given as the shade of gray of every pixel row by row, from
+/// 0 (black) to [`LOCK_SCREEN_GRAY_LEVELS`] - 1 (white). The user must confirm the picture on the
+/// device. `progress` is called with the fraction (between 0 and 1) of the picture written to the
+/// device.
+pub fn set_lock_screen(
+    ledger_api: &dyn LedgerTransport,
+    model: DeviceModel,
+    pixels: &[u8],
+    mut progress: impl FnMut(f32),
+) -> Result<(), Error> {
+    let (width, height) = model
+        .screen_size()
+        .filter(|_| model.has_custom_lock_screen())
+        .ok_or(Error::Unsupported("custom lock screen on this model"))?;
+    if pixels.len() != (width * height) as usize {
+        return Err(Error::Unsupported(
+            "picture of a different size than the screen",
+        ));
+    }
+    let picture = encode_picture(width, height, pixels)?;
+
+    let size = (picture.len() as u32).to_be_bytes();
+    let mut command = CREATE_IMAGE_COMMAND_TEMPLATE;
+    command.data = &size;
+    exchange_checked(ledger_api, &command)?;
+
+    for (i, chunk) in picture.chunks(LOAD_CHUNK_SIZE).enumerate() {
+        let offset = (i * LOAD_CHUNK_SIZE) as u32;
+        let mut data = offset.to_be_bytes().to_vec();
+        data.extend_from_slice(chunk);
+        let mut command = LOAD_IMAGE_COMMAND_TEMPLATE;
+        command.data = &data;
+        exchange_checked(ledger_api, &command)?;
+        progress((offset as usize + chunk.len()) as f32 / picture.len() as f32);
+    }
+
+    exchange_checked(ledger_api, &COMMIT_IMAGE_COMMAND)
+}
+
+/// Restore the default lock screen of the device.
+pub fn clear_lock_screen(ledger_api: &dyn LedgerTransport) -> Result<(), Error> {
+    exchange_checked(ledger_api, &DELETE_IMAGE_COMMAND)
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    #[test]
+    fn encodes_picture() {
+        // 3x2 pixels, row by row. The last shade is out of range.
+        let picture = encode_picture(3, 2, &[0, 1, 2, 3, 4, 20]).expect("Encoded");
+        let data_len = picture.len() - 8;
+        assert_eq!(
+            picture[..8],
+            [3, 0, 2, 0, 0x21, data_len as u8, (data_len >> 8) as u8, 0]
+        );
+        // A single compressed chunk.
+        let chunk_len = u16::from_le_bytes([picture[8], picture[9]]) as usize;
+        assert_eq!(chunk_len, data_len - 2);
+        let mut pixels = Vec::new();
+        GzDecoder::new(&picture[10..])
+            .read_to_end(&mut pixels)
+            .expect("Valid gzip");
+        // Column by column from the right, top to bottom, two pixels a byte.
+        assert_eq!(pixels, [0x2f, 0x14, 0x03]);
+    }
+}
@@ -0,0 +1,346 @@
+//! A stateful client to manage a Ledger device.
+
+use crate::{
+    delete_all_apps_with, delete_app_with, genuine_check_with, get_latest_app_version,
+    install_app_with, latest_app, list_installed_apps_raw, open_app, post_open_info,
+    uninstall_app_with, update_app_with, wait_unlocked, AppInfo, AppVersion, DeviceInfo, Error,
+    InstallErr, InstallOptions, InstalledApp, LedgerApp, MemoryInfo, OpenAppErr, PostOpenInfo,
+    UninstallErr, UpdateErr,
+};
+use crate::{enumerate_devices, LedgerTransport, TransportConfig, TransportHid};
+
+use ledger_transport_hidapi::{hidapi::HidApi, LedgerHIDError, TransportNativeHID};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// How long to wait for the device to be enumerated again after a transient error.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// An error which may be caused by the connection with the device being lost for a moment.
+trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for Error {
+    fn is_transient(&self) -> bool {
+        Error::is_transient(self)
+    }
+}
+
+impl Transient for InstallErr {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_transient())
+    }
+}
+
+impl Transient for UpdateErr {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_transient())
+    }
+}
+
+impl Transient for OpenAppErr {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_transient())
+    }
+}
+
+impl Transient for UninstallErr {
+    fn is_transient(&self) -> bool {
+        matches!(self, Self::Any(e) if e.is_transient())
+    }
+}
+
+// Connect to the Ledger device with this USB serial number, or to the first one found. Returns the
+// serial number of the device connected to.
+fn connect(serial: Option<&str>) -> Result<(TransportNativeHID, Option<String>), Error> {
+    let hid_api = HidApi::new().map_err(LedgerHIDError::from)?;
+    let device = TransportNativeHID::list_ledgers(&hid_api)
+        .find(|d| serial.is_none() || d.serial_number() == serial)
+        .ok_or(LedgerHIDError::DeviceNotFound)?;
+    let transport = TransportNativeHID::open_device(&hid_api, device)?;
+    Ok((transport, device.serial_number().map(str::to_string)))
+}
+
+// Connect with these settings to the Ledger device with this USB serial number, or to the first one
+// found. Returns the serial number of the device connected to.
+fn connect_hid(
+    serial: Option<&str>,
+    config: TransportConfig,
+) -> Result<(TransportHid, Option<String>), Error> {
+    let hid_api = HidApi::new().map_err(LedgerHIDError::from)?;
+    let device = enumerate_devices(&hid_api)
+        .into_iter()
+        .find(|d| serial.is_none() || d.serial.as_deref() == serial)
+        .ok_or(LedgerHIDError::DeviceNotFound)?;
+    Ok((TransportHid::connect(&device, config)?, device.serial))
+}
+
+// Connects again to the device of a manager.
+type Reconnect<T> = Box<dyn FnMut() -> Result<T, Error> + Send>;
+
+/// A connection to a Ledger device, over any transport. It caches the information queried from the
+/// device, in order to avoid redundant queries (and confirmations on the device). The cache is
+/// invalidated whenever an operation modifies the set of apps installed on the device.
+///
+/// This is a session with the device: all the operations run over the same connection, and the user
+/// only has to allow the Ledger manager on the device once, when the installed apps are first
+/// listed (see [`LedgerManager::open_session`]). The secure channel with Ledger's HSM can't be
+/// shared though: the HSM opens a new one for every install or uninstall.
+///
+/// If the connection with the device is lost for a moment, for instance because the channel was
+/// desynchronized by an interrupted previous run, a USB hub reset, the computer woke up from sleep
+/// or the device was unplugged and plugged back in, the manager connects to it again (see
+/// [`LedgerManager::reconnect_with`]) and runs the operation again from the start, once, before the
+/// error is returned.
+pub struct LedgerManager<T: LedgerTransport = TransportNativeHID> {
+    transport: T,
+    reconnect: Option<Reconnect<T>>,
+    device_info: Option<DeviceInfo>,
+    installed_apps: Option<Vec<InstalledApp>>,
+}
+
+impl LedgerManager<TransportNativeHID> {
+    /// Connect to the first Ledger device found over USB and start a session with it. The user is
+    /// asked right away to allow the Ledger manager on the device, so the operations run afterward
+    /// don't interrupt them. The manager reconnects to the same device, as told by its USB serial
+    /// number, if the connection is lost.
+    pub fn open_session() -> Result<Self, Error> {
+        let (transport, serial) = connect(None)?;
+        let mut manager =
+            Self::new(transport).reconnect_with(move || Ok(connect(serial.as_deref())?.0));
+        manager.start()?;
+        Ok(manager)
+    }
+}
+
+impl LedgerManager<TransportHid> {
+    /// Like [`LedgerManager::open_session`], over a [`TransportHid`] with these timeouts and
+    /// retries.
+    pub fn open_hid_session(config: TransportConfig) -> Result<Self, Error> {
+        let (transport, serial) = connect_hid(None, config)?;
+        let mut manager = Self::new(transport)
+            .reconnect_with(move || Ok(connect_hid(serial.as_deref(), config)?.0));
+        manager.start()?;
+        Ok(manager)
+    }
+}
+
+impl<T: LedgerTransport> LedgerManager<T> {
+    /// Manage the device behind this transport, such as a [`TransportHid`], a
+    /// [`crate::SharedTransport`], a [`crate::TransportSpeculos`] or a [`crate::TransportRemote`].
+    /// The operations aren't run again if the connection is lost, unless set with
+    /// [`LedgerManager::reconnect_with`].
+    pub fn new(transport: T) -> Self {
+        Self {
+            transport,
+            reconnect: None,
+            device_info: None,
+            installed_apps: None,
+        }
+    }
+
+    /// Connect again to the device with this function when the connection with it is lost, and
+    /// run the operation again.
+    pub fn reconnect_with(
+        mut self,
+        reconnect: impl FnMut() -> Result<T, Error> + Send + 'static,
+    ) -> Self {
+        self.reconnect = Some(Box::new(reconnect));
+        self
+    }
+
+    // Ask the user to allow the Ledger manager on the device right away.
+    fn start(&mut self) -> Result<(), Error> {
+        self.device_info()?;
+        self.installed_apps()?;
+        Ok(())
+    }
+
+    /// The transport used to talk to the device.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Get back the transport used to talk to the device.
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    /// Forget all the information queried from the device so far.
+    pub fn refresh(&mut self) {
+        self.device_info = None;
+        self.installed_apps = None;
+    }
+
+    // Reconnect to the device, to start over with a fresh channel. Wait for it to be enumerated
+    // again if it's gone for now.
+    fn reconnect(&mut self, reconnect: &mut Reconnect<T>) -> Result<(), Error> {
+        let start = Instant::now();
+        self.transport = loop {
+            match reconnect() {
+                Ok(transport) => break transport,
+                Err(e) if e.is_transient() && start.elapsed() < RECONNECT_TIMEOUT => {
+                    thread::sleep(RECONNECT_POLL_INTERVAL)
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        self.refresh();
+        Ok(())
+    }
+
+    // Run this operation. If it fails because the connection with the device was lost, reconnect
+    // and run it again, if the manager can.
+    fn with_reconnect<R, E: Transient + From<Error>>(
+        &mut self,
+        mut op: impl FnMut(&mut Self) -> Result<R, E>,
+    ) -> Result<R, E> {
+        match op(self) {
+            Err(e) if e.is_transient() => {
+                let Some(mut reconnect) = self.reconnect.take() else {
+                    return Err(e);
+                };
+                log::warn!("Connection with the device lost. Reconnecting and retrying.");
+                let res = self.reconnect(&mut reconnect);
+                self.reconnect = Some(reconnect);
+                res?;
+                op(self)
+            }
+            res => res,
+        }
+    }
+
+    /// Wait for the device to be unlocked, for at most `timeout`. See [`wait_unlocked`].
+    pub fn wait_unlocked(&mut self, timeout: Duration) -> Result<&DeviceInfo, Error> {
+        let device_info = self.with_reconnect(|m| wait_unlocked(&m.transport, timeout))?;
+        Ok(self.device_info.insert(device_info))
+    }
+
+    /// Information about this device. Only queried from the device the first time.
+    pub fn device_info(&mut self) -> Result<&DeviceInfo, Error> {
+        let device_info = match self.device_info.take() {
+            Some(info) => info,
+            None => self.with_reconnect(|m| DeviceInfo::new(&m.transport))?,
+        };
+        Ok(self.device_info.insert(device_info))
+    }
+
+    /// The apps installed on this device. Only queried from the device the first time, or after
+    /// the set of installed apps was modified.
+    pub fn installed_apps(&mut self) -> Result<&[InstalledApp], Error> {
+        let installed_apps = match self.installed_apps.take() {
+            Some(apps) => apps,
+            None => self.with_reconnect(|m| list_installed_apps_raw(&m.transport))?,
+        };
+        Ok(self.installed_apps.insert(installed_apps))
+    }
+
+    /// Whether this app is installed on the device.
+    pub fn is_installed(&mut self, app: LedgerApp) -> Result<bool, Error> {
+        Ok(self
+            .installed_apps()?
+            .iter()
+            .any(|a| a.name.to_lowercase() == app.app_name()))
+    }
+
+    /// How the storage of this device is used. `None` if the storage size of this model is
+    /// unknown.
+    pub fn memory(&mut self) -> Result<Option<MemoryInfo>, Error> {
+        let (installed, device_info) = self.cached()?;
+        Ok(MemoryInfo::new(&device_info, &installed))
+    }
+
+    /// Get the latest version of this app available for this device from the catalog.
+    pub fn latest_app(&mut self, app: LedgerApp) -> Result<Option<AppInfo>, Error> {
+        latest_app(self.device_info()?, app)
+    }
+
+    /// Get the latest version of this app available for this device, and the hash of its binary.
+    pub fn latest_app_version(&mut self, app: LedgerApp) -> Result<Option<AppVersion>, Error> {
+        get_latest_app_version(app, self.device_info()?)
+    }
+
+    // Get both the installed apps and the device info, querying them if not cached yet.
+    fn cached(&mut self) -> Result<(Vec<InstalledApp>, DeviceInfo), Error> {
+        let installed = self.installed_apps()?.to_vec();
+        let device_info = self.device_info()?.clone();
+        Ok((installed, device_info))
+    }
+
+    /// Check whether the device is genuine.
+    pub fn genuine_check(&mut self) -> Result<(), Error> {
+        self.with_reconnect(|m| {
+            let device_info = m.device_info()?.clone();
+            genuine_check_with(&m.transport, &device_info)
+        })
+    }
+
+    /// Install this app. Pass `InstallOptions` instead of a `LedgerApp` to customize the
+    /// installation.
+    pub fn install(&mut self, options: impl Into<InstallOptions>) -> Result<(), InstallErr> {
+        let options = options.into();
+        self.with_reconnect(|m| {
+            let (installed, device_info) = m.cached()?;
+            let res = install_app_with(&m.transport, &device_info, &installed, &options);
+            m.installed_apps = None;
+            res
+        })
+    }
+
+    /// Update this app to the latest version. Pass `InstallOptions` instead of a `LedgerApp` to
+    /// customize the update.
+    pub fn update(&mut self, options: impl Into<InstallOptions>) -> Result<(), UpdateErr> {
+        let options = options.into();
+        self.with_reconnect(|m| {
+            let (installed, device_info) = m.cached()?;
+            let res = update_app_with(&m.transport, &device_info, &installed, &options);
+            m.installed_apps = None;
+            res
+        })
+    }
+
+    /// Uninstall this app.
+    pub fn uninstall(&mut self, app: LedgerApp) -> Result<(), UninstallErr> {
+        self.with_reconnect(|m| {
+            let (installed, device_info) = m.cached()?;
+            let res = uninstall_app_with(&m.transport, &device_info, &installed, app.app_name());
+            m.installed_apps = None;
+            res
+        })
+    }
+
+    /// Uninstall this app. Returns the number of bytes freed on the device.
+    pub fn delete(&mut self, app: LedgerApp) -> Result<u64, UninstallErr> {
+        self.with_reconnect(|m| {
+            let (installed, device_info) = m.cached()?;
+            let res = delete_app_with(&m.transport, &device_info, &installed, app.app_name());
+            m.installed_apps = None;
+            res
+        })
+    }
+
+    /// Uninstall all the apps known to the Ledger API, libraries included. Returns the number of
+    /// bytes freed on the device.
+    pub fn delete_all(&mut self) -> Result<u64, Error> {
+        self.with_reconnect(|m| {
+            let (installed, device_info) = m.cached()?;
+            let res = delete_all_apps_with(&m.transport, &device_info, &installed);
+            m.installed_apps = None;
+            res
+        })
+    }
+
+    /// Open this app on the device.
+    pub fn open(&mut self, app: LedgerApp) -> Result<(), OpenAppErr> {
+        self.with_reconnect(|m| open_app(&m.transport, app))
+    }
+
+    /// Run the app-specific post-open hook for this app. The app must be open.
+    pub fn post_open_info(&mut self, app: LedgerApp) -> Result<Option<PostOpenInfo>, Error> {
+        self.with_reconnect(|m| post_open_info(&m.transport, app))
+    }
+}
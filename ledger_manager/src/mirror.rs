@@ -0,0 +1,186 @@
+//! Selection of the endpoints of Ledger's API and HSM (the "script runner") to use, for instance
+//! mirrors or a staging infrastructure. When several HSM endpoints are available, for instance
+//! regional mirrors, the fastest reachable one is picked using a quick latency probe. Installs
+//! involve many round trips with the HSM, so this matters for users far from the default endpoint.
+//! Also the provider, the channel of the catalog apps and firmwares are looked up in.
+
+use crate::{proxy::connect_timeout, Error, BASE_API_V1_URL, BASE_SOCKET_URL, PROVIDER};
+
+use serde_derive::{Deserialize, Serialize};
+
+use std::{
+    fs,
+    path::Path,
+    sync::RwLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// The Ledger API endpoint set for this process, if not the default one.
+static API_URL: RwLock<Option<String>> = RwLock::new(None);
+// The HSM endpoint set for this process, if not the default one.
+static SOCKET_URL: RwLock<Option<String>> = RwLock::new(None);
+// The provider set for this process, if not the default one.
+static PROVIDER_ID: RwLock<Option<u32>> = RwLock::new(None);
+
+/// How long to wait for an endpoint to accept a connection before considering it unreachable.
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The base URL of the Ledger API endpoint used to query the catalog of apps and firmwares (that
+/// of its version 1, the version 2 being under `/v2`). This is [`BASE_API_V1_URL`] unless another
+/// one was set with [`set_api_url`].
+pub fn api_url() -> String {
+    API_URL
+        .read()
+        .ok()
+        .and_then(|url| url.clone())
+        .unwrap_or_else(|| BASE_API_V1_URL.to_string())
+}
+
+// The base URL of the version 2 of the Ledger API endpoint used.
+pub(crate) fn api_v2_url() -> String {
+    format!("{}/v2", api_url())
+}
+
+/// Use this Ledger API endpoint (the base URL of its version 1, like [`BASE_API_V1_URL`]) for all
+/// the operations of this process from now on.
+pub fn set_api_url(url: impl Into<String>) {
+    if let Ok(mut current) = API_URL.write() {
+        *current = Some(url.into().trim_end_matches('/').to_string());
+    }
+}
+
+/// The provider (channel) whose catalog the apps and firmwares are looked up in. This is
+/// [`PROVIDER`] unless another one was set with [`set_provider`].
+pub fn provider() -> u32 {
+    PROVIDER_ID
+        .read()
+        .ok()
+        .and_then(|provider| *provider)
+        .unwrap_or(PROVIDER)
+}
+
+/// Look up the apps and firmwares in the catalog of this provider for all the operations of this
+/// process from now on, for instance a beta channel or the custom provider of an enterprise.
+pub fn set_provider(provider: u32) {
+    if let Ok(mut current) = PROVIDER_ID.write() {
+        *current = Some(provider);
+    }
+}
+
+/// The URL of the HSM endpoint used to install and uninstall apps and to perform the genuine
+/// check. This is [`BASE_SOCKET_URL`] unless another one was set with [`set_socket_url`].
+pub fn socket_url() -> String {
+    SOCKET_URL
+        .read()
+        .ok()
+        .and_then(|url| url.clone())
+        .unwrap_or_else(|| BASE_SOCKET_URL.to_string())
+}
+
+/// Use this HSM endpoint for all the operations of this process from now on.
+pub fn set_socket_url(url: impl Into<String>) {
+    if let Ok(mut current) = SOCKET_URL.write() {
+        *current = Some(url.into());
+    }
+}
+
+// The host and port to connect to for this websocket URL.
+pub(crate) fn host_and_port(url: &str) -> Option<(&str, u16)> {
+    let (rest, default_port) = if let Some(rest) = url.strip_prefix("wss://") {
+        (rest, 443)
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        (rest, 80)
+    } else {
+        return None;
+    };
+    let authority = rest.split('/').next()?;
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host, port.parse().ok()?)),
+        None => Some((authority, default_port)),
+    }
+}
+
+/// Measure how long it takes to open a connection to this HSM endpoint, through the proxy if any
+/// (see [`crate::set_proxy`]).
+pub fn probe_latency(url: &str, timeout: Duration) -> Result<Duration, Error> {
+    let (host, port) = host_and_port(url)
+        .ok_or_else(|| Error::Hsm(format!("Invalid HSM endpoint URL '{}'.", url)))?;
+    let start = Instant::now();
+    connect_timeout(url, host, port, timeout)?;
+    Ok(start.elapsed())
+}
+
+// The endpoint selected by the last probe, as remembered across runs.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProbeState {
+    candidates: Vec<String>,
+    selected: String,
+    /// When the probe was run, as a UNIX timestamp.
+    probed_at: u64,
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// The endpoint selected by the last probe, if it was among the same candidates and isn't older than
+// this.
+fn cached_selection(state_file: &Path, candidates: &[String], max_age: Duration) -> Option<String> {
+    let state: ProbeState = serde_json::from_slice(&fs::read(state_file).ok()?).ok()?;
+    (state.candidates == candidates && now().saturating_sub(state.probed_at) <= max_age.as_secs())
+        .then_some(state.selected)
+}
+
+/// Select the fastest reachable of these HSM endpoints, and use it for all the operations of this
+/// process (see [`set_socket_url`]). The endpoints are probed at most once every `max_age`: the
+/// result of the probe is remembered in `state_file`. Failing to write the file isn't an error,
+/// the endpoints will just be probed again next time.
+pub fn select_socket_url(
+    candidates: &[String],
+    state_file: &Path,
+    max_age: Duration,
+) -> Result<String, Error> {
+    let selected = match candidates {
+        [] => BASE_SOCKET_URL.to_string(),
+        [url] => url.clone(),
+        _ => match cached_selection(state_file, candidates, max_age) {
+            Some(url) => url,
+            None => {
+                let selected = candidates
+                    .iter()
+                    .filter_map(|url| match probe_latency(url, PROBE_TIMEOUT) {
+                        Ok(latency) => {
+                            log::debug!("HSM endpoint '{}' answered in {:?}.", url, latency);
+                            Some((latency, url))
+                        }
+                        Err(e) => {
+                            log::warn!("{}", e);
+                            None
+                        }
+                    })
+                    .min()
+                    .map(|(_, url)| url.clone())
+                    .ok_or_else(|| {
+                        Error::Hsm("None of the HSM endpoints is reachable.".to_string())
+                    })?;
+                let state = ProbeState {
+                    candidates: candidates.to_vec(),
+                    selected: selected.clone(),
+                    probed_at: now(),
+                };
+                if let Err(e) = serde_json::to_vec(&state)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| fs::write(state_file, json).map_err(|e| e.to_string()))
+                {
+                    log::warn!("Error remembering the selected HSM endpoint: {}", e);
+                }
+                selected
+            }
+        },
+    };
+    set_socket_url(selected.clone());
+    Ok(selected)
+}
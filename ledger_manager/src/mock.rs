@@ -0,0 +1,92 @@
+//! A transport answering from a script rather than a device, to exercise the library (or the crates
+//! built on it) without hardware. Only available with the `test-utils` feature.
+
+use crate::{Error, LedgerTransport};
+
+use ledger_apdu::{APDUAnswer, APDUCommand};
+use std::{collections::VecDeque, sync::Mutex};
+
+/// A transport replaying scripted answers to the APDUs it expects, in order, and recording the
+/// APDUs it was sent. An APDU other than the one expected next is answered with an error.
+///
+/// ```
+/// use ledger_manager::{quit_app, MockTransport};
+///
+/// let transport = MockTransport::new().expect("b0a7000000", "", 0x9000);
+/// quit_app(&transport).unwrap();
+/// assert!(transport.is_done());
+/// ```
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    // The serialized APDUs expected next, with the answer to each (its data then its status word).
+    script: Mutex<VecDeque<(Vec<u8>, Vec<u8>)>>,
+    sent: Mutex<Vec<Vec<u8>>>,
+}
+
+impl MockTransport {
+    /// A transport expecting no APDU.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expect this APDU (serialized as hex, `CLA INS P1 P2 Lc DATA`) after the previous ones, and
+    /// answer it with this data (as hex) and status word.
+    ///
+    /// # Panics
+    ///
+    /// If the APDU or the data isn't valid hex, as it's a mistake in the script.
+    #[allow(clippy::panic)]
+    pub fn expect(self, apdu: &str, data: &str, status: u16) -> Self {
+        let decode = |hex_str: &str| {
+            hex::decode(hex_str).unwrap_or_else(|e| panic!("Invalid hex '{}': {}", hex_str, e))
+        };
+        let mut answer = decode(data);
+        answer.extend_from_slice(&status.to_be_bytes());
+        if let Ok(mut script) = self.script.lock() {
+            script.push_back((decode(apdu), answer));
+        }
+        self
+    }
+
+    /// The APDUs sent so far, serialized, including unexpected ones.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.sent.lock().map(|s| s.clone()).unwrap_or_default()
+    }
+
+    /// Whether every expected APDU was sent.
+    pub fn is_done(&self) -> bool {
+        self.script.lock().map(|s| s.is_empty()).unwrap_or(false)
+    }
+}
+
+impl LedgerTransport for MockTransport {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        let apdu = command.serialize();
+        let mut script = self
+            .script
+            .lock()
+            .map_err(|_| Error::InvalidResponse("poisoned mock transport"))?;
+        let mut sent = self
+            .sent
+            .lock()
+            .map_err(|_| Error::InvalidResponse("poisoned mock transport"))?;
+        sent.push(apdu.clone());
+        match script.pop_front() {
+            Some((expected, answer)) if expected == apdu => APDUAnswer::from_answer(answer)
+                .map_err(|_| Error::InvalidResponse("answer too short")),
+            entry => {
+                // Keep expecting the same APDU.
+                if let Some(entry) = entry {
+                    script.push_front(entry);
+                }
+                Err(Error::InvalidResponse(
+                    "unexpected APDU sent to the mock transport",
+                ))
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        "mock transport".to_string()
+    }
+}
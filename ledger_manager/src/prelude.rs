@@ -0,0 +1,12 @@
+//! The types most users of this library need, to be imported at once with
+//! `use ledger_manager::prelude::*;`. This saves reaching through the transport crates.
+
+pub use crate::{
+    DeviceInfo, DeviceModel, Error, InstallErr, InstallOptions, InstallStrategy, LedgerApp,
+    LedgerManager, LedgerTransport, OpenAppErr, OperationStage, SharedTransport, StatusWord,
+    UninstallErr, UpdateErr,
+};
+pub use ledger_transport_hidapi::{
+    hidapi::{DeviceInfo as HidDeviceInfo, HidApi, HidError},
+    LedgerHIDError, TransportNativeHID,
+};
@@ -0,0 +1,267 @@
+//! The HTTP proxy to reach Ledger's API and HSM through, for networks which don't allow direct
+//! connections. Only HTTP proxies supporting the `CONNECT` method are supported, as the
+//! connections to Ledger's servers are encrypted end to end through the proxy.
+
+use crate::{mirror::host_and_port, package_dir, retry_policy, tls::tls_connector, Error};
+
+use base64::Engine;
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::RwLock,
+    time::Duration,
+};
+use tungstenite::{handshake::HandshakeError, stream::MaybeTlsStream, WebSocket};
+
+// The proxy set for this process, if not the one set in the environment.
+static PROXY: RwLock<Option<String>> = RwLock::new(None);
+
+// The port of a proxy which doesn't tell one, as for minreq.
+const PROXY_DEFAULT_PORT: u16 = 8080;
+// The answer of a proxy to a CONNECT request is a few short headers.
+const MAX_CONNECT_ANSWER_SIZE: usize = 8 * 1024;
+
+// A proxy, as parsed from its URL.
+struct Proxy {
+    host: String,
+    port: u16,
+    // The `user:password` to authenticate with, if any.
+    credentials: Option<String>,
+}
+
+impl Proxy {
+    // Parse a proxy URL, `[http://][USER[:PASSWORD]@]HOST[:PORT][/]`.
+    fn parse(url: &str) -> Result<Self, Error> {
+        let invalid = || Error::Proxy(format!("Invalid proxy URL '{}'.", url));
+        let rest = match url.split_once("://") {
+            Some(("http", rest)) => rest,
+            Some(_) => return Err(invalid()),
+            None => url,
+        };
+        let authority = rest.trim_end_matches('/');
+        let (credentials, address) = match authority.rsplit_once('@') {
+            Some((credentials, address)) => (Some(credentials.to_string()), address),
+            None => (None, authority),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (address, PROXY_DEFAULT_PORT),
+        };
+        if host.is_empty() || host.contains('/') {
+            return Err(invalid());
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            credentials,
+        })
+    }
+}
+
+/// Connect to Ledger's API and HSM through this HTTP proxy (`http://[USER:PASSWORD@]HOST[:PORT]`,
+/// port 8080 by default) for all the operations of this process from now on, rather than through
+/// the one set in the environment if any.
+pub fn set_proxy(url: impl Into<String>) -> Result<(), Error> {
+    let url = url.into();
+    Proxy::parse(&url)?;
+    if let Ok(mut current) = PROXY.write() {
+        *current = Some(url);
+    }
+    Ok(())
+}
+
+/// The URL of the proxy to connect to this URL through, if any: the one set with [`set_proxy`],
+/// else the one set in the `HTTPS_PROXY` environment variable for `https` and `wss` URLs or in
+/// `HTTP_PROXY` for `http` and `ws` ones (or their lowercase versions), else in `ALL_PROXY`.
+pub fn proxy_url(url: &str) -> Option<String> {
+    if let Some(proxy) = PROXY.read().ok().and_then(|proxy| proxy.clone()) {
+        return Some(proxy);
+    }
+    let secure = url.starts_with("https://") || url.starts_with("wss://");
+    let vars: &[&str] = if secure {
+        &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+    } else {
+        &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+    };
+    vars.iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|proxy| !proxy.is_empty())
+}
+
+/// The proxy for minreq to connect to this URL through, if any.
+pub(crate) fn minreq_proxy(url: &str) -> Option<minreq::Proxy> {
+    match minreq::Proxy::new(proxy_url(url)?) {
+        Ok(proxy) => Some(proxy),
+        Err(e) => {
+            // Only a proxy set in the environment can be invalid, set_proxy() checks the others.
+            log::warn!("Ignoring the invalid proxy set in the environment: {}", e);
+            None
+        }
+    }
+}
+
+// Open a tunnel to this host through the proxy, giving up after `timeout` if set.
+fn connect_tunnel(
+    proxy: &Proxy,
+    host: &str,
+    port: u16,
+    timeout: Option<Duration>,
+) -> Result<TcpStream, Error> {
+    let unreachable = |e: io::Error| {
+        Error::Proxy(format!(
+            "Can't reach the proxy at {}:{}: {}",
+            proxy.host, proxy.port, e
+        ))
+    };
+    let mut stream = match timeout {
+        Some(timeout) => {
+            let addr = (proxy.host.as_str(), proxy.port)
+                .to_socket_addrs()
+                .map_err(unreachable)?
+                .next()
+                .ok_or_else(|| {
+                    Error::Proxy(format!(
+                        "Can't resolve the host of the proxy '{}'.",
+                        proxy.host
+                    ))
+                })?;
+            TcpStream::connect_timeout(&addr, timeout).map_err(unreachable)?
+        }
+        None => TcpStream::connect((proxy.host.as_str(), proxy.port)).map_err(unreachable)?,
+    };
+    stream.set_read_timeout(timeout)?;
+    let mut request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n",
+        host = host,
+        port = port
+    );
+    if let Some(credentials) = &proxy.credentials {
+        request.push_str(&format!(
+            "Proxy-Authorization: Basic {}\r\n",
+            base64::engine::general_purpose::STANDARD.encode(credentials)
+        ));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    // Read the answer byte by byte, not to consume what the server sends through the tunnel.
+    let mut answer = Vec::new();
+    let mut byte = [0; 1];
+    while !answer.ends_with(b"\r\n\r\n") {
+        if answer.len() >= MAX_CONNECT_ANSWER_SIZE {
+            return Err(Error::Proxy("Invalid answer from the proxy.".to_string()));
+        }
+        stream.read_exact(&mut byte)?;
+        answer.push(byte[0]);
+    }
+    let answer = String::from_utf8_lossy(&answer);
+    let status_line = answer.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(Error::Proxy(format!(
+            "The proxy refused to connect to {}:{}: {}",
+            host, port, status_line
+        )));
+    }
+    stream.set_read_timeout(None)?;
+    Ok(stream)
+}
+
+/// Open a connection to this host for this URL, through its proxy if any, giving up after
+/// `timeout`. For instance to measure how long it takes to reach one of Ledger's servers.
+pub(crate) fn connect_timeout(
+    url: &str,
+    host: &str,
+    port: u16,
+    timeout: Duration,
+) -> Result<TcpStream, Error> {
+    if let Some(proxy) = proxy_url(url) {
+        return connect_tunnel(&Proxy::parse(&proxy)?, host, port, Some(timeout));
+    }
+    let unreachable = |e: io::Error| Error::Hsm(format!("Can't reach '{}': {}", url, e));
+    let addr = (host, port)
+        .to_socket_addrs()
+        .map_err(unreachable)?
+        .next()
+        .ok_or_else(|| Error::Hsm(format!("Can't resolve the host of '{}'.", url)))?;
+    TcpStream::connect_timeout(&addr, timeout).map_err(unreachable)
+}
+
+/// Open a websocket connection to Ledger's HSM at this URL, through the proxy if any, verifying its
+/// certificate as configured (see [`crate::set_ca_bundle`]). Connecting is retried as set with
+/// [`crate::set_retry_policy`], but not a session interrupted afterward.
+pub(crate) fn connect_websocket(url: &str) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, Error> {
+    retry_policy()
+        .run(|| connect_websocket_once(url), |_| false, |_| None)
+        .map_err(Error::classify)
+        .map_err(|e| match package_dir() {
+            Some(_) if e.is_retryable() => Error::Hsm(format!(
+                "{}. Installing apps and firmwares, and the genuine check, need a connection to \
+                 Ledger's HSM, even with a package directory.",
+                e
+            )),
+            _ => e,
+        })
+}
+
+fn connect_websocket_once(url: &str) -> Result<WebSocket<MaybeTlsStream<TcpStream>>, Error> {
+    let connector = tls_connector()?;
+    let proxy = proxy_url(url)
+        .map(|proxy| Proxy::parse(&proxy))
+        .transpose()?;
+    if proxy.is_none() && connector.is_none() {
+        return Ok(tungstenite::connect(url)?.0);
+    }
+    let (host, port) = host_and_port(url)
+        .ok_or_else(|| Error::Hsm(format!("Invalid HSM endpoint URL '{}'.", url)))?;
+    let stream = match proxy {
+        Some(proxy) => connect_tunnel(&proxy, host, port, None)?,
+        None => TcpStream::connect((host, port)).map_err(tungstenite::Error::Io)?,
+    };
+    match tungstenite::client_tls_with_config(url, stream, None, connector) {
+        Ok((socket, _)) => Ok(socket),
+        Err(HandshakeError::Failure(e)) => Err(e.into()),
+        // The stream is blocking.
+        Err(HandshakeError::Interrupted(_)) => {
+            Err(Error::Hsm("Interrupted websocket handshake.".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proxy_url() {
+        let proxy = Proxy::parse("http://user:p@ss@proxy.example.com:3128/").expect("Valid URL");
+        assert_eq!(proxy.host, "proxy.example.com");
+        assert_eq!(proxy.port, 3128);
+        assert_eq!(proxy.credentials.as_deref(), Some("user:p@ss"));
+    }
+
+    #[test]
+    fn defaults_proxy_port() {
+        let proxy = Proxy::parse("proxy.example.com").expect("Valid URL");
+        assert_eq!(proxy.host, "proxy.example.com");
+        assert_eq!(proxy.port, PROXY_DEFAULT_PORT);
+        assert_eq!(proxy.credentials, None);
+    }
+
+    #[test]
+    fn rejects_invalid_proxy_url() {
+        for url in [
+            "https://proxy.example.com:3128",
+            "socks5://proxy.example.com:1080",
+            "http://proxy.example.com:port",
+            "http://",
+        ] {
+            assert!(
+                matches!(Proxy::parse(url), Err(Error::Proxy(_))),
+                "{} accepted",
+                url
+            );
+        }
+    }
+}
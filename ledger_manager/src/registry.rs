@@ -0,0 +1,274 @@
+//! The knowledge about apps which isn't available from the Ledger catalog, such as which apps an
+//! app needs at runtime. It's loaded from a JSON file embedded in the library, which can be
+//! overridden or extended at runtime with another file (see [`set_registry`]). Supporting a new app
+//! is then a data change.
+
+use crate::Error;
+
+use serde_derive::{Deserialize, Serialize};
+
+use std::{fs, path::Path, sync::RwLock};
+
+// The registry of the apps known to this library.
+const EMBEDDED_REGISTRY: &str = include_str!("registry.json");
+
+// The registry set for this process, if not the embedded one.
+static REGISTRY: RwLock<Option<AppRegistry>> = RwLock::new(None);
+
+/// The registry of known apps used by the operations of this library. This is
+/// [`AppRegistry::embedded`] unless another one was set with [`set_registry`].
+pub fn registry() -> AppRegistry {
+    REGISTRY
+        .read()
+        .ok()
+        .and_then(|registry| registry.clone())
+        .or_else(|| AppRegistry::embedded().ok())
+        .unwrap_or_default()
+}
+
+/// Use this registry for all the operations of this process from now on, for instance one loaded
+/// with [`AppRegistry::load`].
+pub fn set_registry(registry: AppRegistry) {
+    if let Ok(mut current) = REGISTRY.write() {
+        *current = Some(registry);
+    }
+}
+
+/// What we know about an app, beyond what the catalog tells.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppEntry {
+    /// The name of the app as it appears in the catalog and on the device.
+    pub name: String,
+    /// Other names the app may be referred to by, such as its ticker. They are matched
+    /// case-insensitively, like the name.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// If this app is the testnet variant of another app, the name of that app.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub testnet_of: Option<String>,
+    /// The names of the apps this app calls into at runtime, and which must therefore be installed
+    /// along with it. Unlike libraries, those aren't listed as dependencies in the catalog. For
+    /// instance the Exchange app delegates the signing of the swap transactions to the app of each
+    /// currency being swapped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub plugins: Vec<String>,
+    /// If this app is a plugin loaded by another app, the name of that app. For instance the
+    /// clear-signing plugins (such as 1inch or Paraswap) are loaded by the Ethereum app. The base
+    /// app must be installed first for the plugin to be of any use.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plugin_of: Option<String>,
+}
+
+impl AppEntry {
+    /// Whether the app is known by this name (or alias), case-insensitively.
+    pub fn is_named(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether this is the testnet variant of another app.
+    pub fn is_testnet(&self) -> bool {
+        self.testnet_of.is_some()
+    }
+
+    /// Whether this is a plugin loaded by another app.
+    pub fn is_plugin(&self) -> bool {
+        self.plugin_of.is_some()
+    }
+}
+
+/// A set of known apps.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AppRegistry {
+    apps: Vec<AppEntry>,
+}
+
+impl AppRegistry {
+    /// Parse a registry from its JSON representation: a list of [`AppEntry`].
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        Ok(Self {
+            apps: serde_json::from_str(json)?,
+        })
+    }
+
+    /// The registry shipped with this library.
+    pub fn embedded() -> Result<Self, Error> {
+        Self::from_json(EMBEDDED_REGISTRY)
+    }
+
+    /// The registry shipped with this library, overridden by the registry in this file if any.
+    /// See [`AppRegistry::merge`].
+    pub fn load(overrides: Option<&Path>) -> Result<Self, Error> {
+        let mut registry = Self::embedded()?;
+        if let Some(path) = overrides {
+            let json = fs::read_to_string(path).map_err(|e| {
+                Error::Catalog(format!(
+                    "Error reading the app registry at '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            registry.merge(Self::from_json(&json)?);
+        }
+        Ok(registry)
+    }
+
+    /// Add the apps of this other registry. They replace the apps with the same name
+    /// (case-insensitive).
+    pub fn merge(&mut self, other: Self) {
+        for app in other.apps {
+            let name = app.name.to_lowercase();
+            match self.apps.iter_mut().find(|a| a.name.to_lowercase() == name) {
+                Some(existing) => *existing = app,
+                None => self.apps.push(app),
+            }
+        }
+    }
+
+    /// All the apps in this registry.
+    pub fn apps(&self) -> &[AppEntry] {
+        &self.apps
+    }
+
+    /// Get the app with this name or alias (case-insensitive). Names take precedence over aliases.
+    pub fn get(&self, name: &str) -> Option<&AppEntry> {
+        self.apps
+            .iter()
+            .find(|a| a.name.eq_ignore_ascii_case(name))
+            .or_else(|| self.apps.iter().find(|a| a.is_named(name)))
+    }
+
+    /// The name in the catalog of the app with this name or alias. Names unknown to the registry
+    /// are returned as is, they may still be in the catalog.
+    pub fn resolve(&self, name: &str) -> String {
+        self.get(name)
+            .map(|a| a.name.clone())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// The name of the app loading the plugin with this name, if it's a plugin.
+    pub fn base_app(&self, name: &str) -> Option<&str> {
+        self.get(name)?.plugin_of.as_deref()
+    }
+
+    /// The apps which call into the app with this name at runtime, that is which list it among their
+    /// plugins. For instance the Exchange app for the Bitcoin app.
+    pub fn callers(&self, name: &str) -> Vec<&AppEntry> {
+        let Some(name) = self.get(name).map(|a| a.name.as_str()) else {
+            return Vec::new();
+        };
+        self.apps
+            .iter()
+            .filter(|a| {
+                a.plugins
+                    .iter()
+                    .any(|p| self.resolve(p).eq_ignore_ascii_case(name))
+            })
+            .collect()
+    }
+
+    /// The names of the apps the app with this name needs at runtime (transitively, see
+    /// [`AppRegistry::with_plugins`]) which are not among these installed apps. For instance the
+    /// currency apps missing for the Exchange app to swap them.
+    pub fn missing_plugins(&self, name: &str, installed: &[String]) -> Vec<String> {
+        self.with_plugins(&[name.to_string()])
+            .into_iter()
+            .skip(1)
+            .filter(|p| {
+                !installed
+                    .iter()
+                    .any(|i| self.resolve(i).eq_ignore_ascii_case(p))
+            })
+            .collect()
+    }
+
+    /// The names of these apps along with, transitively, the plugins they need, without
+    /// duplicates. Apps unknown to the registry are kept as is.
+    pub fn with_plugins(&self, names: &[String]) -> Vec<String> {
+        let mut all: Vec<String> = Vec::new();
+        let mut queue = names.to_vec();
+        queue.reverse();
+        while let Some(name) = queue.pop() {
+            if all.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+                continue;
+            }
+            match self.get(&name) {
+                Some(entry) => {
+                    queue.extend(entry.plugins.iter().rev().cloned());
+                    all.push(entry.name.clone());
+                }
+                None => all.push(name),
+            }
+        }
+        all
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    fn registry(json: &str) -> AppRegistry {
+        AppRegistry::from_json(json).expect("Valid registry")
+    }
+
+    #[test]
+    fn looks_up_plugins() {
+        let registry = registry(
+            r#"[
+                { "name": "Exchange", "plugins": ["Bitcoin", "eth"] },
+                { "name": "Ethereum", "aliases": ["ETH"], "plugins": ["Paraswap"] },
+                { "name": "Paraswap", "plugin_of": "Ethereum" }
+            ]"#,
+        );
+        assert_eq!(
+            registry.with_plugins(&["exchange".to_string(), "Other".to_string()]),
+            ["Exchange", "Bitcoin", "Ethereum", "Paraswap", "Other"]
+        );
+        let callers: Vec<_> = registry
+            .callers("ethereum")
+            .into_iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(callers, ["Exchange"]);
+        assert_eq!(registry.base_app("paraswap"), Some("Ethereum"));
+        assert_eq!(registry.base_app("Ethereum"), None);
+        assert_eq!(
+            registry.missing_plugins("Exchange", &["eth".to_string()]),
+            ["Bitcoin", "Paraswap"]
+        );
+    }
+
+    #[test]
+    fn overrides_embedded_registry() {
+        let mut merged = AppRegistry::embedded().expect("Valid embedded registry");
+        let count = merged.apps().len();
+        merged.merge(registry(
+            r#"[
+                { "name": "exchange", "plugins": ["Polygon"] },
+                { "name": "Lido", "plugin_of": "Ethereum" }
+            ]"#,
+        ));
+        assert_eq!(merged.apps().len(), count + 1);
+        assert_eq!(
+            merged.with_plugins(&["Exchange".to_string()]),
+            ["exchange", "Polygon"]
+        );
+        assert_eq!(merged.base_app("Lido"), Some("Ethereum"));
+    }
+
+    #[test]
+    fn resolves_aliases() {
+        let registry = registry(
+            r#"[
+                { "name": "Cosmos", "aliases": ["atom"] },
+                { "name": "ATOM" }
+            ]"#,
+        );
+        assert_eq!(registry.resolve("Atom"), "ATOM");
+        assert_eq!(registry.resolve("COSMOS"), "Cosmos");
+        assert_eq!(registry.resolve("Polygon"), "Polygon");
+        assert!(registry.get("cosmos").is_some_and(|a| a.is_named("ATOM")));
+    }
+}
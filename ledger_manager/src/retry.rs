@@ -0,0 +1,201 @@
+//! Retrying the requests to Ledger's API, and the connections to its HSM, when they fail because of
+//! a network blip or an overloaded server. The attempts are spread out with an exponential backoff
+//! with jitter, so many clients failing at once don't all come back at the same time. A server
+//! telling how long to wait (with `Retry-After`) is obeyed by all the threads of the process, and
+//! the requests can be spread out to stay under the rate limit when provisioning many devices.
+
+use crate::Error;
+
+use rand::Rng;
+use std::{
+    sync::{Mutex, PoisonError, RwLock},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How to retry the requests to Ledger's API and the connections to its HSM which fail for a reason
+/// which may be temporary. See [`Error::is_retryable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// How many more times to try. 0 to never retry.
+    pub retries: u32,
+    /// How long to wait before the first retry. The wait doubles after every failed retry.
+    pub initial_backoff: Duration,
+    /// The longest to wait before a retry. A server asking to wait longer isn't retried.
+    pub max_backoff: Duration,
+    /// The least time between two requests to the API or connections to the HSM, across all the
+    /// threads of the process, to spread them out when updating many apps or devices. None by
+    /// default.
+    pub min_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    /// Try 4 more times, waiting about 1, 2, 4 then 8 seconds (between half and all of it, at
+    /// random).
+    fn default() -> Self {
+        Self {
+            retries: 4,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            min_interval: Duration::ZERO,
+        }
+    }
+}
+
+impl RetryPolicy {
+    // How long to wait before this retry (the first one being 0): between half and all of the
+    // backoff, at random.
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_backoff);
+        backoff / 2 + rand::thread_rng().gen_range(Duration::ZERO..=backoff / 2)
+    }
+
+    // Wait until this thread may send a request: not before the time set by the last server which
+    // asked to wait, and `min_interval` after the previous request of any thread.
+    fn wait_turn(&self) {
+        let start = {
+            let mut next = NEXT_REQUEST.lock().unwrap_or_else(PoisonError::into_inner);
+            let start = next.map_or_else(Instant::now, |next| next.max(Instant::now()));
+            *next = Some(start + self.min_interval);
+            start
+        };
+        thread::sleep(start.saturating_duration_since(Instant::now()));
+    }
+
+    /// Run this operation, again after a pause as long as it fails with a retryable error (or with
+    /// an answer `is_retryable_answer` tells is worth retrying), as many times as set. When the
+    /// server tells how long to wait (`retry_after` for an answer), no thread sends a request
+    /// before then.
+    pub(crate) fn run<T>(
+        &self,
+        mut op: impl FnMut() -> Result<T, Error>,
+        is_retryable_answer: impl Fn(&T) -> bool,
+        retry_after: impl Fn(&T) -> Option<Duration>,
+    ) -> Result<T, Error> {
+        let mut retry = 0;
+        loop {
+            self.wait_turn();
+            let res = op();
+            let (retryable, requested) = match &res {
+                Ok(answer) => (is_retryable_answer(answer), retry_after(answer)),
+                Err(e) => (e.is_retryable(), e.retry_after()),
+            };
+            if !retryable || retry >= self.retries {
+                return res;
+            }
+            let delay = match requested {
+                Some(requested) if requested > self.max_backoff => {
+                    log::warn!(
+                        "Ledger's server asks to wait {:?} before trying again, giving up.",
+                        requested
+                    );
+                    return res;
+                }
+                Some(requested) => {
+                    hold_off(requested);
+                    requested
+                }
+                None => self.delay(retry),
+            };
+            match &res {
+                Ok(_) => log::warn!("Ledger's API is unavailable, retrying in {:?}.", delay),
+                Err(e) => log::warn!("{}. Retrying in {:?}.", e, delay),
+            }
+            thread::sleep(delay);
+            retry += 1;
+        }
+    }
+}
+
+// When the next request may be sent, by any thread.
+static NEXT_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+// Send no request before this delay is elapsed, from any thread.
+fn hold_off(delay: Duration) {
+    let mut next = NEXT_REQUEST.lock().unwrap_or_else(PoisonError::into_inner);
+    let until = Instant::now() + delay;
+    *next = Some(next.map_or(until, |next| next.max(until)));
+}
+
+// The policy set for this process, if not the default one.
+static RETRY_POLICY: RwLock<Option<RetryPolicy>> = RwLock::new(None);
+
+/// The policy used to retry the requests to Ledger's API and the connections to its HSM. This is
+/// the default one unless another one was set with [`set_retry_policy`].
+pub fn retry_policy() -> RetryPolicy {
+    RETRY_POLICY
+        .read()
+        .ok()
+        .and_then(|policy| *policy)
+        .unwrap_or_default()
+}
+
+/// Use this policy to retry the requests to Ledger's API and the connections to its HSM for all
+/// the operations of this process from now on.
+pub fn set_retry_policy(policy: RetryPolicy) {
+    if let Ok(mut current) = RETRY_POLICY.write() {
+        *current = Some(policy);
+    }
+}
+
+/// How long a server asks to wait before trying again, from its `Retry-After` header: a number of
+/// seconds or an HTTP date.
+pub(crate) fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => Some(
+            httpdate::parse_http_date(value)
+                .ok()?
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        ),
+    }
+}
+
+// Whether the server failed to answer this time but may next time: it's overloaded, down for a
+// moment or timed out.
+pub(crate) fn is_retryable_status(status: i32) -> bool {
+    matches!(status, 408 | 429 | 500 | 502 | 503 | 504)
+}
+
+#[cfg(test)]
+#[allow(clippy::expect_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_retry_after_seconds() {
+        assert_eq!(parse_retry_after(" 120 "), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("soon"), None);
+    }
+
+    #[test]
+    fn parses_retry_after_date() {
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        let delay = parse_retry_after(&date).expect("Valid date");
+        // The date is rounded down to the second.
+        assert!(delay > Duration::from_secs(58) && delay <= Duration::from_secs(60));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn caps_backoff() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            ..RetryPolicy::default()
+        };
+        assert!(policy.delay(0) <= Duration::from_secs(1));
+        for retry in [3, 10, 40] {
+            let delay = policy.delay(retry);
+            assert!(delay >= Duration::from_millis(2500) && delay <= Duration::from_secs(5));
+        }
+    }
+}
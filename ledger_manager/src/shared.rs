@@ -0,0 +1,58 @@
+//! A transport shared between threads, for instance between the UI thread and the background
+//! threads of a wallet.
+
+use crate::{Error, LedgerTransport};
+
+use ledger_apdu::{APDUAnswer, APDUCommand};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// A transport which can be cloned and used from several threads at once. The exchanges are
+/// serialized: an APDU is only sent once the answer to the previous one was received.
+///
+/// Operations made of several APDUs (such as installing an app) must not be interleaved with other
+/// exchanges. Run them with [`SharedTransport::with`], which holds the transport until they're
+/// done.
+///
+/// A thread panicking in the middle of an exchange doesn't make the transport unusable for the
+/// other ones.
+#[derive(Clone)]
+pub struct SharedTransport {
+    inner: Arc<Mutex<Box<dyn LedgerTransport + Send>>>,
+}
+
+impl SharedTransport {
+    /// Share this transport.
+    pub fn new(transport: impl LedgerTransport + Send + 'static) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Box::new(transport))),
+        }
+    }
+
+    // A panic while holding the lock doesn't leave the transport in an inconsistent state, at
+    // worst the channel is desynchronized as after any interrupted exchange.
+    fn lock(&self) -> MutexGuard<'_, Box<dyn LedgerTransport + Send>> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Run this operation with the transport, while no other thread can exchange with the device.
+    pub fn with<T>(&self, op: impl FnOnce(&dyn LedgerTransport) -> T) -> T {
+        let transport = self.lock();
+        op(&**transport)
+    }
+
+    /// Replace the transport, for instance after reconnecting to the device. The threads sharing
+    /// it use the new one from their next exchange.
+    pub fn replace(&self, transport: impl LedgerTransport + Send + 'static) {
+        *self.lock() = Box::new(transport);
+    }
+}
+
+impl LedgerTransport for SharedTransport {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        self.lock().exchange_apdu(command)
+    }
+
+    fn describe(&self) -> String {
+        self.lock().describe()
+    }
+}
@@ -0,0 +1,59 @@
+//! A transport to Speculos, the emulator of Ledger devices, over its APDU TCP port. This allows
+//! developing and testing the flows which don't involve Ledger's HSM (opening an app, querying the
+//! device) without a physical device.
+// The protocol is that of https://github.com/LedgerHQ/speculos/blob/master/speculos/mcu/apdu.py
+
+use crate::{Error, LedgerTransport};
+
+use ledger_apdu::{APDUAnswer, APDUCommand};
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::Mutex,
+};
+
+/// The port Speculos listens on for APDUs by default.
+pub const SPECULOS_DEFAULT_PORT: u16 = 9999;
+
+/// A connection to a Speculos emulator.
+pub struct TransportSpeculos {
+    stream: Mutex<TcpStream>,
+}
+
+impl TransportSpeculos {
+    /// Connect to the APDU port of a running Speculos emulator.
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self {
+            stream: Mutex::new(stream),
+        })
+    }
+}
+
+impl LedgerTransport for TransportSpeculos {
+    fn exchange_apdu(&self, command: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+        let mut stream = self
+            .stream
+            .lock()
+            .map_err(|_| Error::InvalidResponse("poisoned emulator connection"))?;
+
+        // Both ways, the APDU is prefixed with its length as a big-endian u32. The length of the
+        // answer doesn't count the status word which follows its data.
+        let apdu = command.serialize();
+        stream.write_all(&(apdu.len() as u32).to_be_bytes())?;
+        stream.write_all(&apdu)?;
+
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let mut answer = vec![0; u32::from_be_bytes(len) as usize + 2];
+        stream.read_exact(&mut answer)?;
+        APDUAnswer::from_answer(answer).map_err(|_| Error::InvalidResponse("answer too short"))
+    }
+
+    fn describe(&self) -> String {
+        match self.stream.lock().map(|s| s.peer_addr()) {
+            Ok(Ok(addr)) => format!("Speculos emulator at {}", addr),
+            _ => "Speculos emulator".to_string(),
+        }
+    }
+}
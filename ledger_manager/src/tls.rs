@@ -0,0 +1,201 @@
+//! The verification of the certificate of Ledger's HSM, where apps and firmwares are installed. By
+//! default it must be issued by one of the authorities trusted by the system. They can be replaced
+//! by a custom bundle, for instance to allow the TLS interception of a corporate proxy, and the
+//! certificate can be pinned to protect against an authority issuing a certificate to somebody
+//! else.
+//!
+//! Only the connections to the HSM are verified this way. The queries to the catalog of Ledger's
+//! API go through an HTTP client which always trusts the Mozilla set of authorities, and whose
+//! certificate isn't pinned.
+
+use crate::Error;
+
+use rustls::{
+    client::{
+        danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        WebPkiServerVerifier,
+    },
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+use tungstenite::Connector;
+
+// The verification of the certificates set for this process.
+struct TlsSettings {
+    // The authorities to trust instead of those of the system, if set.
+    roots: Option<Vec<CertificateDer<'static>>>,
+    // The SHA-256 fingerprints of the certificates to pin.
+    pins: Vec<[u8; 32]>,
+}
+
+static TLS: RwLock<TlsSettings> = RwLock::new(TlsSettings {
+    roots: None,
+    pins: Vec::new(),
+});
+
+/// Trust the certificate authorities in this PEM bundle instead of those of the system, for all the
+/// connections to Ledger's HSM of this process from now on. The queries to Ledger's API still
+/// trust the Mozilla set of authorities.
+pub fn set_ca_bundle(path: impl AsRef<Path>) -> Result<(), Error> {
+    let path = path.as_ref();
+    let invalid = |e: String| Error::Tls(format!("Invalid CA bundle '{}': {}", path.display(), e));
+    let file = File::open(path).map_err(|e| invalid(e.to_string()))?;
+    let roots = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(e.to_string()))?;
+    if roots.is_empty() {
+        return Err(invalid("no certificate found".to_string()));
+    }
+    if let Ok(mut tls) = TLS.write() {
+        tls.roots = Some(roots);
+    }
+    Ok(())
+}
+
+/// Only connect to Ledger's HSM if one of the certificates it presents (its own or one of the
+/// intermediate ones) has one of these SHA-256 fingerprints, as hex (optionally separated by
+/// colons, as printed by `openssl x509 -fingerprint -sha256`), for all the connections of this
+/// process from now on. The certificate must still be issued by a trusted authority. Pinning an
+/// intermediate certificate rather than that of the HSM itself survives its renewal. The queries
+/// to Ledger's API aren't pinned.
+pub fn set_certificate_pins<I, S>(pins: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let pins = pins
+        .into_iter()
+        .map(|pin| {
+            let pin = pin.as_ref();
+            hex::decode(pin.replace(':', ""))
+                .ok()
+                .and_then(|fingerprint| fingerprint.try_into().ok())
+                .ok_or_else(|| Error::Tls(format!("Invalid SHA-256 fingerprint '{}'.", pin)))
+        })
+        .collect::<Result<_, _>>()?;
+    if let Ok(mut tls) = TLS.write() {
+        tls.pins = pins;
+    }
+    Ok(())
+}
+
+// Checks the certificate chain as usual, then that it contains a pinned certificate.
+#[derive(Debug)]
+struct PinnedVerifier {
+    inner: Arc<WebPkiServerVerifier>,
+    pins: Vec<[u8; 32]>,
+}
+
+impl PinnedVerifier {
+    fn is_pinned(&self, cert: &CertificateDer<'_>) -> bool {
+        let fingerprint = ring::digest::digest(&ring::digest::SHA256, cert);
+        self.pins
+            .iter()
+            .any(|pin| pin.as_slice() == fingerprint.as_ref())
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+        if std::iter::once(end_entity)
+            .chain(intermediates)
+            .any(|cert| self.is_pinned(cert))
+        {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(
+                "none of the certificates of Ledger's HSM is pinned".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The TLS connector for the connections to Ledger's HSM, if the verification of its certificate
+/// was customized with [`set_ca_bundle`] or [`set_certificate_pins`].
+pub(crate) fn tls_connector() -> Result<Option<Connector>, Error> {
+    let tls = TLS
+        .read()
+        .map_err(|_| Error::Tls("poisoned TLS settings".to_string()))?;
+    if tls.roots.is_none() && tls.pins.is_empty() {
+        return Ok(None);
+    }
+
+    let mut roots = RootCertStore::empty();
+    match &tls.roots {
+        Some(bundle) => {
+            roots.add_parsable_certificates(bundle.iter().cloned());
+        }
+        None => {
+            let native = rustls_native_certs::load_native_certs().map_err(|e| {
+                Error::Tls(format!(
+                    "Error loading the certificates of the system: {}",
+                    e
+                ))
+            })?;
+            roots.add_parsable_certificates(native);
+        }
+    }
+    if roots.is_empty() {
+        return Err(Error::Tls("No trusted certificate authority.".to_string()));
+    }
+
+    let config = if tls.pins.is_empty() {
+        ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth()
+    } else {
+        let inner = WebPkiServerVerifier::builder(Arc::new(roots))
+            .build()
+            .map_err(|e| Error::Tls(e.to_string()))?;
+        ClientConfig::builder()
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier {
+                inner,
+                pins: tls.pins.clone(),
+            }))
+            .with_no_client_auth()
+    };
+    Ok(Some(Connector::Rustls(Arc::new(config))))
+}
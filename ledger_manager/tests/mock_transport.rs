@@ -0,0 +1,189 @@
+//! Check the exchanges with the device of the operations which don't need the Ledger API or HSM,
+//! against the scripted answers of a `MockTransport`.
+
+use ledger_manager::{
+    install_app,
+    ledger_apdu::{APDUAnswer, APDUCommand},
+    ledger_transport_hidapi::LedgerHIDError,
+    list_installed_apps_raw, open_app_by_name, quit_app, DeviceInfo, Error, InstallErr, LedgerApp,
+    LedgerManager, LedgerTransport, MockTransport, OpenAppErr, OperationStage, SharedTransport,
+    StatusWord,
+};
+use std::thread;
+
+const GET_VERSION: &str = "e001000000";
+// The answer of a Nano S running firmware 2.1.0.
+const NANO_S_VERSION: &str = "3110000405322e312e3004a600000005312e313200";
+// The same, from a device which wasn't set up yet.
+const NANO_S_VERSION_NOT_ONBOARDED: &str = "3110000405322e312e30040000000005312e313200";
+
+#[test]
+fn reads_device_info() {
+    let transport = MockTransport::new().expect(GET_VERSION, NANO_S_VERSION, 0x9000);
+    let info = DeviceInfo::new(&transport).expect("Valid answer");
+    assert_eq!(info.version, "2.1.0");
+    assert!(info.is_nano_s());
+    assert!(transport.is_done());
+    assert_eq!(
+        transport.sent(),
+        vec![hex::decode(GET_VERSION).expect("Valid hex")]
+    );
+}
+
+#[test]
+fn reports_locked_device() {
+    let transport = MockTransport::new().expect(GET_VERSION, "", 0x5515);
+    assert!(matches!(
+        DeviceInfo::new(&transport),
+        Err(Error::LockedDevice)
+    ));
+}
+
+#[test]
+fn reports_status_word() {
+    let transport = MockTransport::new().expect(GET_VERSION, "", 0x6a84);
+    let err = match DeviceInfo::new(&transport) {
+        Err(e) => e,
+        Ok(info) => panic!("Unexpected success: {}", info.version),
+    };
+    assert_eq!(err.status_word(), Some(StatusWord::NotEnoughMemory));
+    assert_eq!(
+        err.to_string(),
+        "Device returned error status 0x6a84 (not enough memory)."
+    );
+    assert_eq!(StatusWord::from(0x6a84).code(), 0x6a84);
+    assert_eq!(StatusWord::from(0x1234), StatusWord::Other(0x1234));
+    assert!(StatusWord::from(0x9000).is_ok());
+}
+
+#[test]
+fn reports_user_refusal() {
+    // The user refuses to allow Ledger manager.
+    let transport = MockTransport::new().expect("e0de000000", "", 0x5501);
+    assert!(matches!(
+        list_installed_apps_raw(&transport),
+        Err(Error::UserRefused)
+    ));
+}
+
+#[test]
+fn reports_open_app() {
+    // An app rejects the dashboard command, then tells its name and version.
+    let transport = MockTransport::new().expect(GET_VERSION, "", 0x6e00).expect(
+        "b001000000",
+        "0107426974636f696e05322e312e30",
+        0x9000,
+    );
+    match DeviceInfo::new(&transport) {
+        Err(Error::AppOpen(name)) => assert_eq!(name, "Bitcoin"),
+        res => panic!("Unexpected result: {:?}", res.map(|i| i.version)),
+    }
+    assert!(transport.is_done());
+}
+
+#[test]
+fn names_app_open_during_manager_operations() {
+    let transport = MockTransport::new()
+        .expect("e0de000000", "", 0x6e00)
+        .expect("b001000000", "0107426974636f696e05322e312e30", 0x9000)
+        .expect("e0d8000008457468657265756d", "", 0x6d00)
+        .expect("b001000000", "0107426974636f696e05322e312e30", 0x9000);
+    match list_installed_apps_raw(&transport) {
+        Err(e) => assert_eq!(e.app_open(), Some("Bitcoin")),
+        Ok(apps) => panic!("Unexpected success: {} apps", apps.len()),
+    }
+    match open_app_by_name(&transport, "Ethereum") {
+        Err(OpenAppErr::OtherAppOpen(name)) => assert_eq!(name, "Bitcoin"),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    assert!(transport.is_done());
+}
+
+#[test]
+fn reports_disconnected_device() {
+    // A device unplugged before it answers.
+    struct Unplugged;
+    impl LedgerTransport for Unplugged {
+        fn exchange_apdu(&self, _: &APDUCommand<&[u8]>) -> Result<APDUAnswer<Vec<u8>>, Error> {
+            Err(Error::Transport(LedgerHIDError::DeviceNotFound))
+        }
+
+        fn describe(&self) -> String {
+            "unplugged".to_string()
+        }
+    }
+    // Outside of a session with Ledger's HSM, the error of the transport is left as it is.
+    match DeviceInfo::new(&Unplugged) {
+        Err(e @ Error::Transport(LedgerHIDError::DeviceNotFound)) => assert!(e.is_transient()),
+        res => panic!("Unexpected result: {:?}", res.map(|i| i.version)),
+    }
+    assert!(Error::DeviceDisconnected(OperationStage::Transferring).is_transient());
+}
+
+#[test]
+fn manages_device_over_any_transport() {
+    let transport = MockTransport::new().expect(GET_VERSION, NANO_S_VERSION, 0x9000);
+    let mut manager = LedgerManager::new(transport);
+    // The device info is only queried once.
+    for _ in 0..2 {
+        assert_eq!(
+            manager.device_info().expect("Valid answer").version,
+            "2.1.0"
+        );
+    }
+    assert!(manager.transport().is_done());
+}
+
+#[test]
+fn refuses_install_before_setup() {
+    let transport = MockTransport::new()
+        .expect(GET_VERSION, NANO_S_VERSION_NOT_ONBOARDED, 0x9000)
+        .expect("e0de000000", "", 0x9000);
+    assert!(matches!(
+        install_app(&transport, LedgerApp::Bitcoin),
+        Err(InstallErr::NotOnboarded)
+    ));
+    // Nothing is installed.
+    assert!(transport.is_done());
+    assert_eq!(transport.sent().len(), 2);
+}
+
+#[test]
+fn opens_app() {
+    let transport = MockTransport::new()
+        .expect("e0d8000007426974636f696e", "", 0x9000)
+        .expect("e0d800000746616b65417070", "", 0x6807);
+    assert!(open_app_by_name(&transport, "Bitcoin").is_ok());
+    assert!(matches!(
+        open_app_by_name(&transport, "FakeApp"),
+        Err(OpenAppErr::NotInstalled)
+    ));
+    assert!(transport.is_done());
+}
+
+#[test]
+fn rejects_unexpected_apdu() {
+    let transport = MockTransport::new().expect(GET_VERSION, NANO_S_VERSION, 0x9000);
+    assert!(quit_app(&transport).is_err());
+    // The expected APDU is still answered afterward.
+    assert!(!transport.is_done());
+    assert!(DeviceInfo::new(&transport).is_ok());
+    assert_eq!(transport.sent().len(), 2);
+}
+
+#[test]
+fn shares_transport_between_threads() {
+    let transport = SharedTransport::new(
+        MockTransport::new()
+            .expect(GET_VERSION, NANO_S_VERSION, 0x9000)
+            .expect(GET_VERSION, NANO_S_VERSION, 0x9000),
+    );
+    thread::scope(|s| {
+        for _ in 0..2 {
+            let transport = transport.clone();
+            s.spawn(move || assert!(DeviceInfo::new(&transport).is_ok()));
+        }
+    });
+    // Both threads were answered, and nothing more is expected.
+    assert!(quit_app(&transport).is_err());
+}
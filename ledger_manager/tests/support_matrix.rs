@@ -0,0 +1,117 @@
+//! Check the parsing of the responses to the get version command, and what we derive from it, for
+//! the device models and firmware generations listed in `data/get_version_responses.json`. This
+//! guards against regressions on devices the maintainers don't own.
+
+use ledger_manager::DeviceInfo;
+use serde_derive::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct Case {
+    name: String,
+    /// The data of the response, as hex.
+    response: String,
+    target_id: u32,
+    version: String,
+    is_bootloader: bool,
+    se_version: Option<String>,
+    mcu_version: Option<String>,
+    #[serde(default)]
+    mcu_bootloader_version: Option<String>,
+    #[serde(default)]
+    hardware_version: Option<u8>,
+    /// The `DeviceModel` variant, if the model is known.
+    model: Option<String>,
+    /// The `UiGeneration` variant, if the model is known.
+    ui_generation: Option<String>,
+    block_size: u64,
+}
+
+fn cases() -> Vec<Case> {
+    let data = include_str!("data/get_version_responses.json");
+    serde_json::from_str(data).expect("Valid test data")
+}
+
+fn parse(case: &Case) -> DeviceInfo {
+    let response = hex::decode(&case.response).expect("Valid hex");
+    DeviceInfo::from_version_response(&response)
+        .unwrap_or_else(|e| panic!("{}: parsing failed: {}", case.name, e))
+}
+
+#[test]
+fn parses_version_responses() {
+    for case in cases() {
+        let info = parse(&case);
+        assert_eq!(info.target_id, case.target_id, "{}", case.name);
+        assert_eq!(info.version, case.version, "{}", case.name);
+        assert_eq!(info.is_bootloader, case.is_bootloader, "{}", case.name);
+        assert_eq!(info.se_version, case.se_version, "{}", case.name);
+        assert_eq!(info.mcu_version, case.mcu_version, "{}", case.name);
+        assert_eq!(
+            info.mcu_bootloader_version, case.mcu_bootloader_version,
+            "{}",
+            case.name
+        );
+        assert_eq!(
+            info.hardware_version, case.hardware_version,
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn detects_capabilities() {
+    for case in cases() {
+        let info = parse(&case);
+        let model = info.model();
+        assert_eq!(
+            model.map(|m| format!("{:?}", m)),
+            case.model,
+            "{}",
+            case.name
+        );
+        assert_eq!(
+            model.map(|m| format!("{:?}", m.ui_generation())),
+            case.ui_generation,
+            "{}",
+            case.name
+        );
+        assert_eq!(info.block_size(), case.block_size, "{}", case.name);
+        assert_eq!(
+            info.memory_size(),
+            model.map(|m| m.flash_size()),
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn selects_prompt_flow() {
+    for case in cases() {
+        let info = parse(&case);
+        // Users of the Nano S are prompted to only install app binaries, to save space.
+        assert_eq!(
+            info.is_nano_s(),
+            case.model.as_deref() == Some("NanoS"),
+            "{}",
+            case.name
+        );
+    }
+}
+
+#[test]
+fn rejects_truncated_responses() {
+    for case in cases() {
+        let response = hex::decode(&case.response).expect("Valid hex");
+        for len in 0..response.len() {
+            // Some prefixes are valid responses on their own, just make sure it never panics.
+            let _ = DeviceInfo::from_version_response(&response[..len]);
+        }
+        assert!(
+            DeviceInfo::from_version_response(&response[..4]).is_err(),
+            "{}",
+            case.name
+        );
+    }
+}